@@ -12,6 +12,8 @@ fn make_entity_field() -> dataflow::EntityField {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             },
             dataflow::EntityInfo {
                 display_name: "entity_1".into(),
@@ -19,6 +21,8 @@ fn make_entity_field() -> dataflow::EntityField {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             },
         ],
     })
@@ -115,8 +119,7 @@ pub fn benchmark_entity(c: &mut Criterion) {
 
             let instance = std::time::Instant::now();
             for id in ids {
-                let result = field.modify_variant(id, std::hint::black_box(1)).unwrap();
-                std::hint::black_box(result);
+                field.modify_variant(id, std::hint::black_box(1)).unwrap();
             }
             instance.elapsed()
         });
@@ -148,6 +151,7 @@ pub fn benchmark_entity(c: &mut Criterion) {
         });
     });
 
+    #[allow(clippy::type_complexity)]
     let row: &[(&str, Box<dyn Fn(&dataflow::EntityField, usize)>)] = &[
         ("entity find collision point", Box::new(|field, i| {
             let query = std::hint::black_box(Vec2::new(i as f32, 0.0));
@@ -19,7 +19,7 @@ pub fn benchmark_hgrid(c: &mut Criterion) {
             for i in 0..iters {
                 let rect = std::hint::black_box(IVec2::new(i as i32, 0) + IRect2::new(IVec2::ZERO, IVec2::ONE));
                 let value = std::hint::black_box(u16::default());
-                std::hint::black_box(hgrid.insert(rect, i, value));
+                hgrid.insert(rect, i, value);
             }
             instance.elapsed()
         });
@@ -38,7 +38,7 @@ pub fn benchmark_hgrid(c: &mut Criterion) {
             let instance = std::time::Instant::now();
             for i in 0..iters {
                 let rect = std::hint::black_box(IVec2::new(i as i32, 0) + IRect2::new(IVec2::ZERO, IVec2::ONE));
-                std::hint::black_box(hgrid.remove(rect, i));
+                hgrid.remove(rect, i);
             }
             instance.elapsed()
         });
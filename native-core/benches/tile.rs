@@ -10,11 +10,19 @@ fn make_tile_field() -> dataflow::TileField {
                 display_name: "tile_0".into(),
                 description: "tile_0_desc".into(),
                 collision: true,
+                layer_id: 0,
             },
             dataflow::TileInfo {
                 display_name: "tile_1".into(),
                 description: "tile_1_desc".into(),
                 collision: true,
+                layer_id: 0,
+            },
+        ],
+        layers: vec![
+            dataflow::TileLayerInfo {
+                display_name: "layer_0".into(),
+                description: "layer_0_desc".into(),
             },
         ],
     })
@@ -120,8 +128,7 @@ pub fn benchmark_tile(c: &mut Criterion) {
 
             let instance = std::time::Instant::now();
             for id in ids {
-                let result = field.modify_variant(id, std::hint::black_box(1)).unwrap();
-                std::hint::black_box(result);
+                field.modify_variant(id, std::hint::black_box(1)).unwrap();
             }
             instance.elapsed()
         });
@@ -146,13 +153,13 @@ pub fn benchmark_tile(c: &mut Criterion) {
             let instance = std::time::Instant::now();
             for i in 0..iters {
                 let new_coord = std::hint::black_box(IVec2::new(i as i32, (i % 16) as i32));
-                let result = field.r#move(ids[i as usize], new_coord).unwrap();
-                std::hint::black_box(result);
+                field.r#move(ids[i as usize], new_coord).unwrap();
             }
             instance.elapsed()
         });
     });
 
+    #[allow(clippy::type_complexity)]
     let row: &[(&str, Box<dyn Fn(&dataflow::TileField, usize)>)] = &[
         ("tile find point", Box::new(|field, i| {
             let query = std::hint::black_box(IVec2::new(i as i32, 0));
@@ -186,7 +193,7 @@ pub fn benchmark_tile(c: &mut Criterion) {
                         let id = field
                             .insert(dataflow::Tile {
                                 archetype_id: 0,
-                                coord: IVec2::new(x as i32, y as i32),
+                                coord: IVec2::new(x as i32, y),
                                 ..Default::default()
                             })
                             .unwrap();
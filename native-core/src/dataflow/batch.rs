@@ -0,0 +1,265 @@
+use super::*;
+
+// event handler calls held back until the outermost batch commits
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Dispatch {
+    InsertTile(u16, TileId),
+    RemoveTile(u16, TileId),
+    InsertBlock(u16, BlockId),
+    RemoveBlock(u16, BlockId),
+    InsertEntity(u16, EntityId),
+    RemoveEntity(u16, EntityId),
+}
+
+impl Dispatch {
+    // the field and id of the object and whether it was inserted
+    fn target(self) -> ((u8, u64), bool) {
+        match self {
+            Dispatch::InsertTile(_, id) => ((0, id), true),
+            Dispatch::RemoveTile(_, id) => ((0, id), false),
+            Dispatch::InsertBlock(_, id) => ((1, id), true),
+            Dispatch::RemoveBlock(_, id) => ((1, id), false),
+            Dispatch::InsertEntity(_, id) => ((2, id), true),
+            Dispatch::RemoveEntity(_, id) => ((2, id), false),
+        }
+    }
+}
+
+impl Dataflow {
+    // batch
+
+    // run a batch of tile, block and entity mutations as a whole,
+    // an error reverts every change made by the batch and discards its events and handler calls,
+    // handlers are only called once the outermost batch succeeded,
+    // changes made without the journal are kept, and if they block the revert its error is returned instead
+    pub fn transaction<T>(&mut self, name: &str, f: impl FnOnce(&mut Dataflow) -> Result<T, DataflowError>) -> Result<T, DataflowError> {
+        // changes are recorded through the journal, a temporary one is used if none is enabled
+        let temporary = self.journal.is_none();
+        if temporary {
+            self.journal = Some(Default::default());
+        }
+        let suspended = self.set_journal_suspended(false);
+
+        self.begin_transaction(name);
+        let outermost = self.deferred_dispatches.is_none();
+        let dispatch_checkpoint = self.deferred_dispatches.get_or_insert_default().len();
+        let event_checkpoint = self.event_queue.get_checkpoint();
+        let journal_checkpoint = self.get_journal_checkpoint();
+
+        let mut result = f(self);
+
+        if result.is_err() {
+            if let Err(e) = self.rollback_journal(journal_checkpoint) {
+                result = Err(e);
+            }
+            self.deferred_dispatches.as_mut().unwrap().truncate(dispatch_checkpoint);
+            self.event_queue.rollback(event_checkpoint);
+        } else if suspended {
+            self.discard_journal(journal_checkpoint);
+        }
+
+        self.set_journal_suspended(suspended);
+        self.commit_transaction();
+        if temporary {
+            self.journal = None;
+        }
+
+        if outermost {
+            let dispatches = Self::cancel_dispatches(self.deferred_dispatches.take().unwrap());
+            for dispatch in dispatches {
+                self.dispatch(dispatch);
+            }
+        }
+        result
+    }
+
+    // an object inserted and removed again within the batch never reaches the handlers,
+    // the handlers would otherwise see an id that no longer exists or was reused
+    fn cancel_dispatches(dispatches: Vec<Dispatch>) -> Vec<Dispatch> {
+        let mut inserted = ahash::AHashMap::new();
+        let mut canceled = vec![false; dispatches.len()];
+
+        for (i, dispatch) in dispatches.iter().enumerate() {
+            let (target, is_insert) = dispatch.target();
+            if is_insert {
+                inserted.insert(target, i);
+            } else if let Some(j) = inserted.remove(&target) {
+                canceled[i] = true;
+                canceled[j] = true;
+            }
+        }
+
+        dispatches.into_iter().zip(canceled).filter(|(_, canceled)| !canceled).map(|(dispatch, _)| dispatch).collect()
+    }
+
+    pub(super) fn dispatch(&mut self, dispatch: Dispatch) {
+        if let Some(deferred_dispatches) = &mut self.deferred_dispatches {
+            deferred_dispatches.push(dispatch);
+            return;
+        }
+
+        match dispatch {
+            Dispatch::InsertTile(archetype_id, id) => {
+                let handler = self.event_handlers.tiles.get(archetype_id as usize).unwrap().clone();
+                handler.on_insert(self, id);
+            }
+            Dispatch::RemoveTile(archetype_id, id) => {
+                let handler = self.event_handlers.tiles.get(archetype_id as usize).unwrap().clone();
+                handler.on_remove(self, id);
+            }
+            Dispatch::InsertBlock(archetype_id, id) => {
+                let handler = self.event_handlers.blocks.get(archetype_id as usize).unwrap().clone();
+                handler.on_insert(self, id);
+            }
+            Dispatch::RemoveBlock(archetype_id, id) => {
+                let handler = self.event_handlers.blocks.get(archetype_id as usize).unwrap().clone();
+                handler.on_remove(self, id);
+            }
+            Dispatch::InsertEntity(archetype_id, id) => {
+                let handler = self.event_handlers.entities.get(archetype_id as usize).unwrap().clone();
+                handler.on_insert(self, id);
+            }
+            Dispatch::RemoveEntity(archetype_id, id) => {
+                let handler = self.event_handlers.entities.get(archetype_id as usize).unwrap().clone();
+                handler.on_remove(self, id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct RecordHandler {
+        calls: RefCell<Vec<(bool, u64)>>,
+    }
+
+    impl EventHandler<BlockId> for RecordHandler {
+        fn on_insert(&self, _: &mut Dataflow, id: BlockId) {
+            self.calls.borrow_mut().push((true, id));
+        }
+
+        fn on_remove(&self, _: &mut Dataflow, id: BlockId) {
+            self.calls.borrow_mut().push((false, id));
+        }
+    }
+
+    fn make_dataflow(handler: Rc<RecordHandler>) -> Dataflow {
+        TestDataflowBuilder::new().block(IVec2::ONE).block(IVec2::new(4, 2)).build_with(EventHandlers {
+            tiles: vec![],
+            blocks: vec![handler.clone(), handler],
+            entities: vec![],
+        })
+    }
+
+    #[test]
+    fn rollback_transaction() {
+        let handler = Rc::new(RecordHandler::default());
+        let mut dataflow = make_dataflow(handler.clone());
+        dataflow.subscribe_event(EventKind::BlockInserted);
+
+        let rock_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        let stone_id = dataflow.insert_block(Block { coord: IVec2::new(40, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        handler.calls.borrow_mut().clear();
+        dataflow.drain_events();
+
+        // clear the ground and place a tree that overlaps the other stone
+        let result = dataflow.transaction("place_tree", |dataflow| {
+            dataflow.remove_block(rock_id)?;
+            dataflow.move_block(stone_id, IVec2::new(3, 1))?;
+            dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 1, ..Default::default() })
+        });
+        assert_eq!(result.unwrap_err(), DataflowError::BlockError(BlockError::Conflict));
+
+        let (rock_id, _) = dataflow.find_block_with_point(IVec2::new(0, 0)).unwrap();
+        assert_eq!(dataflow.get_block(*rock_id).unwrap().archetype_id, 0);
+        let (stone_id, _) = dataflow.find_block_with_point(IVec2::new(40, 0)).unwrap();
+        assert_eq!(dataflow.get_block(*stone_id).unwrap().coord, IVec2::new(40, 0));
+        assert!(dataflow.find_block_with_point(IVec2::new(3, 1)).is_none());
+        assert!(dataflow.get_journal().is_none());
+
+        assert!(handler.calls.borrow().is_empty());
+        assert_eq!(dataflow.drain_events(), (vec![], 0));
+    }
+
+    #[test]
+    fn commit_transaction_with_deferred_handler() {
+        let handler = Rc::new(RecordHandler::default());
+        let mut dataflow = make_dataflow(handler.clone());
+        dataflow.enable_journal();
+
+        let rock_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        handler.calls.borrow_mut().clear();
+
+        let tree_id = dataflow.transaction("place_tree", |dataflow| {
+            dataflow.remove_block(rock_id)?;
+            let tree_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 1, ..Default::default() })?;
+            assert!(handler.calls.borrow().is_empty());
+
+            // a failed inner batch only reverts its own changes
+            let result = dataflow.transaction("place_rock", |dataflow| {
+                dataflow.insert_block(Block { coord: IVec2::new(8, 0), archetype_id: 0, ..Default::default() })?;
+                dataflow.insert_block(Block { coord: IVec2::new(1, 1), archetype_id: 0, ..Default::default() })
+            });
+            assert!(result.is_err());
+            Ok(tree_id)
+        }).unwrap();
+
+        assert_eq!(*handler.calls.borrow(), vec![(false, rock_id), (true, tree_id)]);
+        assert!(dataflow.find_block_with_point(IVec2::new(8, 0)).is_none());
+
+        assert_eq!(dataflow.undo().unwrap(), Some("place_tree".to_string()));
+        let (rock_id, _) = dataflow.find_block_with_point(IVec2::new(0, 0)).unwrap();
+        assert_eq!(dataflow.get_block(*rock_id).unwrap().archetype_id, 0);
+    }
+
+    #[test]
+    fn cancel_insert_and_remove_in_transaction() {
+        let handler = Rc::new(RecordHandler::default());
+        let mut dataflow = make_dataflow(handler.clone());
+
+        let rock_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        handler.calls.borrow_mut().clear();
+
+        // the sapling takes the id of the rock and is gone before the batch ends
+        let tree_id = dataflow.transaction("place_tree", |dataflow| {
+            dataflow.remove_block(rock_id)?;
+            let sapling_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() })?;
+            dataflow.remove_block(sapling_id)?;
+            dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 1, ..Default::default() })
+        }).unwrap();
+
+        assert_eq!(*handler.calls.borrow(), vec![(false, rock_id), (true, tree_id)]);
+    }
+
+    #[test]
+    fn rollback_around_changes_without_journal() {
+        let handler = Rc::new(RecordHandler::default());
+        let mut dataflow = make_dataflow(handler.clone());
+        dataflow.enable_journal();
+
+        let rock_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+
+        // the sapling is removed and the place of the rock is taken without the journal
+        let result = dataflow.transaction("place_tree", |dataflow| {
+            dataflow.remove_block(rock_id)?;
+            let sapling_id = dataflow.insert_block(Block { coord: IVec2::new(8, 0), archetype_id: 0, ..Default::default() })?;
+            dataflow.without_journal(|dataflow| {
+                dataflow.remove_block(sapling_id)?;
+                dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 1, ..Default::default() })
+            })?;
+            dataflow.insert_block(Block { coord: IVec2::new(1, 0), archetype_id: 0, ..Default::default() })
+        });
+        assert_eq!(result.unwrap_err(), DataflowError::BlockError(BlockError::NotFound));
+
+        let (tree_id, _) = dataflow.find_block_with_point(IVec2::new(0, 0)).unwrap();
+        assert_eq!(dataflow.get_block(*tree_id).unwrap().archetype_id, 1);
+        assert!(dataflow.find_block_with_point(IVec2::new(8, 0)).is_none());
+        assert_eq!(dataflow.get_journal().unwrap().undo_name(), Some("insert_block"));
+    }
+}
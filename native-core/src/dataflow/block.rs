@@ -2,6 +2,8 @@ use glam::*;
 
 use crate::geom::*;
 
+use super::ValidationReport;
+
 pub type BlockId = u64;
 
 #[inline]
@@ -19,8 +21,13 @@ fn encode_coord(coord: IVec2) -> u64 {
     (coord.x as u32 as u64) << 32 | coord.y as u32 as u64
 }
 
+#[inline]
+fn decode_coord(coord: u64) -> IVec2 {
+    IVec2::new((coord >> 32) as u32 as i32, coord as u32 as i32)
+}
+
 // locality of reference
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BlockSpatialData {
     pub rect: IRect2,
     pub collision_rect: Option<Rect2>,
@@ -35,6 +42,8 @@ pub struct BlockInfo {
     pub collision_rect: Option<Rect2>,
     pub hint_rect: Rect2,
     pub y_sorting: bool,
+    pub light_emission: u8,
+    pub light_opacity: u8,
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +58,8 @@ pub struct BlockArchetype {
     pub hint_rect: Rect2,
     pub broad_rect: IRect2,
     pub y_sorting: bool,
+    pub light_emission: u8,
+    pub light_opacity: u8,
 }
 
 impl BlockArchetype {
@@ -101,35 +112,8 @@ impl BlockField {
     const CHUNK_SIZE: u32 = 32;
 
     pub fn new(info: BlockFieldInfo) -> Self {
-        let mut archetypes = vec![];
-
         assert!(info.blocks.len() <= u16::MAX as usize, "capacity overflow");
-        for block in info.blocks {
-            if block.size.x <= 0 || block.size.y <= 0 {
-                panic!("size must be positive");
-            }
-            let mut broad_rect = IRect2::new(IVec2::ZERO, block.size);
-
-            if let Some(rect) = &block.collision_rect {
-                if rect.size().x < 0.0 || rect.size().y < 0.0 {
-                    panic!("collision size must be non-negative");
-                }
-                broad_rect = broad_rect.maximum(rect.trunc_over().as_irect2());
-            }
-
-            if block.hint_rect.size().x < 0.0 || block.hint_rect.size().y < 0.0 {
-                panic!("hint size must be non-negative");
-            }
-            broad_rect = broad_rect.maximum(block.hint_rect.trunc_over().as_irect2());
-
-            archetypes.push(BlockArchetype {
-                size: block.size,
-                collision_rect: block.collision_rect,
-                hint_rect: block.hint_rect,
-                broad_rect,
-                y_sorting: block.y_sorting,
-            });
-        }
+        let archetypes = info.blocks.into_iter().map(Self::create_archetype).collect();
 
         Self {
             archetypes,
@@ -140,6 +124,91 @@ impl BlockField {
         }
     }
 
+    fn create_archetype(block: BlockInfo) -> BlockArchetype {
+        if block.size.x <= 0 || block.size.y <= 0 {
+            panic!("size must be positive");
+        }
+        let mut broad_rect = IRect2::new(IVec2::ZERO, block.size);
+
+        if let Some(rect) = &block.collision_rect {
+            if rect.size().x < 0.0 || rect.size().y < 0.0 {
+                panic!("collision size must be non-negative");
+            }
+            broad_rect = broad_rect.maximum(rect.trunc_over().as_irect2());
+        }
+
+        if block.hint_rect.size().x < 0.0 || block.hint_rect.size().y < 0.0 {
+            panic!("hint size must be non-negative");
+        }
+        broad_rect = broad_rect.maximum(block.hint_rect.trunc_over().as_irect2());
+
+        BlockArchetype {
+            size: block.size,
+            collision_rect: block.collision_rect,
+            hint_rect: block.hint_rect,
+            broad_rect,
+            y_sorting: block.y_sorting,
+            light_emission: block.light_emission,
+            light_opacity: block.light_opacity,
+        }
+    }
+
+    // replace archetype data in place, instances whose spatial data changed are re-indexed
+    // rejects archetype data that would make blocks overlap
+    pub fn check_reload(&self, info: &BlockFieldInfo) -> Result<(), BlockError> {
+        assert!(info.blocks.len() == self.archetypes.len(), "number of archetype must not change");
+
+        if self.archetypes.iter().zip(&info.blocks).all(|(archetype, block)| archetype.size == block.size) {
+            return Ok(());
+        }
+
+        let mut occupied = ahash::AHashSet::new();
+        for chunk in &self.chunks {
+            for block in &chunk.blocks {
+                let size = info.blocks[block.archetype_id as usize].size;
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        if !occupied.insert(block.coord + IVec2::new(x, y)) {
+                            return Err(BlockError::Conflict);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // nothing changes when the reload is rejected
+    pub fn reload(&mut self, info: BlockFieldInfo) -> Result<(), BlockError> {
+        self.check_reload(&info)?;
+
+        let archetypes = info.blocks.into_iter().map(Self::create_archetype).collect::<Vec<_>>();
+
+        for chunk in &self.chunks {
+            for (block, id) in chunk.blocks.iter().zip(&chunk.ids) {
+                let archetype = &self.archetypes[block.archetype_id as usize];
+                let new_archetype = &archetypes[block.archetype_id as usize];
+                if archetype.size == new_archetype.size
+                    && archetype.collision_rect == new_archetype.collision_rect
+                    && archetype.hint_rect == new_archetype.hint_rect
+                {
+                    continue;
+                }
+
+                self.hgrid.remove(archetype.broad_rect(block.coord), *id);
+                self.hgrid.insert(new_archetype.broad_rect(block.coord), *id, BlockSpatialData {
+                    rect: new_archetype.rect(block.coord),
+                    collision_rect: new_archetype.collision_rect(block.coord),
+                    hint_rect: new_archetype.hint_rect(block.coord),
+                });
+            }
+        }
+
+        self.archetypes = archetypes;
+        Ok(())
+    }
+
     #[inline]
     fn alloc_chunk(&mut self, coord: IVec2) -> u32 {
         let chunk_coord = Self::find_chunk_coord_internal(coord);
@@ -251,14 +320,17 @@ impl BlockField {
         // update spatial index
         let broad_rect = archetype.broad_rect(block.coord);
         let new_broad_rect = archetype.broad_rect(new_coord);
+        let value = BlockSpatialData {
+            rect: archetype.rect(new_coord),
+            collision_rect: archetype.collision_rect(new_coord),
+            hint_rect: archetype.hint_rect(new_coord),
+        };
         if self.hgrid.check_move(broad_rect, new_broad_rect) {
-            let value = BlockSpatialData {
-                rect: archetype.rect(new_coord),
-                collision_rect: archetype.collision_rect(new_coord),
-                hint_rect: archetype.hint_rect(new_coord),
-            };
             self.hgrid.remove(broad_rect, id);
             self.hgrid.insert(new_broad_rect, id, value);
+        } else {
+            // same cells, but the stored rects still describe the old coordinate
+            self.hgrid.modify(new_broad_rect, id, value);
         }
 
         // move owner
@@ -324,6 +396,12 @@ impl BlockField {
         coord.div_euclid(IVec2::splat(Self::CHUNK_SIZE as i32))
     }
 
+    // chunks are never dropped, so a chunk once created stays in the iteration
+    #[inline]
+    pub fn chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.coord_index.keys().map(|chunk_coord| decode_coord(*chunk_coord))
+    }
+
     #[inline]
     pub fn get_chunk(&self, chunk_coord: IVec2) -> Result<&BlockChunk, BlockError> {
         let chunk_coord_ = encode_coord(chunk_coord);
@@ -370,6 +448,102 @@ impl BlockField {
         self.hgrid.find(rect.trunc_over().as_irect2())
             .filter(move |(_, data)| Intersects::intersects(&rect, &data.hint_rect))
     }
+
+    // validation
+
+    // cross-check the chunk index, the id index and the spatial index against the chunks
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        // chunk index
+        let mut chunk_coords = vec![None; self.chunks.len()];
+        for (chunk_coord_, chunk_id) in &self.coord_index {
+            let chunk_coord = decode_coord(*chunk_coord_);
+            match chunk_coords.get_mut(*chunk_id as usize) {
+                Some(slot @ None) => *slot = Some(chunk_coord),
+                Some(Some(other)) => report.push(format!("chunk {} is indexed at both ({}, {}) and ({}, {})", chunk_id, other.x, other.y, chunk_coord.x, chunk_coord.y)),
+                None => report.push(format!("chunk ({}, {}) is indexed to missing chunk {}", chunk_coord.x, chunk_coord.y, chunk_id)),
+            }
+        }
+
+        // chunks against the other indices, counting the spatial cells each block was found in
+        let mut found = ahash::AHashMap::<BlockId, usize>::default();
+        for (chunk_id, chunk) in self.chunks.iter().enumerate() {
+            let chunk_coord = chunk_coords[chunk_id];
+            if chunk_coord.is_none() {
+                report.push(format!("chunk {} is not indexed by its coordinate", chunk_id));
+            }
+            if chunk.blocks.len() != chunk.ids.len() {
+                report.push(format!("chunk {} holds {} blocks but {} ids", chunk_id, chunk.blocks.len(), chunk.ids.len()));
+            }
+
+            for (local_id, (block, id)) in chunk.blocks.iter().zip(&chunk.ids).enumerate() {
+                let at = format!("block {} at ({}, {})", id, block.coord.x, block.coord.y);
+
+                let address = encode_address(chunk_id as u32, local_id as u32);
+                match self.id_index.get(*id as usize) {
+                    Some(other) if *other == address => {}
+                    Some(other) => {
+                        let (other_chunk_id, other_local_id) = decode_address(*other);
+                        report.push(format!("{}: stored at {}:{} but indexed at {}:{}", at, chunk_id, local_id, other_chunk_id, other_local_id));
+                    }
+                    None => report.push(format!("{}: stored at {}:{} but missing from the id index", at, chunk_id, local_id)),
+                }
+
+                let expected_chunk_coord = Self::find_chunk_coord_internal(block.coord);
+                if let Some(chunk_coord) = chunk_coord && chunk_coord != expected_chunk_coord {
+                    report.push(format!("{}: stored in chunk ({}, {}) instead of ({}, {})", at, chunk_coord.x, chunk_coord.y, expected_chunk_coord.x, expected_chunk_coord.y));
+                }
+
+                let Some(archetype) = self.archetypes.get(block.archetype_id as usize) else {
+                    report.push(format!("{}: invalid archetype {}", at, block.archetype_id));
+                    continue;
+                };
+                let value = BlockSpatialData {
+                    rect: archetype.rect(block.coord),
+                    collision_rect: archetype.collision_rect(block.coord),
+                    hint_rect: archetype.hint_rect(block.coord),
+                };
+                let cells = self.hgrid.get(archetype.broad_rect(block.coord), *id);
+                let missing = cells.iter().filter(|cell| cell.is_none()).count();
+                let stale = cells.iter().flatten().filter(|cell| ***cell != value).count();
+                if missing > 0 {
+                    report.push(format!("{}: missing from {} of {} spatial cells", at, missing, cells.len()));
+                }
+                if stale > 0 {
+                    report.push(format!("{}: stale spatial data in {} of {} spatial cells", at, stale, cells.len()));
+                }
+                found.insert(*id, cells.len() - missing);
+            }
+        }
+
+        // ids that point at nothing
+        for (id, address) in &self.id_index {
+            let (chunk_id, local_id) = decode_address(*address);
+            let stored = self.chunks.get(chunk_id as usize).and_then(|chunk| chunk.ids.get(local_id as usize));
+            match stored {
+                Some(other) if *other == id as BlockId => {}
+                Some(other) => report.push(format!("block {}: indexed at {}:{} which holds block {}", id, chunk_id, local_id, other)),
+                None => report.push(format!("block {}: indexed at {}:{} which is empty", id, chunk_id, local_id)),
+            }
+        }
+
+        // entries left in cells the block does not cover, or of removed blocks
+        let mut counts = ahash::AHashMap::<BlockId, usize>::default();
+        for (id, _) in self.hgrid.iter() {
+            *counts.entry(*id).or_default() += 1;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+        for (id, count) in counts {
+            let expected = found.get(&id).copied().unwrap_or_default();
+            if count > expected {
+                report.push(format!("block {}: {} stray entries in the spatial index", id, count - expected));
+            }
+        }
+
+        report
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -405,6 +579,8 @@ mod tests {
                     collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                     hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                     y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
                 },
                 BlockInfo {
                     display_name: "block_1".into(),
@@ -413,6 +589,8 @@ mod tests {
                     collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                     hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                     y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
                 },
             ],
         })
@@ -429,6 +607,8 @@ mod tests {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             }],
         });
     }
@@ -444,6 +624,8 @@ mod tests {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(-1.0, -1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             }],
         });
     }
@@ -459,6 +641,8 @@ mod tests {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(-1.0, -1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             }],
         });
     }
@@ -723,4 +907,136 @@ mod tests {
         let chunk = field.get_chunk(IVec2::new(-1, 0)).unwrap();
         assert_eq!(chunk.blocks.len(), 3);
     }
+
+    #[test]
+    fn reload_block() {
+        let mut field = make_block_field();
+
+        let id = field
+            .insert(Block {
+                archetype_id: 1,
+                coord: IVec2::new(-1, 3),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let query = field.find_with_point(IVec2::new(0, 4)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+
+        field.reload(BlockFieldInfo {
+            blocks: vec![
+                BlockInfo {
+                    display_name: "block_0".into(),
+                    description: "block_0_desc".into(),
+                    size: IVec2::new(1, 1),
+                    collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                },
+                BlockInfo {
+                    display_name: "block_1".into(),
+                    description: "block_1_desc".into(),
+                    size: IVec2::new(2, 2),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0)),
+                    y_sorting: true,
+                    light_emission: 0,
+                    light_opacity: 0,
+                },
+            ],
+        })
+        .unwrap();
+
+        let block = field.get(id).unwrap();
+        assert_eq!(block.archetype_id, 1);
+        assert_eq!(block.coord, IVec2::new(-1, 3));
+        assert!(field.get_archetype(1).unwrap().y_sorting);
+
+        let query = field.find_with_point(IVec2::new(0, 4)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id));
+        let vec = field.find_with_collision_point(Vec2::new(-0.5, 3.5)).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert!(vec.is_empty());
+
+        field.remove(id).unwrap();
+        let query = field.find_with_point(IVec2::new(0, 4)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+    }
+
+    #[test]
+    fn reload_block_with_conflict() {
+        let mut field = make_block_field();
+
+        field.insert(Block { archetype_id: 0, coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        field.insert(Block { archetype_id: 1, coord: IVec2::new(1, 1), ..Default::default() }).unwrap();
+
+        // block_0 would grow over block_1
+        let info = BlockFieldInfo {
+            blocks: ["block_0", "block_1"]
+                .into_iter()
+                .map(|name| BlockInfo {
+                    display_name: name.into(),
+                    description: format!("{}_desc", name),
+                    size: IVec2::new(2, 2),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                })
+                .collect(),
+        };
+        assert_eq!(field.reload(info), Err(BlockError::Conflict));
+
+        assert_eq!(field.get_archetype(0).unwrap().size, IVec2::new(1, 1));
+        assert_eq!(field.find_with_point(IVec2::new(1, 0)).map(|(id, _)| *id), None);
+        assert!(field.validate().is_valid());
+    }
+
+    #[test]
+    #[should_panic]
+    fn reload_block_with_invalid_count() {
+        let mut field = make_block_field();
+
+        let _ = field.reload(BlockFieldInfo { blocks: vec![] });
+    }
+
+    #[test]
+    fn move_block_in_same_cell() {
+        let mut field = make_block_field();
+
+        let id = field.insert(Block { archetype_id: 1, coord: IVec2::new(2, 0), ..Default::default() }).unwrap();
+        field.r#move(id, IVec2::new(3, 1)).unwrap();
+
+        let query = field.find_with_point(IVec2::new(3, 1)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id));
+        let query = field.find_with_point(IVec2::new(2, 0)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+        assert_eq!(field.find_with_hint_point(Vec2::new(3.5, 1.5)).count(), 1);
+        assert_eq!(field.find_with_hint_point(Vec2::new(2.5, 0.5)).count(), 0);
+    }
+
+    #[test]
+    fn validate_block() {
+        let mut field = make_block_field();
+
+        let mut ids = vec![];
+        for x in 0..4 {
+            ids.push(field.insert(Block { archetype_id: 0, coord: IVec2::new(x, 0), ..Default::default() }).unwrap());
+        }
+        field.remove(ids[0]).unwrap();
+        field.r#move(ids[1], IVec2::new(-1, -40)).unwrap();
+        field.r#move(ids[2], IVec2::new(2, 1)).unwrap();
+        assert!(field.validate().is_valid());
+
+        let archetype = field.get_archetype(0).unwrap().clone();
+        field.hgrid.modify(archetype.broad_rect(IVec2::new(3, 0)), ids[3], BlockSpatialData {
+            rect: archetype.rect(IVec2::new(3, 5)),
+            collision_rect: None,
+            hint_rect: archetype.hint_rect(IVec2::new(3, 5)),
+        });
+        let report = field.validate();
+        assert_eq!(report.issues, [format!("block {} at (3, 0): stale spatial data in 1 of 1 spatial cells", ids[3])]);
+    }
 }
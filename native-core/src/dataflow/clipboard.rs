@@ -0,0 +1,233 @@
+use glam::*;
+
+use super::*;
+
+// copied objects with coordinates relative to the minimum of the copied region
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    pub size: IVec2,
+    pub tiles: Vec<Tile>,
+    pub blocks: Vec<Block>,
+    pub entities: Vec<Entity>,
+}
+
+impl Dataflow {
+    // clipboard
+
+    // copy the tiles, blocks and entities whose coordinate is inside the region,
+    // a block is copied whole if its origin is inside even if it sticks out of the region
+    pub fn copy_region(&self, region: IRect2) -> Clipboard {
+        let offset = region.min;
+
+        let mut tile_ids = self.find_tile_with_rect(region).map(|(id, _)| *id).collect::<Vec<_>>();
+        tile_ids.sort();
+        tile_ids.dedup();
+        let tiles = tile_ids
+            .into_iter()
+            .map(|id| self.get_tile(id).unwrap().clone())
+            .map(|tile| Tile { coord: tile.coord - offset, ..tile })
+            .collect();
+
+        let mut block_ids = self
+            .find_block_with_rect(region)
+            .map(|(id, _)| *id)
+            .filter(|id| Intersects::intersects(&region, &self.get_block(*id).unwrap().coord))
+            .collect::<Vec<_>>();
+        block_ids.sort();
+        block_ids.dedup();
+        let blocks = block_ids
+            .into_iter()
+            .map(|id| self.get_block(id).unwrap().clone())
+            .map(|block| Block { coord: block.coord - offset, ..block })
+            .collect();
+
+        // entities have no extent of their own, so they are taken from the chunks
+        let min = region.min.as_vec2();
+        let max = (region.max + IVec2::ONE).as_vec2();
+        let min_chunk_coord = self.find_entity_chunk_coord(min);
+        let max_chunk_coord = self.find_entity_chunk_coord(max);
+        let mut entities = vec![];
+        for y in min_chunk_coord.y..=max_chunk_coord.y {
+            for x in min_chunk_coord.x..=max_chunk_coord.x {
+                let Ok(chunk) = self.get_entity_chunk(IVec2::new(x, y)) else {
+                    continue;
+                };
+
+                for entity in &chunk.entities {
+                    if entity.coord.cmpge(min).all() && entity.coord.cmplt(max).all() {
+                        entities.push(Entity { coord: entity.coord - min, ..entity.clone() });
+                    }
+                }
+            }
+        }
+
+        Clipboard {
+            size: region.max - region.min + IVec2::ONE,
+            tiles,
+            blocks,
+            entities,
+        }
+    }
+
+    // the smallest region holding the coordinate of every tile, block and entity, None if there is nothing
+    pub fn find_occupied_region(&self) -> Option<IRect2> {
+        let mut coords = vec![];
+        for chunk_coord in self.get_tile_chunk_coords() {
+            coords.extend(self.get_tile_chunk(chunk_coord).unwrap().tiles.iter().map(|tile| tile.coord));
+        }
+        for chunk_coord in self.get_block_chunk_coords() {
+            coords.extend(self.get_block_chunk(chunk_coord).unwrap().blocks.iter().map(|block| block.coord));
+        }
+        for chunk_coord in self.get_entity_chunk_coords() {
+            coords.extend(self.get_entity_chunk(chunk_coord).unwrap().entities.iter().map(|entity| entity.coord.floor().as_ivec2()));
+        }
+
+        let first = *coords.first()?;
+        let region = coords.into_iter().fold(IRect2::new(first, first), |region, coord| IRect2::new(region.min.min(coord), region.max.max(coord)));
+        Some(region)
+    }
+
+    // paste relative to the origin as a single transaction, abort leaves the field untouched,
+    // entities never conflict and are always pasted
+    pub fn paste_region(&mut self, clipboard: &Clipboard, origin: IVec2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.transaction("paste_region", |dataflow| dataflow.paste_region_internal(clipboard, origin, policy))
+    }
+
+    fn paste_region_internal(&mut self, clipboard: &Clipboard, origin: IVec2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        let mut summary = EditSummary::default();
+
+        if policy == ConflictPolicy::Abort {
+            for tile in &clipboard.tiles {
+                if !self.find_tile_conflicts(tile.archetype_id, tile.coord + origin)?.is_empty() {
+                    summary.aborted = true;
+                    return Ok(summary);
+                }
+            }
+            for block in &clipboard.blocks {
+                if !self.find_block_conflicts(block.archetype_id, block.coord + origin)?.is_empty() {
+                    summary.aborted = true;
+                    return Ok(summary);
+                }
+            }
+        }
+
+        for tile in &clipboard.tiles {
+            let coord = tile.coord + origin;
+            let conflicts = self.find_tile_conflicts(tile.archetype_id, coord)?;
+            if !conflicts.is_empty() && policy == ConflictPolicy::Skip {
+                summary.skipped += 1;
+                continue;
+            }
+            for id in conflicts {
+                self.remove_til(id)?;
+                summary.removed += 1;
+            }
+
+            self.insert_tile(Tile { coord, ..tile.clone() })?;
+            summary.inserted += 1;
+        }
+
+        for block in &clipboard.blocks {
+            let coord = block.coord + origin;
+            let conflicts = self.find_block_conflicts(block.archetype_id, coord)?;
+            if !conflicts.is_empty() && policy == ConflictPolicy::Skip {
+                summary.skipped += 1;
+                continue;
+            }
+            for id in conflicts {
+                self.remove_block(id)?;
+                summary.removed += 1;
+            }
+
+            self.insert_block(Block { coord, ..block.clone() })?;
+            summary.inserted += 1;
+        }
+
+        for entity in &clipboard.entities {
+            self.insert_entity(Entity { coord: entity.coord + origin.as_vec2(), ..entity.clone() })?;
+            summary.inserted += 1;
+        }
+
+        Ok(summary)
+    }
+
+    fn find_tile_conflicts(&self, archetype_id: u16, coord: IVec2) -> Result<Vec<TileId>, DataflowError> {
+        let layer_id = self.get_tile_archetype(archetype_id)?.layer_id;
+        Ok(self.find_tile_with_point_in_layer(layer_id, coord).map(|(id, _)| *id).into_iter().collect())
+    }
+
+    fn find_block_conflicts(&self, archetype_id: u16, coord: IVec2) -> Result<Vec<BlockId>, DataflowError> {
+        let rect = self.get_block_archetype(archetype_id)?.rect(coord);
+        let mut ids = self.find_block_with_rect(rect).map(|(id, _)| *id).collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dataflow() -> Dataflow {
+        TestDataflowBuilder::new().tile(false).block(IVec2::ONE).block(IVec2::splat(2)).entity().build()
+    }
+
+    fn make_clipboard(dataflow: &mut Dataflow) -> Clipboard {
+        for y in 10..12 {
+            for x in 10..12 {
+                dataflow.insert_tile(Tile { coord: IVec2::new(x, y), variant: 1, ..Default::default() }).unwrap();
+            }
+        }
+        dataflow.insert_block(Block { coord: IVec2::new(10, 10), archetype_id: 1, ..Default::default() }).unwrap();
+        dataflow.insert_block(Block { coord: IVec2::new(9, 10), archetype_id: 0, ..Default::default() }).unwrap();
+        dataflow.insert_entity(Entity { coord: Vec2::new(11.5, 10.5), ..Default::default() }).unwrap();
+        dataflow.insert_entity(Entity { coord: Vec2::new(12.5, 10.5), ..Default::default() }).unwrap();
+
+        dataflow.copy_region(IRect2::new(IVec2::new(10, 10), IVec2::new(11, 11)))
+    }
+
+    #[test]
+    fn copy_region() {
+        let mut dataflow = make_dataflow();
+        let clipboard = make_clipboard(&mut dataflow);
+
+        assert_eq!(clipboard.size, IVec2::new(2, 2));
+        assert_eq!(clipboard.tiles.len(), 4);
+        assert!(clipboard.tiles.iter().all(|tile| tile.coord.cmpge(IVec2::ZERO).all() && tile.variant == 1));
+        assert_eq!(clipboard.blocks.len(), 1);
+        assert_eq!(clipboard.blocks[0].coord, IVec2::new(0, 0));
+        assert_eq!(clipboard.entities.len(), 1);
+        assert_eq!(clipboard.entities[0].coord, Vec2::new(1.5, 0.5));
+
+        assert_eq!(dataflow.find_occupied_region(), Some(IRect2::new(IVec2::new(9, 10), IVec2::new(12, 11))));
+        assert_eq!(make_dataflow().find_occupied_region(), None);
+    }
+
+    #[test]
+    fn paste_region() {
+        let mut dataflow = make_dataflow();
+        let clipboard = make_clipboard(&mut dataflow);
+
+        let summary = dataflow.paste_region(&clipboard, IVec2::new(40, 40), ConflictPolicy::Abort).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 6, ..Default::default() });
+        assert_eq!(dataflow.get_tile(*dataflow.find_tile_with_point(IVec2::new(41, 41)).unwrap().0).unwrap().variant, 1);
+        assert!(dataflow.find_block_with_point(IVec2::new(41, 41)).is_some());
+        assert_eq!(dataflow.find_entity_with_hint_point(Vec2::new(41.6, 40.6)).count(), 1);
+
+        // overlapping the original by one cell
+        let summary = dataflow.paste_region(&clipboard, IVec2::new(11, 11), ConflictPolicy::Abort).unwrap();
+        assert_eq!(summary, EditSummary { aborted: true, ..Default::default() });
+        assert!(dataflow.find_tile_with_point(IVec2::new(12, 12)).is_none());
+
+        let summary = dataflow.paste_region(&clipboard, IVec2::new(11, 11), ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 4, skipped: 2, ..Default::default() });
+
+        let summary = dataflow.paste_region(&clipboard, IVec2::new(11, 11), ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 6, removed: 5, ..Default::default() });
+        assert!(dataflow.find_block_with_point(IVec2::new(10, 10)).is_none());
+        assert!(dataflow.find_block_with_point(IVec2::new(12, 12)).is_some());
+        assert_eq!(dataflow.validate(), ValidationReport::default());
+        dataflow.debug_validate();
+    }
+}
@@ -0,0 +1,439 @@
+use glam::*;
+
+use super::*;
+
+// what to do when a cell is already occupied by a different archetype
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brush {
+    Rect(IRect2),
+    Line(IVec2, IVec2),
+    Ellipse(IRect2),
+}
+
+impl Brush {
+    // covered cells in a stable order, rects are inclusive
+    pub fn coords(&self) -> Vec<IVec2> {
+        match *self {
+            Brush::Rect(rect) => {
+                let mut coords = vec![];
+                for y in rect.min.y..=rect.max.y {
+                    for x in rect.min.x..=rect.max.x {
+                        coords.push(IVec2::new(x, y));
+                    }
+                }
+                coords
+            }
+            Brush::Line(from, to) => {
+                // bresenham
+                let delta = (to - from).abs();
+                let step = (to - from).signum();
+                let mut error = delta.x - delta.y;
+                let mut coord = from;
+                let mut coords = vec![coord];
+                while coord != to {
+                    let error2 = error * 2;
+                    if error2 > -delta.y {
+                        error -= delta.y;
+                        coord.x += step.x;
+                    }
+                    if error2 < delta.x {
+                        error += delta.x;
+                        coord.y += step.y;
+                    }
+                    coords.push(coord);
+                }
+                coords
+            }
+            Brush::Ellipse(rect) => {
+                // cells whose center lies inside the ellipse inscribed in the rect
+                let center = (rect.min.as_vec2() + rect.max.as_vec2() + 1.0) * 0.5;
+                let radius = (rect.max - rect.min + 1).as_vec2() * 0.5;
+                let mut coords = vec![];
+                for y in rect.min.y..=rect.max.y {
+                    for x in rect.min.x..=rect.max.x {
+                        let offset = (IVec2::new(x, y).as_vec2() + 0.5 - center) / radius;
+                        if offset.length_squared() <= 1.0 {
+                            coords.push(IVec2::new(x, y));
+                        }
+                    }
+                }
+                coords
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditSummary {
+    pub inserted: usize,
+    pub removed: usize,
+    pub skipped: usize,
+    pub aborted: bool,
+}
+
+impl Dataflow {
+    // cells flood fill may visit at most
+    pub const MAX_FLOOD_FILL: usize = 65536;
+
+    pub fn paint_tiles(&mut self, archetype_id: u16, brush: &Brush, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.begin_transaction("paint_tiles");
+        let result = self.paint_tiles_internal(archetype_id, brush, policy);
+        self.commit_transaction();
+        result
+    }
+
+    fn paint_tiles_internal(&mut self, archetype_id: u16, brush: &Brush, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.get_tile_archetype(archetype_id)?;
+
+        let mut summary = EditSummary::default();
+        for coord in brush.coords() {
+            if !self.paint_tile(archetype_id, coord, policy, &mut summary)? {
+                break;
+            }
+        }
+        Ok(summary)
+    }
+
+    // fill the connected cells that hold the same archetype as the start cell in the layer of the new archetype,
+    // cells outside of the bounds are never visited
+    pub fn flood_fill_tiles(&mut self, archetype_id: u16, start: IVec2, bounds: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.begin_transaction("flood_fill_tiles");
+        let result = self.flood_fill_tiles_internal(archetype_id, start, bounds, policy);
+        self.commit_transaction();
+        result
+    }
+
+    fn flood_fill_tiles_internal(&mut self, archetype_id: u16, start: IVec2, bounds: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        let layer_id = self.get_tile_archetype(archetype_id)?.layer_id;
+
+        let archetype_at = |dataflow: &Dataflow, coord: IVec2| {
+            dataflow
+                .find_tile_with_point_in_layer(layer_id, coord)
+                .map(|(id, _)| dataflow.get_tile(*id).unwrap().archetype_id)
+        };
+
+        let mut summary = EditSummary::default();
+        if !Intersects::intersects(&bounds, &start) {
+            return Ok(summary);
+        }
+
+        let target = archetype_at(self, start);
+        if target == Some(archetype_id) {
+            return Ok(summary);
+        }
+
+        let mut region = vec![];
+        let mut visited = ahash::AHashSet::new();
+        let mut queue = std::collections::VecDeque::from([start]);
+        visited.insert(start);
+        while let Some(coord) = queue.pop_front() {
+            region.push(coord);
+            if visited.len() >= Self::MAX_FLOOD_FILL {
+                continue;
+            }
+
+            for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                let next = coord + offset;
+                if !Intersects::intersects(&bounds, &next) || visited.contains(&next) || archetype_at(self, next) != target {
+                    continue;
+                }
+                visited.insert(next);
+                queue.push_back(next);
+            }
+        }
+
+        for coord in region {
+            if !self.paint_tile(archetype_id, coord, policy, &mut summary)? {
+                break;
+            }
+        }
+        Ok(summary)
+    }
+
+    // replace every tile of one archetype inside the region,
+    // a tile is only removed once its replacement is known to fit
+    pub fn replace_tiles(&mut self, from_archetype_id: u16, to_archetype_id: u16, region: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.begin_transaction("replace_tiles");
+        let result = self.replace_tiles_internal(from_archetype_id, to_archetype_id, region, policy);
+        self.commit_transaction();
+        result
+    }
+
+    fn replace_tiles_internal(&mut self, from_archetype_id: u16, to_archetype_id: u16, region: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        let from_layer_id = self.get_tile_archetype(from_archetype_id)?.layer_id;
+        let to_layer_id = self.get_tile_archetype(to_archetype_id)?.layer_id;
+
+        let mut ids = self
+            .find_tile_with_rect(region)
+            .map(|(id, _)| *id)
+            .filter(|id| self.get_tile(*id).unwrap().archetype_id == from_archetype_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+
+        let mut summary = EditSummary::default();
+        if from_archetype_id == to_archetype_id {
+            return Ok(summary);
+        }
+
+        for id in ids {
+            let coord = self.get_tile(id)?.coord;
+
+            // the removed tile frees its own cell, only a tile on another layer can conflict
+            let conflict = from_layer_id != to_layer_id && self.find_tile_with_point_in_layer(to_layer_id, coord).is_some();
+            if conflict {
+                match policy {
+                    ConflictPolicy::Skip => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    ConflictPolicy::Abort => {
+                        summary.aborted = true;
+                        break;
+                    }
+                    ConflictPolicy::Overwrite => {}
+                }
+            }
+
+            self.remove_til(id)?;
+            summary.removed += 1;
+            self.paint_tile(to_archetype_id, coord, ConflictPolicy::Overwrite, &mut summary)?;
+        }
+        Ok(summary)
+    }
+
+    // replace every block of one archetype whose origin is inside the region,
+    // a block is only removed once its replacement is known to fit
+    pub fn replace_blocks(&mut self, from_archetype_id: u16, to_archetype_id: u16, region: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.begin_transaction("replace_blocks");
+        let result = self.replace_blocks_internal(from_archetype_id, to_archetype_id, region, policy);
+        self.commit_transaction();
+        result
+    }
+
+    fn replace_blocks_internal(&mut self, from_archetype_id: u16, to_archetype_id: u16, region: IRect2, policy: ConflictPolicy) -> Result<EditSummary, DataflowError> {
+        self.get_block_archetype(from_archetype_id)?;
+        let to_archetype = self.get_block_archetype(to_archetype_id)?.clone();
+
+        let mut ids = self
+            .find_block_with_rect(region)
+            .map(|(id, _)| *id)
+            .filter(|id| {
+                let block = self.get_block(*id).unwrap();
+                block.archetype_id == from_archetype_id && Intersects::intersects(&region, &block.coord)
+            })
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+
+        let mut summary = EditSummary::default();
+        if from_archetype_id == to_archetype_id {
+            return Ok(summary);
+        }
+
+        // blocks of the list that an earlier replacement overwrote
+        let mut removed_ids = ahash::AHashSet::new();
+
+        for id in ids {
+            if removed_ids.contains(&id) {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let coord = self.get_block(id)?.coord;
+
+            let mut conflicts = self
+                .find_block_with_rect(to_archetype.rect(coord))
+                .map(|(other_id, _)| *other_id)
+                .filter(|other_id| *other_id != id)
+                .collect::<Vec<_>>();
+            conflicts.sort();
+            conflicts.dedup();
+
+            if !conflicts.is_empty() {
+                match policy {
+                    ConflictPolicy::Skip => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    ConflictPolicy::Abort => {
+                        summary.aborted = true;
+                        break;
+                    }
+                    ConflictPolicy::Overwrite => {
+                        for other_id in conflicts {
+                            self.remove_block(other_id)?;
+                            removed_ids.insert(other_id);
+                            summary.removed += 1;
+                        }
+                    }
+                }
+            }
+
+            self.remove_block(id)?;
+            summary.removed += 1;
+            self.insert_block(Block { coord, archetype_id: to_archetype_id, ..Default::default() })?;
+            summary.inserted += 1;
+        }
+        Ok(summary)
+    }
+
+    // returns false when the operation has to stop
+    fn paint_tile(&mut self, archetype_id: u16, coord: IVec2, policy: ConflictPolicy, summary: &mut EditSummary) -> Result<bool, DataflowError> {
+        let layer_id = self.get_tile_archetype(archetype_id)?.layer_id;
+
+        if let Some((id, _)) = self.find_tile_with_point_in_layer(layer_id, coord) {
+            let id = *id;
+            if self.get_tile(id)?.archetype_id == archetype_id {
+                return Ok(true);
+            }
+
+            match policy {
+                ConflictPolicy::Skip => {
+                    summary.skipped += 1;
+                    return Ok(true);
+                }
+                ConflictPolicy::Abort => {
+                    summary.aborted = true;
+                    return Ok(false);
+                }
+                ConflictPolicy::Overwrite => {
+                    self.remove_til(id)?;
+                    summary.removed += 1;
+                }
+            }
+        }
+
+        self.insert_tile(Tile { coord, archetype_id, ..Default::default() })?;
+        summary.inserted += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dataflow() -> Dataflow {
+        TestDataflowBuilder::new()
+            .tile(false)
+            .tile(false)
+            .tile(true)
+            .block(IVec2::ONE)
+            .block(IVec2::splat(2))
+            .build()
+    }
+
+    fn archetype_at(dataflow: &Dataflow, coord: IVec2) -> Option<u16> {
+        dataflow.find_tile_with_point(coord).map(|(id, _)| dataflow.get_tile(*id).unwrap().archetype_id)
+    }
+
+    #[test]
+    fn brush_coords() {
+        let coords = Brush::Line(IVec2::new(0, 0), IVec2::new(3, 1)).coords();
+        assert_eq!(coords.first(), Some(&IVec2::new(0, 0)));
+        assert_eq!(coords.last(), Some(&IVec2::new(3, 1)));
+        assert_eq!(coords.len(), 4);
+
+        let coords = Brush::Rect(IRect2::new(IVec2::new(0, 0), IVec2::new(2, 1))).coords();
+        assert_eq!(coords.len(), 6);
+
+        let coords = Brush::Ellipse(IRect2::new(IVec2::new(0, 0), IVec2::new(4, 4))).coords();
+        assert!(coords.contains(&IVec2::new(2, 2)));
+        assert!(coords.contains(&IVec2::new(2, 0)));
+        assert!(!coords.contains(&IVec2::new(0, 0)));
+        assert!(!coords.contains(&IVec2::new(4, 4)));
+    }
+
+    #[test]
+    fn paint_tiles_with_policy() {
+        let mut dataflow = make_dataflow();
+        let rect = Brush::Rect(IRect2::new(IVec2::new(0, 0), IVec2::new(3, 3)));
+
+        dataflow.insert_tile(Tile { coord: IVec2::new(1, 1), archetype_id: 1, ..Default::default() }).unwrap();
+
+        let summary = dataflow.paint_tiles(0, &rect, ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 15, removed: 0, skipped: 1, aborted: false });
+        assert_eq!(archetype_at(&dataflow, IVec2::new(1, 1)), Some(1));
+
+        let summary = dataflow.paint_tiles(1, &rect, ConflictPolicy::Abort).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 0, removed: 0, skipped: 0, aborted: true });
+
+        let summary = dataflow.paint_tiles(1, &rect, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 15, removed: 15, skipped: 0, aborted: false });
+        assert_eq!(archetype_at(&dataflow, IVec2::new(3, 3)), Some(1));
+
+        assert_eq!(dataflow.paint_tiles(5, &rect, ConflictPolicy::Skip), Err(DataflowError::TileError(TileError::InvalidId)));
+    }
+
+    #[test]
+    fn flood_fill_tiles_in_bounds() {
+        let mut dataflow = make_dataflow();
+
+        // a wall splits the empty area in two
+        dataflow.paint_tiles(2, &Brush::Line(IVec2::new(2, 0), IVec2::new(2, 4)), ConflictPolicy::Skip).unwrap();
+
+        let bounds = IRect2::new(IVec2::new(0, 0), IVec2::new(4, 4));
+        let summary = dataflow.flood_fill_tiles(0, IVec2::new(0, 0), bounds, ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary.inserted, 10);
+        assert_eq!(archetype_at(&dataflow, IVec2::new(1, 4)), Some(0));
+        assert_eq!(archetype_at(&dataflow, IVec2::new(3, 0)), None);
+        assert_eq!(archetype_at(&dataflow, IVec2::new(-1, 0)), None);
+
+        let summary = dataflow.flood_fill_tiles(1, IVec2::new(0, 0), bounds, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 10, removed: 10, skipped: 0, aborted: false });
+        assert_eq!(archetype_at(&dataflow, IVec2::new(2, 2)), Some(2));
+    }
+
+    #[test]
+    fn replace_tiles_in_region() {
+        let mut dataflow = make_dataflow();
+
+        dataflow.paint_tiles(0, &Brush::Rect(IRect2::new(IVec2::new(0, 0), IVec2::new(3, 0))), ConflictPolicy::Skip).unwrap();
+
+        let region = IRect2::new(IVec2::new(0, 0), IVec2::new(1, 0));
+        let summary = dataflow.replace_tiles(0, 2, region, ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 2, removed: 2, skipped: 0, aborted: false });
+        assert_eq!(archetype_at(&dataflow, IVec2::new(1, 0)), Some(2));
+        assert_eq!(archetype_at(&dataflow, IVec2::new(2, 0)), Some(0));
+    }
+
+    #[test]
+    fn replace_blocks_with_policy() {
+        let mut dataflow = make_dataflow();
+
+        let id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        dataflow.insert_block(Block { coord: IVec2::new(1, 1), archetype_id: 0, ..Default::default() }).unwrap();
+        let region = IRect2::new(IVec2::new(0, 0), IVec2::new(0, 0));
+
+        // the larger block would overlap its neighbor
+        let summary = dataflow.replace_blocks(0, 1, region, ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 0, removed: 0, skipped: 1, aborted: false });
+        assert!(dataflow.get_block(id).is_ok());
+
+        let summary = dataflow.replace_blocks(0, 1, region, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 1, removed: 2, skipped: 0, aborted: false });
+        let (new_id, _) = dataflow.find_block_with_point(IVec2::new(1, 1)).unwrap();
+        assert_eq!(dataflow.get_block(*new_id).unwrap().archetype_id, 1);
+
+        // both blocks are in the region, the second one is overwritten by the first replacement
+        let mut dataflow = make_dataflow();
+        dataflow.insert_block(Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        dataflow.insert_block(Block { coord: IVec2::new(1, 1), archetype_id: 0, ..Default::default() }).unwrap();
+        let region = IRect2::new(IVec2::new(0, 0), IVec2::new(1, 1));
+
+        let summary = dataflow.replace_blocks(0, 1, region, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary, EditSummary { inserted: 1, removed: 2, skipped: 1, aborted: false });
+        let ids = dataflow.find_block_with_rect(region).map(|(id, _)| *id).collect::<ahash::AHashSet<_>>();
+        assert_eq!(ids.len(), 1);
+        let block = dataflow.get_block(*ids.iter().next().unwrap()).unwrap();
+        assert_eq!((block.coord, block.archetype_id), (IVec2::new(0, 0), 1));
+    }
+}
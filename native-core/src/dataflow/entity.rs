@@ -2,6 +2,8 @@ use glam::*;
 
 use crate::geom::*;
 
+use super::ValidationReport;
+
 pub type EntityId = u64;
 
 #[inline]
@@ -19,8 +21,13 @@ fn encode_coord(coord: IVec2) -> u64 {
     (coord.x as u32 as u64) << 32 | coord.y as u32 as u64
 }
 
+#[inline]
+fn decode_coord(coord: u64) -> IVec2 {
+    IVec2::new((coord >> 32) as u32 as i32, coord as u32 as i32)
+}
+
 // locality of reference
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EntitySpatialData {
     pub collision_rect: Option<Rect2>,
     pub hint_rect: Rect2,
@@ -33,6 +40,8 @@ pub struct EntityInfo {
     pub collision_rect: Option<Rect2>,
     pub hint_rect: Rect2,
     pub y_sorting: bool,
+    pub light_emission: u8,
+    pub light_opacity: u8,
 }
 
 #[derive(Debug, Clone)]
@@ -46,6 +55,8 @@ pub struct EntityArchetype {
     pub hint_rect: Rect2,
     pub broad_rect: IRect2,
     pub y_sorting: bool,
+    pub light_emission: u8,
+    pub light_opacity: u8,
 }
 
 impl EntityArchetype {
@@ -93,31 +104,8 @@ impl EntityField {
     const CHUNK_SIZE: u32 = 32;
 
     pub fn new(info: EntityFieldInfo) -> Self {
-        let mut archetypes = vec![];
-
         assert!(info.entities.len() <= u16::MAX as usize, "capacity overflow");
-        for entity in info.entities {
-            let mut broad_rect = IRect2::new(IVec2::MAX, IVec2::MIN);
-
-            if let Some(rect) = &entity.collision_rect {
-                if rect.size().x < 0.0 || rect.size().y < 0.0 {
-                    panic!("collision size must be non-negative");
-                }
-                broad_rect = broad_rect.maximum(rect.trunc_over().as_irect2());
-            }
-
-            if entity.hint_rect.size().x < 0.0 || entity.hint_rect.size().y < 0.0 {
-                panic!("hint size must be non-negative");
-            }
-            broad_rect = broad_rect.maximum(entity.hint_rect.trunc_over().as_irect2());
-
-            archetypes.push(EntityArchetype {
-                collision_rect: entity.collision_rect,
-                hint_rect: entity.hint_rect,
-                broad_rect,
-                y_sorting: entity.y_sorting,
-            });
-        }
+        let archetypes = info.entities.into_iter().map(Self::create_archetype).collect();
 
         Self {
             archetypes,
@@ -128,6 +116,55 @@ impl EntityField {
         }
     }
 
+    fn create_archetype(entity: EntityInfo) -> EntityArchetype {
+        let mut broad_rect = IRect2::new(IVec2::MAX, IVec2::MIN);
+
+        if let Some(rect) = &entity.collision_rect {
+            if rect.size().x < 0.0 || rect.size().y < 0.0 {
+                panic!("collision size must be non-negative");
+            }
+            broad_rect = broad_rect.maximum(rect.trunc_over().as_irect2());
+        }
+
+        if entity.hint_rect.size().x < 0.0 || entity.hint_rect.size().y < 0.0 {
+            panic!("hint size must be non-negative");
+        }
+        broad_rect = broad_rect.maximum(entity.hint_rect.trunc_over().as_irect2());
+
+        EntityArchetype {
+            collision_rect: entity.collision_rect,
+            hint_rect: entity.hint_rect,
+            broad_rect,
+            y_sorting: entity.y_sorting,
+            light_emission: entity.light_emission,
+            light_opacity: entity.light_opacity,
+        }
+    }
+
+    // replace archetype data in place, instances whose spatial data changed are re-indexed
+    pub fn reload(&mut self, info: EntityFieldInfo) {
+        assert!(info.entities.len() == self.archetypes.len(), "number of archetype must not change");
+        let archetypes = info.entities.into_iter().map(Self::create_archetype).collect::<Vec<_>>();
+
+        for chunk in &self.chunks {
+            for (entity, id) in chunk.entities.iter().zip(&chunk.ids) {
+                let archetype = &self.archetypes[entity.archetype_id as usize];
+                let new_archetype = &archetypes[entity.archetype_id as usize];
+                if archetype.collision_rect == new_archetype.collision_rect && archetype.hint_rect == new_archetype.hint_rect {
+                    continue;
+                }
+
+                self.hgrid.remove(archetype.broad_rect(entity.coord), *id);
+                self.hgrid.insert(new_archetype.broad_rect(entity.coord), *id, EntitySpatialData {
+                    collision_rect: new_archetype.collision_rect(entity.coord),
+                    hint_rect: new_archetype.hint_rect(entity.coord),
+                });
+            }
+        }
+
+        self.archetypes = archetypes;
+    }
+
     #[inline]
     fn alloc_chunk(&mut self, coord: Vec2) -> u32 {
         let chunk_coord = Self::find_chunk_coord_internal(coord);
@@ -232,13 +269,16 @@ impl EntityField {
         // update spatial index
         let broad_rect = archetype.broad_rect(entity.coord);
         let new_broad_rect = archetype.broad_rect(new_coord);
+        let value = EntitySpatialData {
+            collision_rect: archetype.collision_rect(new_coord),
+            hint_rect: archetype.hint_rect(new_coord),
+        };
         if self.hgrid.check_move(broad_rect, new_broad_rect) {
-            let value = EntitySpatialData {
-                collision_rect: archetype.collision_rect(new_coord),
-                hint_rect: archetype.hint_rect(new_coord),
-            };
             self.hgrid.remove(broad_rect, id);
             self.hgrid.insert(new_broad_rect, id, value);
+        } else {
+            // same cells, but the stored rects still describe the old coordinate
+            self.hgrid.modify(new_broad_rect, id, value);
         }
 
         // move owner
@@ -304,6 +344,12 @@ impl EntityField {
         coord.div_euclid(Vec2::splat(Self::CHUNK_SIZE as f32)).as_ivec2()
     }
 
+    // chunks are never dropped, so a chunk once created stays in the iteration
+    #[inline]
+    pub fn chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.coord_index.keys().map(|chunk_coord| decode_coord(*chunk_coord))
+    }
+
     #[inline]
     pub fn get_chunk(&self, chunk_coord: IVec2) -> Result<&EntityChunk, EntityError> {
         let chunk_coord_ = encode_coord(chunk_coord);
@@ -337,6 +383,101 @@ impl EntityField {
         self.hgrid.find(rect.trunc_over().as_irect2())
             .filter(move |(_, data)| Intersects::intersects(&rect, &data.hint_rect))
     }
+
+    // validation
+
+    // cross-check the chunk index, the id index and the spatial index against the chunks
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        // chunk index
+        let mut chunk_coords = vec![None; self.chunks.len()];
+        for (chunk_coord_, chunk_id) in &self.coord_index {
+            let chunk_coord = decode_coord(*chunk_coord_);
+            match chunk_coords.get_mut(*chunk_id as usize) {
+                Some(slot @ None) => *slot = Some(chunk_coord),
+                Some(Some(other)) => report.push(format!("chunk {} is indexed at both ({}, {}) and ({}, {})", chunk_id, other.x, other.y, chunk_coord.x, chunk_coord.y)),
+                None => report.push(format!("chunk ({}, {}) is indexed to missing chunk {}", chunk_coord.x, chunk_coord.y, chunk_id)),
+            }
+        }
+
+        // chunks against the other indices, counting the spatial cells each entity was found in
+        let mut found = ahash::AHashMap::<EntityId, usize>::default();
+        for (chunk_id, chunk) in self.chunks.iter().enumerate() {
+            let chunk_coord = chunk_coords[chunk_id];
+            if chunk_coord.is_none() {
+                report.push(format!("chunk {} is not indexed by its coordinate", chunk_id));
+            }
+            if chunk.entities.len() != chunk.ids.len() {
+                report.push(format!("chunk {} holds {} entitys but {} ids", chunk_id, chunk.entities.len(), chunk.ids.len()));
+            }
+
+            for (local_id, (entity, id)) in chunk.entities.iter().zip(&chunk.ids).enumerate() {
+                let at = format!("entity {} at ({}, {})", id, entity.coord.x, entity.coord.y);
+
+                let address = encode_address(chunk_id as u32, local_id as u32);
+                match self.id_index.get(*id as usize) {
+                    Some(other) if *other == address => {}
+                    Some(other) => {
+                        let (other_chunk_id, other_local_id) = decode_address(*other);
+                        report.push(format!("{}: stored at {}:{} but indexed at {}:{}", at, chunk_id, local_id, other_chunk_id, other_local_id));
+                    }
+                    None => report.push(format!("{}: stored at {}:{} but missing from the id index", at, chunk_id, local_id)),
+                }
+
+                let expected_chunk_coord = Self::find_chunk_coord_internal(entity.coord);
+                if let Some(chunk_coord) = chunk_coord && chunk_coord != expected_chunk_coord {
+                    report.push(format!("{}: stored in chunk ({}, {}) instead of ({}, {})", at, chunk_coord.x, chunk_coord.y, expected_chunk_coord.x, expected_chunk_coord.y));
+                }
+
+                let Some(archetype) = self.archetypes.get(entity.archetype_id as usize) else {
+                    report.push(format!("{}: invalid archetype {}", at, entity.archetype_id));
+                    continue;
+                };
+                let value = EntitySpatialData {
+                    collision_rect: archetype.collision_rect(entity.coord),
+                    hint_rect: archetype.hint_rect(entity.coord),
+                };
+                let cells = self.hgrid.get(archetype.broad_rect(entity.coord), *id);
+                let missing = cells.iter().filter(|cell| cell.is_none()).count();
+                let stale = cells.iter().flatten().filter(|cell| ***cell != value).count();
+                if missing > 0 {
+                    report.push(format!("{}: missing from {} of {} spatial cells", at, missing, cells.len()));
+                }
+                if stale > 0 {
+                    report.push(format!("{}: stale spatial data in {} of {} spatial cells", at, stale, cells.len()));
+                }
+                found.insert(*id, cells.len() - missing);
+            }
+        }
+
+        // ids that point at nothing
+        for (id, address) in &self.id_index {
+            let (chunk_id, local_id) = decode_address(*address);
+            let stored = self.chunks.get(chunk_id as usize).and_then(|chunk| chunk.ids.get(local_id as usize));
+            match stored {
+                Some(other) if *other == id as EntityId => {}
+                Some(other) => report.push(format!("entity {}: indexed at {}:{} which holds entity {}", id, chunk_id, local_id, other)),
+                None => report.push(format!("entity {}: indexed at {}:{} which is empty", id, chunk_id, local_id)),
+            }
+        }
+
+        // entries left in cells the entity does not cover, or of removed entitys
+        let mut counts = ahash::AHashMap::<EntityId, usize>::default();
+        for (id, _) in self.hgrid.iter() {
+            *counts.entry(*id).or_default() += 1;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+        for (id, count) in counts {
+            let expected = found.get(&id).copied().unwrap_or_default();
+            if count > expected {
+                report.push(format!("entity {}: {} stray entries in the spatial index", id, count - expected));
+            }
+        }
+
+        report
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -371,6 +512,8 @@ mod tests {
                     collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                     hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                     y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
                 },
                 EntityInfo {
                     display_name: "entity_1".into(),
@@ -378,6 +521,8 @@ mod tests {
                     collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                     hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                     y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
                 },
             ],
         })
@@ -393,6 +538,8 @@ mod tests {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(-1.0, -1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             }],
         });
     }
@@ -407,6 +554,8 @@ mod tests {
                 collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
                 hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(-1.0, -1.0)),
                 y_sorting: false,
+                light_emission: 0,
+                light_opacity: 0,
             }],
         });
     }
@@ -625,4 +774,84 @@ mod tests {
         let chunk = field.get_chunk(IVec2::new(-1, 0)).unwrap();
         assert_eq!(chunk.entities.len(), 3);
     }
+
+    #[test]
+    fn reload_entity() {
+        let mut field = make_entity_field();
+
+        let id = field
+            .insert(Entity {
+                archetype_id: 1,
+                coord: Vec2::new(-1.0, 3.0),
+                ..Default::default()
+            })
+            .unwrap();
+
+        field.reload(EntityFieldInfo {
+            entities: vec![
+                EntityInfo {
+                    display_name: "entity_0".into(),
+                    description: "entity_0_desc".into(),
+                    collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                },
+                EntityInfo {
+                    display_name: "entity_1".into(),
+                    description: "entity_1_desc".into(),
+                    collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(3.0, 3.0))),
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                },
+            ],
+        });
+
+        let entity = field.get(id).unwrap();
+        assert_eq!(entity.archetype_id, 1);
+        assert_eq!(entity.coord, Vec2::new(-1.0, 3.0));
+
+        let vec = field.find_with_collision_point(Vec2::new(1.5, 5.5)).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(vec, vec![id]);
+    }
+
+    #[test]
+    fn move_entity_in_same_cell() {
+        let mut field = make_entity_field();
+
+        let id = field.insert(Entity { archetype_id: 1, coord: Vec2::new(2.0, 0.0), ..Default::default() }).unwrap();
+        field.r#move(id, Vec2::new(3.5, 1.5)).unwrap();
+
+        assert_eq!(field.find_with_hint_point(Vec2::new(4.0, 2.0)).map(|(id, _)| *id).collect::<Vec<_>>(), [id]);
+        assert_eq!(field.find_with_hint_point(Vec2::new(2.5, 0.5)).count(), 0);
+        assert_eq!(field.find_with_collision_point(Vec2::new(4.0, 2.0)).count(), 1);
+        assert_eq!(field.find_with_collision_point(Vec2::new(2.5, 0.5)).count(), 0);
+    }
+
+    #[test]
+    fn validate_entity() {
+        let mut field = make_entity_field();
+
+        let mut ids = vec![];
+        for x in 0..4 {
+            ids.push(field.insert(Entity { archetype_id: 0, coord: Vec2::new(x as f32, 0.5), ..Default::default() }).unwrap());
+        }
+        field.remove(ids[0]).unwrap();
+        field.r#move(ids[1], Vec2::new(40.0, 0.5)).unwrap();
+        field.r#move(ids[2], Vec2::new(2.5, 1.5)).unwrap();
+        assert!(field.validate().is_valid());
+
+        let chunk = field.chunks.get_mut(0).unwrap();
+        chunk.entities[0].coord = Vec2::new(-0.5, 0.5);
+        let id = chunk.ids[0];
+        let report = field.validate();
+        assert_eq!(report.issues, [
+            format!("entity {} at (-0.5, 0.5): stored in chunk (0, 0) instead of (-1, 0)", id),
+            format!("entity {} at (-0.5, 0.5): missing from 1 of 2 spatial cells", id),
+            format!("entity {} at (-0.5, 0.5): stale spatial data in 1 of 2 spatial cells", id),
+        ]);
+    }
 }
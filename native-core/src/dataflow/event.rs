@@ -0,0 +1,207 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    TileInserted,
+    TileRemoved,
+    TileChanged,
+    BlockInserted,
+    BlockRemoved,
+    BlockChanged,
+    EntityInserted,
+    EntityRemoved,
+    EntityChanged,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 9] = [
+        EventKind::TileInserted,
+        EventKind::TileRemoved,
+        EventKind::TileChanged,
+        EventKind::BlockInserted,
+        EventKind::BlockRemoved,
+        EventKind::BlockChanged,
+        EventKind::EntityInserted,
+        EventKind::EntityRemoved,
+        EventKind::EntityChanged,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::TileInserted => "tile_inserted",
+            EventKind::TileRemoved => "tile_removed",
+            EventKind::TileChanged => "tile_changed",
+            EventKind::BlockInserted => "block_inserted",
+            EventKind::BlockRemoved => "block_removed",
+            EventKind::BlockChanged => "block_changed",
+            EventKind::EntityInserted => "entity_inserted",
+            EventKind::EntityRemoved => "entity_removed",
+            EventKind::EntityChanged => "entity_changed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub id: u64,
+}
+
+// events of unsubscribed kinds are never stored,
+// events past the capacity are dropped and counted until the next drain
+#[derive(Debug, Clone)]
+pub struct EventQueue {
+    capacity: usize,
+    subscriptions: ahash::AHashSet<EventKind>,
+    events: Vec<Event>,
+    dropped: usize,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl EventQueue {
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            subscriptions: Default::default(),
+            events: Default::default(),
+            dropped: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn subscribe(&mut self, kind: EventKind) {
+        self.subscriptions.insert(kind);
+    }
+
+    #[inline]
+    pub fn unsubscribe(&mut self, kind: EventKind) {
+        self.subscriptions.remove(&kind);
+    }
+
+    #[inline]
+    pub fn is_subscribed(&self, kind: EventKind) -> bool {
+        self.subscriptions.contains(&kind)
+    }
+
+    #[inline]
+    pub fn push(&mut self, kind: EventKind, id: u64) {
+        if !self.subscriptions.contains(&kind) {
+            return;
+        }
+
+        if self.events.len() >= self.capacity {
+            self.dropped += 1;
+            return;
+        }
+
+        self.events.push(Event { kind, id });
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // the queue length and drop count to roll back to
+    #[inline]
+    pub(super) fn get_checkpoint(&self) -> (usize, usize) {
+        (self.events.len(), self.dropped)
+    }
+
+    // forget the events queued or dropped after the checkpoint
+    #[inline]
+    pub(super) fn rollback(&mut self, checkpoint: (usize, usize)) {
+        let (len, dropped) = checkpoint;
+        self.events.truncate(len);
+        self.dropped = dropped;
+    }
+
+    // returns the queued events in order and the number of dropped events
+    pub fn drain(&mut self) -> (Vec<Event>, usize) {
+        let events = std::mem::take(&mut self.events);
+        let dropped = std::mem::take(&mut self.dropped);
+        (events, dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_kind_name() {
+        for kind in EventKind::ALL {
+            assert_eq!(EventKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(EventKind::from_name("tile"), None);
+    }
+
+    #[test]
+    fn filter_event() {
+        let mut queue = EventQueue::default();
+
+        queue.subscribe(EventKind::EntityInserted);
+        queue.push(EventKind::EntityInserted, 1);
+        queue.push(EventKind::EntityChanged, 1);
+        queue.push(EventKind::EntityInserted, 2);
+
+        let (events, dropped) = queue.drain();
+        assert_eq!(events, vec![
+            Event { kind: EventKind::EntityInserted, id: 1 },
+            Event { kind: EventKind::EntityInserted, id: 2 },
+        ]);
+        assert_eq!(dropped, 0);
+
+        queue.unsubscribe(EventKind::EntityInserted);
+        queue.push(EventKind::EntityInserted, 3);
+        assert_eq!(queue.drain(), (vec![], 0));
+    }
+
+    #[test]
+    fn bounded_event() {
+        let mut queue = EventQueue::new(2);
+
+        queue.subscribe(EventKind::BlockChanged);
+        for id in 0..5 {
+            queue.push(EventKind::BlockChanged, id);
+        }
+
+        let (events, dropped) = queue.drain();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].id, 1);
+        assert_eq!(dropped, 3);
+
+        assert_eq!(queue.drain(), (vec![], 0));
+    }
+
+    #[test]
+    fn rollback_event() {
+        let mut queue = EventQueue::new(2);
+
+        queue.subscribe(EventKind::BlockChanged);
+        queue.push(EventKind::BlockChanged, 0);
+        let checkpoint = queue.get_checkpoint();
+        for id in 1..5 {
+            queue.push(EventKind::BlockChanged, id);
+        }
+        queue.rollback(checkpoint);
+
+        let (events, dropped) = queue.drain();
+        assert_eq!(events, vec![Event { kind: EventKind::BlockChanged, id: 0 }]);
+        assert_eq!(dropped, 0);
+    }
+}
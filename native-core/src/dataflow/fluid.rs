@@ -0,0 +1,417 @@
+use glam::*;
+
+#[inline]
+fn encode_coord(coord: IVec2) -> u64 {
+    (coord.x as u32 as u64) << 32 | coord.y as u32 as u64
+}
+
+#[derive(Debug, Clone)]
+pub struct FluidInfo {
+    pub display_name: String,
+    pub description: String,
+    pub viscosity: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct FluidFieldInfo {
+    pub fluids: Vec<FluidInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FluidArchetype {
+    pub viscosity: u8,
+}
+
+impl FluidArchetype {
+    // amount moved toward a lower neighbor in a single tick,
+    // at most a fifth of the difference so that a cell never overshoots its neighbors
+    #[inline]
+    pub fn flow(&self, level: u8, neighbor_level: u8) -> u8 {
+        ((level - neighbor_level) as u16 / (5 + self.viscosity as u16)) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fluid {
+    pub archetype_id: u16,
+    pub level: u8,
+}
+
+#[derive(Debug)]
+pub struct FluidChunk {
+    pub version: u64,
+    pub fluids: Vec<Fluid>,
+}
+
+#[derive(Debug)]
+pub struct FluidField {
+    archetypes: Vec<FluidArchetype>,
+    chunks: Vec<FluidChunk>,
+    coord_index: ahash::AHashMap<u64, u32>,
+    awake_chunks: ahash::AHashSet<IVec2>,
+}
+
+impl FluidField {
+    const CHUNK_SIZE: u32 = 32;
+
+    pub const MAX_LEVEL: u8 = u8::MAX;
+
+    pub fn new(info: FluidFieldInfo) -> Self {
+        let mut archetypes = vec![];
+
+        assert!(info.fluids.len() <= u16::MAX as usize, "capacity overflow");
+        for fluid in info.fluids {
+            archetypes.push(FluidArchetype {
+                viscosity: fluid.viscosity,
+            });
+        }
+
+        Self {
+            archetypes,
+            chunks: Default::default(),
+            coord_index: Default::default(),
+            awake_chunks: Default::default(),
+        }
+    }
+
+    #[inline]
+    fn alloc_chunk(&mut self, coord: IVec2) -> u32 {
+        let chunk_coord = Self::find_chunk_coord_internal(coord);
+        let chunk_coord_ = encode_coord(chunk_coord);
+
+        if let Some(chunk_id) = self.coord_index.get(&chunk_coord_) {
+            *chunk_id
+        } else {
+            assert!(self.chunks.len() <= u32::MAX as usize, "capacity overflow");
+            let chunk_id = self.chunks.len() as u32;
+            self.chunks.push(FluidChunk {
+                version: Default::default(),
+                fluids: vec![Fluid::default(); (Self::CHUNK_SIZE * Self::CHUNK_SIZE) as usize],
+            });
+            self.coord_index.insert(chunk_coord_, chunk_id);
+            chunk_id
+        }
+    }
+
+    #[inline]
+    fn local_index(coord: IVec2) -> usize {
+        let local = coord.rem_euclid(IVec2::splat(Self::CHUNK_SIZE as i32));
+        (local.x + local.y * Self::CHUNK_SIZE as i32) as usize
+    }
+
+    pub fn fill(&mut self, coord: IVec2, fluid: Fluid) -> Result<(), FluidError> {
+        self.archetypes.get(fluid.archetype_id as usize).ok_or(FluidError::InvalidId)?;
+
+        if self.get(coord).is_some_and(|current| current.archetype_id != fluid.archetype_id) {
+            return Err(FluidError::Conflict);
+        }
+
+        let chunk_id = self.alloc_chunk(coord);
+        let chunk = self.chunks.get_mut(chunk_id as usize).unwrap();
+        chunk.fluids[Self::local_index(coord)] = if fluid.level == 0 { Fluid::default() } else { fluid };
+        chunk.version += 1;
+
+        self.wake(coord);
+        Ok(())
+    }
+
+    pub fn drain(&mut self, coord: IVec2) -> Result<Fluid, FluidError> {
+        let fluid = *self.get(coord).ok_or(FluidError::NotFound)?;
+
+        let chunk_id = self.alloc_chunk(coord);
+        let chunk = self.chunks.get_mut(chunk_id as usize).unwrap();
+        chunk.fluids[Self::local_index(coord)] = Fluid::default();
+        chunk.version += 1;
+
+        self.wake(coord);
+        Ok(fluid)
+    }
+
+    // returns the fluid only if the cell is not empty
+    #[inline]
+    pub fn get(&self, coord: IVec2) -> Option<&Fluid> {
+        let chunk_coord = Self::find_chunk_coord_internal(coord);
+        let chunk_id = *self.coord_index.get(&encode_coord(chunk_coord))?;
+        let chunk = self.chunks.get(chunk_id as usize).unwrap();
+        let fluid = chunk.fluids.get(Self::local_index(coord)).unwrap();
+        (fluid.level > 0).then_some(fluid)
+    }
+
+    #[inline]
+    pub fn get_level(&self, coord: IVec2) -> u8 {
+        self.get(coord).map(|fluid| fluid.level).unwrap_or_default()
+    }
+
+    // archetype
+
+    #[inline]
+    pub fn get_archetype(&self, archetype_id: u16) -> Result<&FluidArchetype, FluidError> {
+        self.archetypes.get(archetype_id as usize).ok_or(FluidError::InvalidId)
+    }
+
+    // simulation
+
+    // wake the chunk containing the coord and the neighbors sharing an edge with it
+    pub fn wake(&mut self, coord: IVec2) {
+        for offset in [IVec2::ZERO, IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let chunk_coord = Self::find_chunk_coord_internal(coord + offset);
+            if self.coord_index.contains_key(&encode_coord(chunk_coord)) {
+                self.awake_chunks.insert(chunk_coord);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_sleeping(&self, chunk_coord: IVec2) -> bool {
+        !self.awake_chunks.contains(&chunk_coord)
+    }
+
+    // advance the simulation by a single tick, every chunk without changes falls asleep
+    pub fn step<F>(&mut self, is_wall: F) where F: Fn(IVec2) -> bool {
+        const NEIGHBORS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+        let mut awake_chunks = std::mem::take(&mut self.awake_chunks).into_iter().collect::<Vec<_>>();
+        awake_chunks.sort_by_key(|coord| (coord.y, coord.x));
+
+        // compute flows from the snapshot, so the result is independent of iteration order
+        let mut deltas = ahash::AHashMap::<IVec2, (u16, i32)>::new();
+        for chunk_coord in awake_chunks {
+            let origin = chunk_coord * Self::CHUNK_SIZE as i32;
+
+            for v in 0..Self::CHUNK_SIZE as i32 {
+                for u in 0..Self::CHUNK_SIZE as i32 {
+                    let coord = origin + IVec2::new(u, v);
+
+                    let Some(fluid) = self.get(coord) else {
+                        continue;
+                    };
+                    if is_wall(coord) {
+                        continue;
+                    }
+                    let archetype = self.archetypes.get(fluid.archetype_id as usize).unwrap();
+
+                    for offset in NEIGHBORS {
+                        let neighbor_coord = coord + offset;
+
+                        let neighbor_level = match self.get(neighbor_coord) {
+                            Some(neighbor) if neighbor.archetype_id != fluid.archetype_id => continue,
+                            Some(neighbor) => neighbor.level,
+                            None if !self.is_receivable(neighbor_coord, fluid.archetype_id) => continue,
+                            None => 0,
+                        };
+                        if neighbor_level >= fluid.level || is_wall(neighbor_coord) {
+                            continue;
+                        }
+
+                        let flow = archetype.flow(fluid.level, neighbor_level);
+                        if flow == 0 {
+                            continue;
+                        }
+
+                        deltas.entry(coord).or_insert((fluid.archetype_id, 0)).1 -= flow as i32;
+                        deltas.entry(neighbor_coord).or_insert((fluid.archetype_id, 0)).1 += flow as i32;
+                    }
+                }
+            }
+        }
+
+        // apply flows and keep only changed chunks awake
+        for (coord, (archetype_id, delta)) in deltas {
+            if delta == 0 {
+                continue;
+            }
+
+            let chunk_id = self.alloc_chunk(coord);
+            let chunk = self.chunks.get_mut(chunk_id as usize).unwrap();
+            let fluid = chunk.fluids.get_mut(Self::local_index(coord)).unwrap();
+
+            let level = (fluid.level as i32 + delta).clamp(0, Self::MAX_LEVEL as i32) as u8;
+            *fluid = if level == 0 { Fluid::default() } else { Fluid { archetype_id, level } };
+            chunk.version += 1;
+
+            self.awake_chunks.insert(Self::find_chunk_coord_internal(coord));
+        }
+    }
+
+    // an empty cell accepts a fluid only if no other kind of fluid touches it,
+    // which keeps different fluids from racing into the same cell
+    #[inline]
+    fn is_receivable(&self, coord: IVec2, archetype_id: u16) -> bool {
+        [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+            .into_iter()
+            .all(|offset| self.get(coord + offset).is_none_or(|fluid| fluid.archetype_id == archetype_id))
+    }
+
+    // transfer chunk data
+
+    #[inline]
+    pub fn find_chunk_coord(&self, coord: Vec2) -> IVec2 {
+        coord.div_euclid(Vec2::splat(Self::CHUNK_SIZE as f32)).as_ivec2()
+    }
+
+    #[inline]
+    fn find_chunk_coord_internal(coord: IVec2) -> IVec2 {
+        coord.div_euclid(IVec2::splat(Self::CHUNK_SIZE as i32))
+    }
+
+    #[inline]
+    pub fn get_chunk(&self, chunk_coord: IVec2) -> Result<&FluidChunk, FluidError> {
+        let chunk_coord_ = encode_coord(chunk_coord);
+        let chunk_id = *self.coord_index.get(&chunk_coord_).ok_or(FluidError::NotFound)?;
+        let chunk = self.chunks.get(chunk_id as usize).unwrap();
+        Ok(chunk)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FluidError {
+    NotFound,
+    Conflict,
+    InvalidId,
+}
+
+impl std::fmt::Display for FluidError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found error"),
+            Self::Conflict => write!(f, "conflict error"),
+            Self::InvalidId => write!(f, "invalid id error"),
+        }
+    }
+}
+
+impl std::error::Error for FluidError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_fluid_field() -> FluidField {
+        FluidField::new(FluidFieldInfo {
+            fluids: vec![
+                FluidInfo {
+                    display_name: "fluid_0".into(),
+                    description: "fluid_0_desc".into(),
+                    viscosity: 0,
+                },
+                FluidInfo {
+                    display_name: "fluid_1".into(),
+                    description: "fluid_1_desc".into(),
+                    viscosity: 4,
+                },
+            ],
+        })
+    }
+
+    fn total_level(field: &FluidField, rect: std::ops::RangeInclusive<i32>) -> u32 {
+        let mut total = 0;
+        for y in rect.clone() {
+            for x in rect.clone() {
+                total += field.get_level(IVec2::new(x, y)) as u32;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn crud_fluid() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 1, level: 100 }).unwrap();
+
+        let fluid = field.get(IVec2::new(-1, 3)).unwrap();
+        assert_eq!(fluid.archetype_id, 1);
+        assert_eq!(fluid.level, 100);
+        assert_eq!(field.get_level(IVec2::new(-1, 3)), 100);
+        assert_eq!(field.get_level(IVec2::new(-1, 4)), 0);
+
+        let fluid = field.drain(IVec2::new(-1, 3)).unwrap();
+        assert_eq!(fluid.level, 100);
+
+        assert_eq!(field.get(IVec2::new(-1, 3)), None);
+        assert_eq!(field.drain(IVec2::new(-1, 3)), Err(FluidError::NotFound));
+    }
+
+    #[test]
+    fn fill_fluid_with_invalid() {
+        let mut field = make_fluid_field();
+
+        assert_eq!(field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 2, level: 100 }), Err(FluidError::InvalidId));
+
+        field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 0, level: 100 }).unwrap();
+        assert_eq!(field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 1, level: 100 }), Err(FluidError::Conflict));
+
+        field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 0, level: 50 }).unwrap();
+        assert_eq!(field.get_level(IVec2::new(-1, 3)), 50);
+    }
+
+    #[test]
+    fn spread_fluid() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(31, 31), Fluid { archetype_id: 0, level: 255 }).unwrap();
+
+        for _ in 0..64 {
+            field.step(|_| false);
+        }
+
+        assert!(field.get_level(IVec2::new(31, 31)) < 255);
+        assert!(field.get_level(IVec2::new(32, 31)) > 0);
+        assert!(field.get_level(IVec2::new(31, 32)) > 0);
+        assert_eq!(total_level(&field, 0..=63), 255);
+    }
+
+    #[test]
+    fn sleep_fluid() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(0, 0), Fluid { archetype_id: 0, level: 4 }).unwrap();
+        assert!(!field.is_sleeping(IVec2::new(0, 0)));
+
+        field.step(|_| false);
+        assert!(field.is_sleeping(IVec2::new(0, 0)));
+        assert_eq!(field.get_level(IVec2::new(0, 0)), 4);
+
+        field.fill(IVec2::new(1, 0), Fluid { archetype_id: 0, level: 255 }).unwrap();
+        assert!(!field.is_sleeping(IVec2::new(0, 0)));
+    }
+
+    #[test]
+    fn wall_fluid() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(0, 0), Fluid { archetype_id: 0, level: 255 }).unwrap();
+
+        let is_wall = |coord: IVec2| coord.x.abs() == 1 || coord.y.abs() == 1;
+        for _ in 0..64 {
+            field.step(is_wall);
+        }
+
+        assert_eq!(field.get_level(IVec2::new(0, 0)), 255);
+        assert_eq!(field.get_level(IVec2::new(1, 0)), 0);
+    }
+
+    #[test]
+    fn viscosity_fluid() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(0, 0), Fluid { archetype_id: 0, level: 255 }).unwrap();
+        field.fill(IVec2::new(100, 0), Fluid { archetype_id: 1, level: 255 }).unwrap();
+
+        field.step(|_| false);
+
+        assert!(field.get_level(IVec2::new(1, 0)) > field.get_level(IVec2::new(101, 0)));
+    }
+
+    #[test]
+    fn fluid_chunk() {
+        let mut field = make_fluid_field();
+
+        field.fill(IVec2::new(-1, 3), Fluid { archetype_id: 0, level: 100 }).unwrap();
+
+        assert!(field.get_chunk(IVec2::new(0, 0)).is_err());
+
+        let chunk = field.get_chunk(IVec2::new(-1, 0)).unwrap();
+        assert_eq!(chunk.fluids.iter().filter(|fluid| fluid.level > 0).count(), 1);
+    }
+}
@@ -1,3 +1,5 @@
+use super::ValidationReport;
+
 pub type InventoryId = u64;
 
 #[derive(Debug, Clone)]
@@ -167,6 +169,56 @@ impl ItemStorage {
         let chunk = self.chunks.get(chunk_id as usize).unwrap();
         Ok(chunk)
     }
+
+    // validation
+
+    // cross-check the id index against the chunks and every stack against its inventory limits
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        if self.chunks.len() != self.inventories.len() {
+            report.push(format!("{} chunks but {} inventories", self.chunks.len(), self.inventories.len()));
+        }
+
+        for (chunk_id, chunk) in self.chunks.iter().enumerate() {
+            let at = format!("inventory {}", chunk.id);
+
+            match self.id_index.get(chunk.id as usize) {
+                Some(other) if *other as usize == chunk_id => {}
+                Some(other) => report.push(format!("{}: stored at {} but indexed at {}", at, chunk_id, other)),
+                None => report.push(format!("{}: stored at {} but missing from the id index", at, chunk_id)),
+            }
+
+            let Some(inventory) = self.inventories.get(chunk_id) else {
+                continue;
+            };
+            if chunk.items.len() as u32 > inventory.max_variety {
+                report.push(format!("{}: {} kinds of item exceed the variety of {}", at, chunk.items.len(), inventory.max_variety));
+            }
+            for (local_id, item) in chunk.items.iter().enumerate() {
+                if self.archetypes.get(item.archetype_id as usize).is_none() {
+                    report.push(format!("{}: item {} has invalid archetype {}", at, local_id, item.archetype_id));
+                }
+                if item.amount == 0 || item.amount > inventory.max_stack {
+                    report.push(format!("{}: item {} has amount {} outside 1..={}", at, local_id, item.amount, inventory.max_stack));
+                }
+                if chunk.items[..local_id].iter().any(|other| other.archetype_id == item.archetype_id) {
+                    report.push(format!("{}: item {} repeats archetype {}", at, local_id, item.archetype_id));
+                }
+            }
+        }
+
+        // ids that point at nothing
+        for (id, chunk_id) in &self.id_index {
+            match self.chunks.get(*chunk_id as usize) {
+                Some(chunk) if chunk.id == id as InventoryId => {}
+                Some(chunk) => report.push(format!("inventory {}: indexed at {} which holds inventory {}", id, chunk_id, chunk.id)),
+                None => report.push(format!("inventory {}: indexed at {} which is empty", id, chunk_id)),
+            }
+        }
+
+        report
+    }
 }
 
 // error handling
@@ -197,3 +249,34 @@ impl std::fmt::Display for ItemError {
 }
 
 impl std::error::Error for ItemError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_item() {
+        let mut storage = ItemStorage::new(ItemStorageInfo {
+            items: vec![ItemInfo {
+                display_name: "item_0".into(),
+                description: "item_0_desc".into(),
+            }],
+        });
+
+        let inventory = Inventory { max_variety: 1, max_stack: 10 };
+        let ids = (0..3).map(|_| storage.insert_inventory(inventory.clone()).unwrap()).collect::<Vec<_>>();
+        storage.insert(ids[2], Item { amount: 4, archetype_id: 0 }).unwrap();
+        storage.remove_inventory(ids[0]).unwrap();
+        assert!(storage.validate().is_valid());
+
+        storage.chunks[0].items.push(Item { amount: 0, archetype_id: 1 });
+        storage.id_index[ids[1] as usize] = 5;
+        assert_eq!(storage.validate().issues, [
+            "inventory 2: 2 kinds of item exceed the variety of 1",
+            "inventory 2: item 1 has invalid archetype 1",
+            "inventory 2: item 1 has amount 0 outside 1..=10",
+            "inventory 1: stored at 1 but indexed at 5",
+            "inventory 1: indexed at 5 which is empty",
+        ]);
+    }
+}
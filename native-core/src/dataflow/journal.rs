@@ -0,0 +1,661 @@
+use glam::*;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ObjectKind {
+    Tile,
+    Block,
+    Entity,
+}
+
+// objects are referred to by journal ids, which survive the new slab id given on re-insertion
+#[derive(Debug, Clone)]
+enum Change {
+    InsertTile(u64, Tile),
+    RemoveTile(u64, Tile),
+    MoveTile(u64, IVec2, IVec2),
+    ModifyTile(u64, (u16, u32), (u16, u32)),
+    InsertBlock(u64, Block),
+    RemoveBlock(u64, Block),
+    MoveBlock(u64, IVec2, IVec2),
+    ModifyBlock(u64, (u16, u32), (u16, u32)),
+    InsertEntity(u64, Entity),
+    RemoveEntity(u64, Entity),
+    MoveEntity(u64, Vec2, Vec2),
+    ModifyEntity(u64, (u16, u32), (u16, u32)),
+}
+
+impl Change {
+    fn inverse(&self) -> Change {
+        match self {
+            Change::InsertTile(journal_id, tile) => Change::RemoveTile(*journal_id, tile.clone()),
+            Change::RemoveTile(journal_id, tile) => Change::InsertTile(*journal_id, tile.clone()),
+            Change::MoveTile(journal_id, from, to) => Change::MoveTile(*journal_id, *to, *from),
+            Change::ModifyTile(journal_id, from, to) => Change::ModifyTile(*journal_id, *to, *from),
+            Change::InsertBlock(journal_id, block) => Change::RemoveBlock(*journal_id, block.clone()),
+            Change::RemoveBlock(journal_id, block) => Change::InsertBlock(*journal_id, block.clone()),
+            Change::MoveBlock(journal_id, from, to) => Change::MoveBlock(*journal_id, *to, *from),
+            Change::ModifyBlock(journal_id, from, to) => Change::ModifyBlock(*journal_id, *to, *from),
+            Change::InsertEntity(journal_id, entity) => Change::RemoveEntity(*journal_id, entity.clone()),
+            Change::RemoveEntity(journal_id, entity) => Change::InsertEntity(*journal_id, entity.clone()),
+            Change::MoveEntity(journal_id, from, to) => Change::MoveEntity(*journal_id, *to, *from),
+            Change::ModifyEntity(journal_id, from, to) => Change::ModifyEntity(*journal_id, *to, *from),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Transaction {
+    name: String,
+    changes: Vec<Change>,
+}
+
+#[derive(Debug, Default)]
+pub struct Journal {
+    next_id: u64,
+    journal_ids: ahash::AHashMap<(ObjectKind, u64), u64>,
+    current_ids: ahash::AHashMap<(ObjectKind, u64), u64>,
+    depth: u32,
+    suspended: bool,
+    pending: Option<Transaction>,
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+}
+
+impl Journal {
+    pub const MAX_HISTORY: usize = 256;
+
+    fn journal_id(&mut self, kind: ObjectKind, id: u64) -> u64 {
+        if let Some(journal_id) = self.journal_ids.get(&(kind, id)) {
+            return *journal_id;
+        }
+
+        let journal_id = self.next_id;
+        self.next_id += 1;
+        self.bind(kind, journal_id, id);
+        journal_id
+    }
+
+    fn current_id(&self, kind: ObjectKind, journal_id: u64) -> Result<u64, DataflowError> {
+        self.current_ids.get(&(kind, journal_id)).copied().ok_or(match kind {
+            ObjectKind::Tile => DataflowError::TileError(TileError::NotFound),
+            ObjectKind::Block => DataflowError::BlockError(BlockError::NotFound),
+            ObjectKind::Entity => DataflowError::EntityError(EntityError::NotFound),
+        })
+    }
+
+    fn bind(&mut self, kind: ObjectKind, journal_id: u64, id: u64) {
+        self.journal_ids.insert((kind, id), journal_id);
+        self.current_ids.insert((kind, journal_id), id);
+    }
+
+    fn unbind(&mut self, kind: ObjectKind, id: u64) {
+        if let Some(journal_id) = self.journal_ids.remove(&(kind, id)) {
+            self.current_ids.remove(&(kind, journal_id));
+        }
+    }
+
+    fn push(&mut self, name: &str, change: Change) {
+        match &mut self.pending {
+            Some(transaction) => transaction.changes.push(change),
+            None => self.commit(Transaction { name: name.to_string(), changes: vec![change] }),
+        }
+    }
+
+    fn commit(&mut self, transaction: Transaction) {
+        if transaction.changes.is_empty() {
+            return;
+        }
+
+        self.undo_stack.push(transaction);
+        if self.undo_stack.len() > Self::MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    #[inline]
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo_stack.last().map(|transaction| transaction.name.as_str())
+    }
+
+    #[inline]
+    pub fn redo_name(&self) -> Option<&str> {
+        self.redo_stack.last().map(|transaction| transaction.name.as_str())
+    }
+}
+
+impl Dataflow {
+    // journal
+
+    // start recording, calls outside of a transaction become transactions of their own
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Default::default());
+        }
+    }
+
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    // changes made while suspended are not undoable and their objects are not tracked,
+    // undoing a recorded change on an object that was removed meanwhile fails
+    pub fn suspend_journal(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.suspended = true;
+        }
+    }
+
+    pub fn resume_journal(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.suspended = false;
+        }
+    }
+
+    // runs f with the journal suspended and puts the previous state back, so calls may nest
+    pub fn without_journal<T>(&mut self, f: impl FnOnce(&mut Dataflow) -> T) -> T {
+        let suspended = self.set_journal_suspended(true);
+        let result = f(self);
+        self.set_journal_suspended(suspended);
+        result
+    }
+
+    #[inline]
+    pub fn get_journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    // transactions nest, only the outermost name is kept
+    pub fn begin_transaction(&mut self, name: &str) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+
+        if journal.depth == 0 {
+            journal.pending = Some(Transaction { name: name.to_string(), changes: vec![] });
+        }
+        journal.depth += 1;
+    }
+
+    pub fn commit_transaction(&mut self) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+
+        journal.depth = journal.depth.saturating_sub(1);
+        if journal.depth == 0
+            && let Some(transaction) = journal.pending.take()
+        {
+            journal.commit(transaction);
+        }
+    }
+
+    // revert the last transaction and return its name,
+    // a failed step puts the world and the transaction back as they were
+    pub fn undo(&mut self) -> Result<Option<String>, DataflowError> {
+        self.replay(true)
+    }
+
+    // re-apply the last undone transaction and return its name,
+    // a failed step puts the world and the transaction back as they were
+    pub fn redo(&mut self) -> Result<Option<String>, DataflowError> {
+        self.replay(false)
+    }
+
+    // side effects of event handlers were recorded as changes of their own,
+    // so they are silenced while replaying to avoid applying them twice
+    fn replay(&mut self, undo: bool) -> Result<Option<String>, DataflowError> {
+        let Some(mut journal) = self.journal.take() else {
+            return Ok(None);
+        };
+        let transaction = if undo { journal.undo_stack.pop() } else { journal.redo_stack.pop() };
+        let Some(transaction) = transaction else {
+            self.journal = Some(journal);
+            return Ok(None);
+        };
+
+        let silent_handlers = EventHandlers {
+            tiles: self.event_handlers.tiles.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+            blocks: self.event_handlers.blocks.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+            entities: self.event_handlers.entities.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+        };
+        let event_handlers = std::mem::replace(&mut self.event_handlers, silent_handlers);
+
+        let steps = if undo {
+            transaction.changes.iter().rev().map(Change::inverse).collect::<Vec<_>>()
+        } else {
+            transaction.changes.clone()
+        };
+
+        let mut result = Ok(());
+        for (i, step) in steps.iter().enumerate() {
+            result = self.apply_change(&mut journal, step);
+            if result.is_err() {
+                // the steps that were just applied revert cleanly, nothing else ran in between
+                for step in steps[..i].iter().rev() {
+                    let _ = self.apply_change(&mut journal, &step.inverse());
+                }
+                break;
+            }
+        }
+
+        self.event_handlers = event_handlers;
+        let name = transaction.name.clone();
+        match (undo, result.is_ok()) {
+            (true, true) | (false, false) => journal.redo_stack.push(transaction),
+            (false, true) | (true, false) => journal.undo_stack.push(transaction),
+        }
+        self.journal = Some(journal);
+        result.map(|_| Some(name))
+    }
+
+    // batch support, positions refer to the changes of the pending transaction
+
+    pub(super) fn set_journal_suspended(&mut self, suspended: bool) -> bool {
+        match &mut self.journal {
+            Some(journal) => std::mem::replace(&mut journal.suspended, suspended),
+            None => false,
+        }
+    }
+
+    pub(super) fn get_journal_checkpoint(&self) -> usize {
+        self.journal
+            .as_ref()
+            .and_then(|journal| journal.pending.as_ref())
+            .map(|transaction| transaction.changes.len())
+            .unwrap_or_default()
+    }
+
+    // revert the changes recorded after the checkpoint and forget them,
+    // a change that unrecorded edits made impossible to revert is skipped and the first such error returned
+    pub(super) fn rollback_journal(&mut self, checkpoint: usize) -> Result<(), DataflowError> {
+        let Some(mut journal) = self.journal.take() else {
+            return Ok(());
+        };
+        let changes = match &mut journal.pending {
+            Some(transaction) => transaction.changes.split_off(checkpoint),
+            None => vec![],
+        };
+
+        let mut result = Ok(());
+        for change in changes.iter().rev() {
+            if let Err(e) = self.revert_change(&mut journal, change) {
+                result = result.and(Err(e));
+            }
+        }
+        self.journal = Some(journal);
+        result
+    }
+
+    // forget the changes recorded after the checkpoint without reverting them
+    pub(super) fn discard_journal(&mut self, checkpoint: usize) {
+        if let Some(journal) = &mut self.journal
+            && let Some(transaction) = &mut journal.pending
+        {
+            transaction.changes.truncate(checkpoint);
+        }
+    }
+
+    fn apply_change(&mut self, journal: &mut Journal, change: &Change) -> Result<(), DataflowError> {
+        match change {
+            Change::InsertTile(journal_id, tile) => {
+                let id = self.insert_tile(tile.clone())?;
+                journal.bind(ObjectKind::Tile, *journal_id, id);
+            }
+            Change::RemoveTile(journal_id, _) => {
+                let id = journal.current_id(ObjectKind::Tile, *journal_id)?;
+                self.remove_til(id)?;
+                journal.unbind(ObjectKind::Tile, id);
+            }
+            Change::MoveTile(journal_id, _, coord) => {
+                let id = journal.current_id(ObjectKind::Tile, *journal_id)?;
+                self.move_tile(id, *coord)?;
+            }
+            Change::ModifyTile(journal_id, _, (variant, tick)) => {
+                let id = journal.current_id(ObjectKind::Tile, *journal_id)?;
+                self.modify_tile_variant(id, *variant)?;
+                self.modify_tile_tick(id, *tick)?;
+            }
+            Change::InsertBlock(journal_id, block) => {
+                let id = self.insert_block(block.clone())?;
+                journal.bind(ObjectKind::Block, *journal_id, id);
+            }
+            Change::RemoveBlock(journal_id, _) => {
+                let id = journal.current_id(ObjectKind::Block, *journal_id)?;
+                self.remove_block(id)?;
+                journal.unbind(ObjectKind::Block, id);
+            }
+            Change::MoveBlock(journal_id, _, coord) => {
+                let id = journal.current_id(ObjectKind::Block, *journal_id)?;
+                self.move_block(id, *coord)?;
+            }
+            Change::ModifyBlock(journal_id, _, (variant, tick)) => {
+                let id = journal.current_id(ObjectKind::Block, *journal_id)?;
+                self.modify_block_variant(id, *variant)?;
+                self.modify_block_tick(id, *tick)?;
+            }
+            Change::InsertEntity(journal_id, entity) => {
+                let id = self.insert_entity(entity.clone())?;
+                journal.bind(ObjectKind::Entity, *journal_id, id);
+            }
+            Change::RemoveEntity(journal_id, _) => {
+                let id = journal.current_id(ObjectKind::Entity, *journal_id)?;
+                self.remove_entity(id)?;
+                journal.unbind(ObjectKind::Entity, id);
+            }
+            Change::MoveEntity(journal_id, _, coord) => {
+                let id = journal.current_id(ObjectKind::Entity, *journal_id)?;
+                self.move_entity(id, *coord)?;
+            }
+            Change::ModifyEntity(journal_id, _, (variant, tick)) => {
+                let id = journal.current_id(ObjectKind::Entity, *journal_id)?;
+                self.modify_entity_variant(id, *variant)?;
+                self.modify_entity_tick(id, *tick)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn revert_change(&mut self, journal: &mut Journal, change: &Change) -> Result<(), DataflowError> {
+        self.apply_change(journal, &change.inverse())
+    }
+
+    // recording, called by the mutation methods after they succeeded,
+    // nothing is looked up or bound while the journal is suspended
+
+    pub(super) fn record_insert_tile(&mut self, id: TileId) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+
+        if journal.suspended {
+            // a reused id no longer refers to the recorded object
+            journal.unbind(ObjectKind::Tile, id);
+            return;
+        }
+        let tile = self.tile_field.get(id).unwrap().clone();
+        let journal_id = journal.journal_id(ObjectKind::Tile, id);
+        journal.push("insert_tile", Change::InsertTile(journal_id, tile));
+    }
+
+    pub(super) fn record_remove_tile(&mut self, id: TileId, tile: &Tile) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Tile, id);
+            journal.push("remove_tile", Change::RemoveTile(journal_id, tile.clone()));
+            journal.unbind(ObjectKind::Tile, id);
+        }
+    }
+
+    pub(super) fn record_move_tile(&mut self, id: TileId, from: IVec2, to: IVec2) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Tile, id);
+            journal.push("move_tile", Change::MoveTile(journal_id, from, to));
+        }
+    }
+
+    pub(super) fn record_modify_tile(&mut self, id: TileId, from: (u16, u32)) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let tile = self.tile_field.get(id).unwrap();
+            let journal_id = journal.journal_id(ObjectKind::Tile, id);
+            journal.push("modify_tile", Change::ModifyTile(journal_id, from, (tile.variant, tile.tick)));
+        }
+    }
+
+    pub(super) fn record_insert_block(&mut self, id: BlockId) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+
+        if journal.suspended {
+            // a reused id no longer refers to the recorded object
+            journal.unbind(ObjectKind::Block, id);
+            return;
+        }
+        let block = self.block_field.get(id).unwrap().clone();
+        let journal_id = journal.journal_id(ObjectKind::Block, id);
+        journal.push("insert_block", Change::InsertBlock(journal_id, block));
+    }
+
+    pub(super) fn record_remove_block(&mut self, id: BlockId, block: &Block) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Block, id);
+            journal.push("remove_block", Change::RemoveBlock(journal_id, block.clone()));
+            journal.unbind(ObjectKind::Block, id);
+        }
+    }
+
+    pub(super) fn record_move_block(&mut self, id: BlockId, from: IVec2, to: IVec2) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Block, id);
+            journal.push("move_block", Change::MoveBlock(journal_id, from, to));
+        }
+    }
+
+    pub(super) fn record_modify_block(&mut self, id: BlockId, from: (u16, u32)) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let block = self.block_field.get(id).unwrap();
+            let journal_id = journal.journal_id(ObjectKind::Block, id);
+            journal.push("modify_block", Change::ModifyBlock(journal_id, from, (block.variant, block.tick)));
+        }
+    }
+
+    pub(super) fn record_insert_entity(&mut self, id: EntityId) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+
+        if journal.suspended {
+            // a reused id no longer refers to the recorded object
+            journal.unbind(ObjectKind::Entity, id);
+            return;
+        }
+        let entity = self.entity_field.get(id).unwrap().clone();
+        let journal_id = journal.journal_id(ObjectKind::Entity, id);
+        journal.push("insert_entity", Change::InsertEntity(journal_id, entity));
+    }
+
+    pub(super) fn record_remove_entity(&mut self, id: EntityId, entity: &Entity) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Entity, id);
+            journal.push("remove_entity", Change::RemoveEntity(journal_id, entity.clone()));
+            journal.unbind(ObjectKind::Entity, id);
+        }
+    }
+
+    pub(super) fn record_move_entity(&mut self, id: EntityId, from: Vec2, to: Vec2) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let journal_id = journal.journal_id(ObjectKind::Entity, id);
+            journal.push("move_entity", Change::MoveEntity(journal_id, from, to));
+        }
+    }
+
+    pub(super) fn record_modify_entity(&mut self, id: EntityId, from: (u16, u32)) {
+        if let Some(journal) = &mut self.journal
+            && !journal.suspended
+        {
+            let entity = self.entity_field.get(id).unwrap();
+            let journal_id = journal.journal_id(ObjectKind::Entity, id);
+            journal.push("modify_entity", Change::ModifyEntity(journal_id, from, (entity.variant, entity.tick)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dataflow() -> Dataflow {
+        TestDataflowBuilder::new().tile(false).block(IVec2::ONE).entity().build()
+    }
+
+    #[test]
+    fn undo_redo_transaction() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        dataflow.begin_transaction("build");
+        let tile_id = dataflow.insert_tile(Tile { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        let block_id = dataflow.insert_block(Block { coord: IVec2::new(1, 0), ..Default::default() }).unwrap();
+        dataflow.move_block(block_id, IVec2::new(64, 0)).unwrap();
+        dataflow.modify_tile_variant(tile_id, 3).unwrap();
+        dataflow.commit_transaction();
+        assert_eq!(dataflow.get_journal().unwrap().undo_name(), Some("build"));
+
+        assert_eq!(dataflow.undo().unwrap(), Some("build".to_string()));
+        assert!(dataflow.find_tile_with_point(IVec2::new(0, 0)).is_none());
+        assert!(dataflow.find_block_with_point(IVec2::new(64, 0)).is_none());
+        assert_eq!(dataflow.undo().unwrap(), None);
+
+        assert_eq!(dataflow.redo().unwrap(), Some("build".to_string()));
+        let (tile_id, _) = dataflow.find_tile_with_point(IVec2::new(0, 0)).unwrap();
+        assert_eq!(dataflow.get_tile(*tile_id).unwrap().variant, 3);
+        assert!(dataflow.find_block_with_point(IVec2::new(64, 0)).is_some());
+        assert_eq!(dataflow.redo().unwrap(), None);
+    }
+
+    #[test]
+    fn undo_with_remapped_id() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        let entity_id = dataflow.insert_entity(Entity { coord: Vec2::new(0.0, 0.0), ..Default::default() }).unwrap();
+        dataflow.move_entity(entity_id, Vec2::new(64.0, 0.0)).unwrap();
+        dataflow.remove_entity(entity_id).unwrap();
+
+        // occupy the freed slot without recording so that re-insertion gets another id
+        let journal = dataflow.journal.take();
+        let other_id = dataflow.insert_entity(Entity { coord: Vec2::new(128.0, 0.0), ..Default::default() }).unwrap();
+        assert_eq!(other_id, entity_id);
+        dataflow.journal = journal;
+
+        assert_eq!(dataflow.undo().unwrap(), Some("remove_entity".to_string()));
+        let (new_id, _) = dataflow.find_entity_with_hint_point(Vec2::new(64.5, 0.5)).next().unwrap();
+        let new_id = *new_id;
+        assert_ne!(new_id, entity_id);
+
+        assert_eq!(dataflow.undo().unwrap(), Some("move_entity".to_string()));
+        assert_eq!(dataflow.get_entity(new_id).unwrap().coord, Vec2::new(0.0, 0.0));
+        assert_eq!(dataflow.undo().unwrap(), Some("insert_entity".to_string()));
+        assert!(dataflow.get_entity(new_id).is_err());
+        assert_eq!(dataflow.get_entity(other_id).unwrap().coord, Vec2::new(128.0, 0.0));
+
+        for _ in 0..3 {
+            dataflow.redo().unwrap();
+        }
+        assert_eq!(dataflow.find_entity_with_hint_point(Vec2::new(64.5, 0.5)).count(), 0);
+        assert_eq!(dataflow.find_entity_with_hint_point(Vec2::new(128.5, 0.5)).count(), 1);
+    }
+
+    #[test]
+    fn commit_clears_redo() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        dataflow.insert_tile(Tile { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        dataflow.undo().unwrap();
+        assert_eq!(dataflow.get_journal().unwrap().redo_name(), Some("insert_tile"));
+
+        dataflow.insert_tile(Tile { coord: IVec2::new(1, 0), ..Default::default() }).unwrap();
+        assert_eq!(dataflow.get_journal().unwrap().redo_name(), None);
+
+        dataflow.suspend_journal();
+        dataflow.insert_tile(Tile { coord: IVec2::new(2, 0), ..Default::default() }).unwrap();
+        dataflow.resume_journal();
+        dataflow.undo().unwrap();
+        dataflow.undo().unwrap();
+        assert!(dataflow.find_tile_with_point(IVec2::new(1, 0)).is_none());
+        assert!(dataflow.find_tile_with_point(IVec2::new(2, 0)).is_some());
+    }
+
+    #[test]
+    fn change_without_journal() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        dataflow.insert_tile(Tile { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        dataflow.without_journal(|dataflow| {
+            dataflow.insert_tile(Tile { coord: IVec2::new(1, 0), ..Default::default() }).unwrap();
+            dataflow.without_journal(|dataflow| dataflow.insert_tile(Tile { coord: IVec2::new(2, 0), ..Default::default() }).unwrap());
+            // still suspended after the nested call
+            dataflow.insert_tile(Tile { coord: IVec2::new(3, 0), ..Default::default() }).unwrap();
+        });
+        // only the recorded tile is tracked
+        assert_eq!(dataflow.get_journal().unwrap().journal_ids.len(), 1);
+
+        assert_eq!(dataflow.undo().unwrap(), Some("insert_tile".to_string()));
+        assert_eq!(dataflow.undo().unwrap(), None);
+        assert!(dataflow.find_tile_with_point(IVec2::new(0, 0)).is_none());
+        for x in 1..4 {
+            assert!(dataflow.find_tile_with_point(IVec2::new(x, 0)).is_some());
+        }
+
+        // recording goes on afterwards
+        dataflow.insert_tile(Tile { coord: IVec2::new(4, 0), ..Default::default() }).unwrap();
+        assert_eq!(dataflow.get_journal().unwrap().undo_name(), Some("insert_tile"));
+    }
+
+    #[test]
+    fn undo_with_conflict() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        let rock_id = dataflow.insert_block(Block { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        dataflow.begin_transaction("move_rock");
+        dataflow.remove_block(rock_id).unwrap();
+        dataflow.insert_block(Block { coord: IVec2::new(5, 0), ..Default::default() }).unwrap();
+        dataflow.commit_transaction();
+
+        // something that is not undoable takes the old place of the rock
+        let stone_id = dataflow.without_journal(|dataflow| dataflow.insert_block(Block { coord: IVec2::new(0, 0), variant: 1, ..Default::default() }).unwrap());
+
+        // the rock at the new place was already removed when the old place turned out to be taken
+        assert_eq!(dataflow.undo(), Err(DataflowError::BlockError(BlockError::Conflict)));
+        assert!(dataflow.find_block_with_point(IVec2::new(5, 0)).is_some());
+        assert_eq!(dataflow.get_block(stone_id).unwrap().variant, 1);
+        assert_eq!(dataflow.get_journal().unwrap().undo_name(), Some("move_rock"));
+        assert_eq!(dataflow.get_journal().unwrap().redo_name(), None);
+
+        dataflow.without_journal(|dataflow| dataflow.remove_block(stone_id).unwrap());
+        assert_eq!(dataflow.undo().unwrap(), Some("move_rock".to_string()));
+        assert!(dataflow.find_block_with_point(IVec2::new(5, 0)).is_none());
+        assert!(dataflow.find_block_with_point(IVec2::new(0, 0)).is_some());
+
+        // a failed redo is put back as well
+        dataflow.without_journal(|dataflow| dataflow.insert_block(Block { coord: IVec2::new(5, 0), ..Default::default() }).unwrap());
+        assert_eq!(dataflow.redo(), Err(DataflowError::BlockError(BlockError::Conflict)));
+        assert!(dataflow.find_block_with_point(IVec2::new(0, 0)).is_some());
+        assert_eq!(dataflow.get_journal().unwrap().redo_name(), Some("move_rock"));
+    }
+
+    #[test]
+    fn undo_edit_as_one_transaction() {
+        let mut dataflow = make_dataflow();
+        dataflow.enable_journal();
+
+        let brush = Brush::Rect(IRect2::new(IVec2::new(0, 0), IVec2::new(3, 3)));
+        let summary = dataflow.paint_tiles(0, &brush, ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary.inserted, 16);
+
+        assert_eq!(dataflow.undo().unwrap(), Some("paint_tiles".to_string()));
+        assert_eq!(dataflow.find_tile_with_rect(IRect2::new(IVec2::new(0, 0), IVec2::new(3, 3))).count(), 0);
+        assert_eq!(dataflow.get_journal().unwrap().undo_name(), None);
+    }
+}
@@ -0,0 +1,369 @@
+use glam::*;
+
+use crate::geom::*;
+
+use super::{BlockField, EntityField};
+
+#[inline]
+fn encode_coord(coord: IVec2) -> u64 {
+    (coord.x as u32 as u64) << 32 | coord.y as u32 as u64
+}
+
+#[inline]
+fn decode_coord(coord: u64) -> IVec2 {
+    IVec2::new((coord >> 32) as u32 as i32, coord as u32 as i32)
+}
+
+#[derive(Debug, Clone)]
+pub struct LightFieldInfo {
+    pub ambient: u8,
+}
+
+#[derive(Debug)]
+pub struct LightChunk {
+    pub version: u64,
+    pub levels: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct LightField {
+    ambient: u8,
+    chunks: Vec<LightChunk>,
+    coord_index: ahash::AHashMap<u64, u32>,
+    dirty_chunks: ahash::AHashSet<IVec2>,
+}
+
+impl LightField {
+    const CHUNK_SIZE: u32 = 32;
+
+    pub const MAX_LEVEL: u8 = 15;
+
+    pub fn new(info: LightFieldInfo) -> Self {
+        Self {
+            ambient: info.ambient.min(Self::MAX_LEVEL),
+            chunks: Default::default(),
+            coord_index: Default::default(),
+            dirty_chunks: Default::default(),
+        }
+    }
+
+    #[inline]
+    fn alloc_chunk(&mut self, chunk_coord: IVec2) -> u32 {
+        let chunk_coord_ = encode_coord(chunk_coord);
+
+        if let Some(chunk_id) = self.coord_index.get(&chunk_coord_) {
+            *chunk_id
+        } else {
+            assert!(self.chunks.len() <= u32::MAX as usize, "capacity overflow");
+            let chunk_id = self.chunks.len() as u32;
+            self.chunks.push(LightChunk {
+                version: Default::default(),
+                levels: vec![0; (Self::CHUNK_SIZE * Self::CHUNK_SIZE) as usize],
+            });
+            self.coord_index.insert(chunk_coord_, chunk_id);
+            chunk_id
+        }
+    }
+
+    // ambient
+
+    #[inline]
+    pub fn get_ambient(&self) -> u8 {
+        self.ambient
+    }
+
+    #[inline]
+    pub fn set_ambient(&mut self, ambient: u8) {
+        self.ambient = ambient.min(Self::MAX_LEVEL);
+    }
+
+    // propagation
+
+    // mark every chunk that light emitted or blocked inside the rect can reach
+    pub fn invalidate(&mut self, rect: IRect2) {
+        let reach = Self::MAX_LEVEL as i32;
+        let min = Self::find_chunk_coord_internal(rect.min - reach);
+        let max = Self::find_chunk_coord_internal(rect.max + reach);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.dirty_chunks.insert(IVec2::new(x, y));
+            }
+        }
+    }
+
+    // mark every computed chunk, used when emission or opacity of archetypes changed
+    pub fn invalidate_all(&mut self) {
+        for chunk_coord in self.coord_index.keys() {
+            self.dirty_chunks.insert(decode_coord(*chunk_coord));
+        }
+    }
+
+    #[inline]
+    pub fn is_dirty(&self, chunk_coord: IVec2) -> bool {
+        self.dirty_chunks.contains(&chunk_coord)
+    }
+
+    // recompute the light map of every dirty chunk by flood-fill,
+    // sources within reach of the chunk are gathered so that light crosses chunk boundaries
+    pub fn update(&mut self, block_field: &BlockField, entity_field: &EntityField) {
+        let dirty_chunks = std::mem::take(&mut self.dirty_chunks);
+
+        for chunk_coord in dirty_chunks {
+            let reach = Self::MAX_LEVEL as i32;
+            let origin = chunk_coord * Self::CHUNK_SIZE as i32;
+            let region = IRect2::new(origin - reach, origin + Self::CHUNK_SIZE as i32 - 1 + reach);
+            let width = region.size().x + 1;
+            let height = region.size().y + 1;
+            let index = |coord: IVec2| ((coord.x - region.min.x) + (coord.y - region.min.y) * width) as usize;
+
+            let mut levels = vec![0u8; (width * height) as usize];
+            let mut opacities = vec![0u8; (width * height) as usize];
+            let mut sources = vec![];
+
+            let mut register = |rect: IRect2, emission: u8, opacity: u8| {
+                let min = rect.min.max(region.min);
+                let max = rect.max.min(region.max);
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        let coord = IVec2::new(x, y);
+                        let i = index(coord);
+                        opacities[i] = opacities[i].max(opacity);
+                        if emission > levels[i] {
+                            levels[i] = emission;
+                            sources.push(coord);
+                        }
+                    }
+                }
+            };
+
+            for (id, _) in block_field.find_with_rect(region) {
+                let block = block_field.get(*id).unwrap();
+                let archetype = block_field.get_archetype(block.archetype_id).unwrap();
+                if archetype.light_emission == 0 && archetype.light_opacity == 0 {
+                    continue;
+                }
+                register(archetype.rect(block.coord), archetype.light_emission.min(Self::MAX_LEVEL), archetype.light_opacity);
+            }
+
+            let min = entity_field.find_chunk_coord(region.min.as_vec2());
+            let max = entity_field.find_chunk_coord(region.max.as_vec2());
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let Ok(chunk) = entity_field.get_chunk(IVec2::new(x, y)) else {
+                        continue;
+                    };
+
+                    for entity in &chunk.entities {
+                        let archetype = entity_field.get_archetype(entity.archetype_id).unwrap();
+                        if archetype.light_emission == 0 && archetype.light_opacity == 0 {
+                            continue;
+                        }
+                        let coord = entity.coord.floor().as_ivec2();
+                        register(IRect2::new(coord, coord), archetype.light_emission.min(Self::MAX_LEVEL), archetype.light_opacity);
+                    }
+                }
+            }
+
+            // breadth-first flood-fill, each step loses one level plus the opacity of the cell entered
+            let mut queue = std::collections::VecDeque::from(sources);
+            while let Some(coord) = queue.pop_front() {
+                let level = levels[index(coord)];
+
+                for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                    let neighbor_coord = coord + offset;
+                    if !Intersects::intersects(&neighbor_coord, &region) {
+                        continue;
+                    }
+
+                    let i = index(neighbor_coord);
+                    let new_level = level.saturating_sub(1).saturating_sub(opacities[i]);
+                    if new_level > levels[i] {
+                        levels[i] = new_level;
+                        queue.push_back(neighbor_coord);
+                    }
+                }
+            }
+
+            let chunk_id = self.alloc_chunk(chunk_coord);
+            let chunk = self.chunks.get_mut(chunk_id as usize).unwrap();
+            for v in 0..Self::CHUNK_SIZE as i32 {
+                for u in 0..Self::CHUNK_SIZE as i32 {
+                    let local_id = (u + v * Self::CHUNK_SIZE as i32) as usize;
+                    chunk.levels[local_id] = levels[index(origin + IVec2::new(u, v))];
+                }
+            }
+            chunk.version += 1;
+        }
+    }
+
+    #[inline]
+    pub fn get_level(&self, coord: IVec2) -> u8 {
+        let chunk_coord = Self::find_chunk_coord_internal(coord);
+        let Some(chunk_id) = self.coord_index.get(&encode_coord(chunk_coord)) else {
+            return 0;
+        };
+
+        let chunk = self.chunks.get(*chunk_id as usize).unwrap();
+        let local = coord.rem_euclid(IVec2::splat(Self::CHUNK_SIZE as i32));
+        chunk.levels[(local.x + local.y * Self::CHUNK_SIZE as i32) as usize]
+    }
+
+    // transfer chunk data
+
+    #[inline]
+    pub fn find_chunk_coord(&self, coord: Vec2) -> IVec2 {
+        coord.div_euclid(Vec2::splat(Self::CHUNK_SIZE as f32)).as_ivec2()
+    }
+
+    #[inline]
+    fn find_chunk_coord_internal(coord: IVec2) -> IVec2 {
+        coord.div_euclid(IVec2::splat(Self::CHUNK_SIZE as i32))
+    }
+
+    #[inline]
+    pub fn get_chunk(&self, chunk_coord: IVec2) -> Result<&LightChunk, LightError> {
+        let chunk_coord_ = encode_coord(chunk_coord);
+        let chunk_id = *self.coord_index.get(&chunk_coord_).ok_or(LightError::NotFound)?;
+        let chunk = self.chunks.get(chunk_id as usize).unwrap();
+        Ok(chunk)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightError {
+    NotFound,
+}
+
+impl std::fmt::Display for LightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found error"),
+        }
+    }
+}
+
+impl std::error::Error for LightError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataflow::*;
+
+    fn make_block_field() -> BlockField {
+        BlockField::new(BlockFieldInfo {
+            blocks: vec![
+                BlockInfo {
+                    display_name: "torch".into(),
+                    description: "torch_desc".into(),
+                    size: IVec2::new(1, 1),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: false,
+                    light_emission: 15,
+                    light_opacity: 0,
+                },
+                BlockInfo {
+                    display_name: "wall".into(),
+                    description: "wall_desc".into(),
+                    size: IVec2::new(1, 1),
+                    collision_rect: Some(Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))),
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 15,
+                },
+            ],
+        })
+    }
+
+    fn make_entity_field() -> EntityField {
+        EntityField::new(EntityFieldInfo {
+            entities: vec![EntityInfo {
+                display_name: "lantern".into(),
+                description: "lantern_desc".into(),
+                collision_rect: None,
+                hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                y_sorting: false,
+                light_emission: 8,
+                light_opacity: 0,
+            }],
+        })
+    }
+
+    #[test]
+    fn propagate_light() {
+        let mut block_field = make_block_field();
+        let entity_field = make_entity_field();
+        let mut field = LightField::new(LightFieldInfo { ambient: 0 });
+
+        block_field.insert(Block { archetype_id: 0, coord: IVec2::new(31, 0), ..Default::default() }).unwrap();
+        field.invalidate(IRect2::new(IVec2::new(31, 0), IVec2::new(31, 0)));
+        assert!(field.is_dirty(IVec2::new(0, 0)));
+        assert!(field.is_dirty(IVec2::new(1, 0)));
+
+        field.update(&block_field, &entity_field);
+        assert!(!field.is_dirty(IVec2::new(0, 0)));
+
+        assert_eq!(field.get_level(IVec2::new(31, 0)), 15);
+        assert_eq!(field.get_level(IVec2::new(30, 0)), 14);
+        assert_eq!(field.get_level(IVec2::new(33, 1)), 12);
+        assert_eq!(field.get_level(IVec2::new(46, 0)), 0);
+        assert_eq!(field.get_level(IVec2::new(31, -3)), 12);
+    }
+
+    #[test]
+    fn opacity_light() {
+        let mut block_field = make_block_field();
+        let entity_field = make_entity_field();
+        let mut field = LightField::new(LightFieldInfo { ambient: 0 });
+
+        block_field.insert(Block { archetype_id: 0, coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        for y in -16..=16 {
+            block_field.insert(Block { archetype_id: 1, coord: IVec2::new(1, y), ..Default::default() }).unwrap();
+        }
+        field.invalidate(IRect2::new(IVec2::new(0, -16), IVec2::new(1, 16)));
+        field.update(&block_field, &entity_field);
+
+        assert_eq!(field.get_level(IVec2::new(-1, 0)), 14);
+        assert_eq!(field.get_level(IVec2::new(1, 0)), 0);
+        assert_eq!(field.get_level(IVec2::new(2, 0)), 0);
+    }
+
+    #[test]
+    fn entity_light() {
+        let block_field = make_block_field();
+        let mut entity_field = make_entity_field();
+        let mut field = LightField::new(LightFieldInfo { ambient: 4 });
+
+        let id = entity_field.insert(Entity { archetype_id: 0, coord: Vec2::new(-0.5, -0.5), ..Default::default() }).unwrap();
+        field.invalidate(IRect2::new(IVec2::new(-1, -1), IVec2::new(-1, -1)));
+        field.update(&block_field, &entity_field);
+
+        assert_eq!(field.get_level(IVec2::new(-1, -1)), 8);
+        assert_eq!(field.get_level(IVec2::new(0, -1)), 7);
+        assert_eq!(field.get_ambient(), 4);
+
+        entity_field.remove(id).unwrap();
+        field.invalidate(IRect2::new(IVec2::new(-1, -1), IVec2::new(-1, -1)));
+        field.update(&block_field, &entity_field);
+
+        assert_eq!(field.get_level(IVec2::new(-1, -1)), 0);
+    }
+
+    #[test]
+    fn invalidate_all_light() {
+        let mut block_field = make_block_field();
+        let entity_field = make_entity_field();
+        let mut field = LightField::new(LightFieldInfo { ambient: 0 });
+
+        block_field.insert(Block { archetype_id: 0, coord: IVec2::new(31, 0), ..Default::default() }).unwrap();
+        field.invalidate(IRect2::new(IVec2::new(31, 0), IVec2::new(31, 0)));
+        field.update(&block_field, &entity_field);
+        assert!(!field.is_dirty(IVec2::new(0, -1)));
+
+        field.invalidate_all();
+        assert!(field.is_dirty(IVec2::new(0, 0)));
+        assert!(field.is_dirty(IVec2::new(0, -1)));
+        assert!(!field.is_dirty(IVec2::new(5, 5)));
+    }
+}
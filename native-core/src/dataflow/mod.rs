@@ -3,18 +3,35 @@ use glam::*;
 use crate::geom::*;
 
 pub use block::*;
+pub use clipboard::*;
+pub use edit::*;
 pub use entity::*;
+pub use event::*;
+pub use fluid::*;
 pub use item::*;
+pub use journal::*;
+pub use light::*;
 pub use resource::*;
 pub use tile::*;
 pub use time::*;
+pub use validation::*;
 
+use batch::Dispatch;
+
+mod batch;
 mod block;
+mod clipboard;
+mod edit;
 mod entity;
+mod event;
+mod fluid;
 mod item;
+mod journal;
+mod light;
 mod resource;
 mod tile;
 mod time;
+mod validation;
 
 // event handling
 
@@ -28,6 +45,7 @@ impl<T> EventHandler<T> for () {
     fn on_remove(&self, _: &mut Dataflow, _: T) { }
 }
 
+#[derive(Default)]
 pub struct EventHandlers {
     pub tiles: Vec<std::rc::Rc<dyn EventHandler<TileId>>>,
     pub blocks: Vec<std::rc::Rc<dyn EventHandler<BlockId>>>,
@@ -37,6 +55,26 @@ pub struct EventHandlers {
 // dataflow
 
 pub struct DataflowInfo {
+    pub tile_field: TileFieldInfo,
+    pub block_field: BlockFieldInfo,
+    pub entity_field: EntityFieldInfo,
+    pub fluid_field: FluidFieldInfo,
+    pub light_field: LightFieldInfo,
+    pub event_handlers: EventHandlers,
+}
+
+// field declarations only, used to build a detached dataflow on another thread
+#[derive(Debug, Clone)]
+pub struct DetachedInfo {
+    pub tile_field: TileFieldInfo,
+    pub block_field: BlockFieldInfo,
+    pub entity_field: EntityFieldInfo,
+    pub fluid_field: FluidFieldInfo,
+    pub light_field: LightFieldInfo,
+}
+
+// archetype declarations swapped in by reload, fluids and light are kept as they are
+pub struct ReloadInfo {
     pub tile_field: TileFieldInfo,
     pub block_field: BlockFieldInfo,
     pub entity_field: EntityFieldInfo,
@@ -44,32 +82,94 @@ pub struct DataflowInfo {
 }
 
 pub struct Dataflow {
+    detached_info: DetachedInfo,
     time_storage: TimeStorage,
 
     // structured data storage
     tile_field: TileField,
     block_field: BlockField,
     entity_field: EntityField,
+    fluid_field: FluidField,
+    light_field: LightField,
     event_handlers: EventHandlers,
+    event_queue: EventQueue,
+    journal: Option<Journal>,
+    deferred_dispatches: Option<Vec<Dispatch>>,
 
     // external data storage
     resource_storage: ResourceStorage,
 }
 
 impl Dataflow {
+    const MAX_FLUID_STEPS: u64 = 8;
+
     pub fn new(info: DataflowInfo) -> Self {
         Self {
+            detached_info: DetachedInfo {
+                tile_field: info.tile_field.clone(),
+                block_field: info.block_field.clone(),
+                entity_field: info.entity_field.clone(),
+                fluid_field: info.fluid_field.clone(),
+                light_field: info.light_field.clone(),
+            },
             time_storage: TimeStorage::new(),
 
             tile_field: TileField::new(info.tile_field),
             block_field: BlockField::new(info.block_field),
             entity_field: EntityField::new(info.entity_field),
+            fluid_field: FluidField::new(info.fluid_field),
+            light_field: LightField::new(info.light_field),
             event_handlers: info.event_handlers,
+            event_queue: Default::default(),
+            journal: Default::default(),
+            deferred_dispatches: Default::default(),
 
             resource_storage: ResourceStorage::new(),
         }
     }
 
+    // same fields with handlers that do nothing and without resources
+    pub fn new_detached(info: DetachedInfo) -> Self {
+        let event_handlers = EventHandlers {
+            tiles: info.tile_field.tiles.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+            blocks: info.block_field.blocks.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+            entities: info.entity_field.entities.iter().map(|_| std::rc::Rc::new(()) as _).collect(),
+        };
+
+        Self::new(DataflowInfo {
+            tile_field: info.tile_field,
+            block_field: info.block_field,
+            entity_field: info.entity_field,
+            fluid_field: info.fluid_field,
+            light_field: info.light_field,
+            event_handlers,
+        })
+    }
+
+    #[inline]
+    pub fn get_detached_info(&self) -> &DetachedInfo {
+        &self.detached_info
+    }
+
+    // swap archetype data of tiles, blocks and entities while keeping every instance,
+    // the number of archetypes must not change since instances refer to them by id,
+    // a reload that makes instances collide is rejected before anything changes
+    pub fn reload(&mut self, info: ReloadInfo) -> Result<(), DataflowError> {
+        self.tile_field.check_reload(&info.tile_field)?;
+        self.block_field.check_reload(&info.block_field)?;
+
+        self.detached_info.tile_field = info.tile_field.clone();
+        self.detached_info.block_field = info.block_field.clone();
+        self.detached_info.entity_field = info.entity_field.clone();
+
+        self.tile_field.reload(info.tile_field)?;
+        self.block_field.reload(info.block_field)?;
+        self.entity_field.reload(info.entity_field);
+        self.light_field.invalidate_all();
+        self.event_handlers = info.event_handlers;
+        Ok(())
+    }
+
     // time
 
     #[inline]
@@ -82,9 +182,21 @@ impl Dataflow {
         self.time_storage.get_tick()
     }
 
-    #[inline]
     pub fn process(&mut self, delta_secs: f32) {
+        let tick = self.time_storage.get_tick();
         self.time_storage.process(delta_secs);
+        let new_tick = self.time_storage.get_tick();
+
+        // fluid simulation runs once per elapsed tick
+        let steps = new_tick.wrapping_sub(tick).min(Self::MAX_FLUID_STEPS);
+        for _ in 0..steps {
+            let block_field = &self.block_field;
+            self.fluid_field.step(|coord| {
+                block_field.find_with_collision_point(coord.as_vec2() + 0.5).next().is_some()
+            });
+        }
+
+        self.light_field.update(&self.block_field, &self.entity_field);
     }
 
     // tile
@@ -93,34 +205,47 @@ impl Dataflow {
     pub fn insert_tile(&mut self, tile: Tile) -> Result<TileId, DataflowError> {
         let archetype_id = tile.archetype_id;
         let tile_id = self.tile_field.insert(tile)?;
-        let handler = self.event_handlers.tiles.get(archetype_id as usize).unwrap().clone();
-        handler.on_insert(self, tile_id);
+        self.event_queue.push(EventKind::TileInserted, tile_id);
+        self.record_insert_tile(tile_id);
+        self.dispatch(Dispatch::InsertTile(archetype_id, tile_id));
         Ok(tile_id)
     }
 
     #[inline]
     pub fn remove_til(&mut self, tile_id: TileId) -> Result<Tile, DataflowError> {
         let tile = self.tile_field.remove(tile_id)?;
-        let handler = self.event_handlers.tiles.get(tile.archetype_id as usize).unwrap().clone();
-        handler.on_remove(self, tile_id);
+        self.event_queue.push(EventKind::TileRemoved, tile_id);
+        self.record_remove_tile(tile_id, &tile);
+        self.dispatch(Dispatch::RemoveTile(tile.archetype_id, tile_id));
         Ok(tile)
     }
 
     #[inline]
     pub fn modify_tile_variant(&mut self, tile_id: TileId, variant: u16) -> Result<(), DataflowError> {
+        let tile = self.tile_field.get(tile_id)?;
+        let prev = (tile.variant, tile.tick);
         self.tile_field.modify_variant(tile_id, variant)?;
+        self.event_queue.push(EventKind::TileChanged, tile_id);
+        self.record_modify_tile(tile_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn modify_tile_tick(&mut self, tile_id: TileId, tick: u32) -> Result<(), DataflowError> {
+        let tile = self.tile_field.get(tile_id)?;
+        let prev = (tile.variant, tile.tick);
         self.tile_field.modify_tick(tile_id, tick)?;
+        self.event_queue.push(EventKind::TileChanged, tile_id);
+        self.record_modify_tile(tile_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn move_tile(&mut self, tile_id: TileId, new_coord: IVec2) -> Result<(), DataflowError> {
+        let prev_coord = self.tile_field.get(tile_id)?.coord;
         self.tile_field.r#move(tile_id, new_coord)?;
+        self.event_queue.push(EventKind::TileChanged, tile_id);
+        self.record_move_tile(tile_id, prev_coord, new_coord);
         Ok(())
     }
 
@@ -135,6 +260,11 @@ impl Dataflow {
         self.tile_field.find_chunk_coord(point)
     }
 
+    #[inline]
+    pub fn get_tile_chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.tile_field.chunk_coords()
+    }
+
     #[inline]
     pub fn get_tile_chunk(&self, chunk_coord: IVec2) -> Result<&TileChunk, DataflowError> {
         let chunk = self.tile_field.get_chunk(chunk_coord)?;
@@ -147,6 +277,11 @@ impl Dataflow {
         Ok(archetype)
     }
 
+    #[inline]
+    pub fn get_tile_layer_len(&self) -> u16 {
+        self.tile_field.get_layer_len()
+    }
+
     // tile spatial features
 
     #[inline]
@@ -159,6 +294,16 @@ impl Dataflow {
         self.tile_field.find_with_rect(rect)
     }
 
+    #[inline]
+    pub fn find_tile_with_point_in_layer(&self, layer_id: u16, point: IVec2) -> Option<(&TileId, &TileSpatialData)> {
+        self.tile_field.find_with_point_in_layer(layer_id, point)
+    }
+
+    #[inline]
+    pub fn find_tile_with_rect_in_layer(&self, layer_id: u16, rect: IRect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.tile_field.find_with_rect_in_layer(layer_id, rect)
+    }
+
     // tile collision features
 
     #[inline]
@@ -171,40 +316,71 @@ impl Dataflow {
         self.tile_field.find_with_collision_rect(rect)
     }
 
+    #[inline]
+    pub fn find_tile_with_collision_point_in_layer(&self, layer_id: u16, point: Vec2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.tile_field.find_with_collision_point_in_layer(layer_id, point)
+    }
+
+    #[inline]
+    pub fn find_tile_with_collision_rect_in_layer(&self, layer_id: u16, rect: Rect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.tile_field.find_with_collision_rect_in_layer(layer_id, rect)
+    }
+
     // block
 
     #[inline]
     pub fn insert_block(&mut self, block: Block) -> Result<BlockId, DataflowError> {
         let archetype_id = block.archetype_id;
         let block_id = self.block_field.insert(block)?;
-        let handler = self.event_handlers.blocks.get(archetype_id as usize).unwrap().clone();
-        handler.on_insert(self, block_id);
+        self.wake_fluid_with_block(block_id);
+        self.invalidate_light_with_block(block_id);
+        self.event_queue.push(EventKind::BlockInserted, block_id);
+        self.record_insert_block(block_id);
+        self.dispatch(Dispatch::InsertBlock(archetype_id, block_id));
         Ok(block_id)
     }
 
     #[inline]
     pub fn remove_block(&mut self, block_id: BlockId) -> Result<Block, DataflowError> {
+        self.wake_fluid_with_block(block_id);
+        self.invalidate_light_with_block(block_id);
         let block = self.block_field.remove(block_id)?;
-        let handler = self.event_handlers.blocks.get(block.archetype_id as usize).unwrap().clone();
-        handler.on_remove(self, block_id);
+        self.event_queue.push(EventKind::BlockRemoved, block_id);
+        self.record_remove_block(block_id, &block);
+        self.dispatch(Dispatch::RemoveBlock(block.archetype_id, block_id));
         Ok(block)
     }
 
     #[inline]
     pub fn modify_block_variant(&mut self, block_id: BlockId, variant: u16) -> Result<(), DataflowError> {
+        let block = self.block_field.get(block_id)?;
+        let prev = (block.variant, block.tick);
         self.block_field.modify_variant(block_id, variant)?;
+        self.event_queue.push(EventKind::BlockChanged, block_id);
+        self.record_modify_block(block_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn modify_block_tick(&mut self, block_id: BlockId, tick: u32) -> Result<(), DataflowError> {
+        let block = self.block_field.get(block_id)?;
+        let prev = (block.variant, block.tick);
         self.block_field.modify_tick(block_id, tick)?;
+        self.event_queue.push(EventKind::BlockChanged, block_id);
+        self.record_modify_block(block_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn move_block(&mut self, block_id: BlockId, new_coord: IVec2) -> Result<(), DataflowError> {
+        self.wake_fluid_with_block(block_id);
+        self.invalidate_light_with_block(block_id);
+        let prev_coord = self.block_field.get(block_id)?.coord;
         self.block_field.r#move(block_id, new_coord)?;
+        self.event_queue.push(EventKind::BlockChanged, block_id);
+        self.record_move_block(block_id, prev_coord, new_coord);
+        self.wake_fluid_with_block(block_id);
+        self.invalidate_light_with_block(block_id);
         Ok(())
     }
 
@@ -219,6 +395,11 @@ impl Dataflow {
         self.block_field.find_chunk_coord(point)
     }
 
+    #[inline]
+    pub fn get_block_chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.block_field.chunk_coords()
+    }
+
     #[inline]
     pub fn get_block_chunk(&self, chunk_coord: IVec2) -> Result<&BlockChunk, DataflowError> {
         let chunk = self.block_field.get_chunk(chunk_coord)?;
@@ -273,34 +454,51 @@ impl Dataflow {
     pub fn insert_entity(&mut self, entity: Entity) -> Result<EntityId, DataflowError> {
         let archetype_id = entity.archetype_id;
         let entity_id = self.entity_field.insert(entity)?;
-        let handler = self.event_handlers.entities.get(archetype_id as usize).unwrap().clone();
-        handler.on_insert(self, entity_id);
+        self.invalidate_light_with_entity(entity_id);
+        self.event_queue.push(EventKind::EntityInserted, entity_id);
+        self.record_insert_entity(entity_id);
+        self.dispatch(Dispatch::InsertEntity(archetype_id, entity_id));
         Ok(entity_id)
     }
 
     #[inline]
     pub fn remove_entity(&mut self, entity_id: EntityId) -> Result<Entity, DataflowError> {
+        self.invalidate_light_with_entity(entity_id);
         let entity = self.entity_field.remove(entity_id)?;
-        let handler = self.event_handlers.entities.get(entity.archetype_id as usize).unwrap().clone();
-        handler.on_remove(self, entity_id);
+        self.event_queue.push(EventKind::EntityRemoved, entity_id);
+        self.record_remove_entity(entity_id, &entity);
+        self.dispatch(Dispatch::RemoveEntity(entity.archetype_id, entity_id));
         Ok(entity)
     }
 
     #[inline]
     pub fn modify_entity_variant(&mut self, entity_id: EntityId, variant: u16) -> Result<(), DataflowError> {
+        let entity = self.entity_field.get(entity_id)?;
+        let prev = (entity.variant, entity.tick);
         self.entity_field.modify_variant(entity_id, variant)?;
+        self.event_queue.push(EventKind::EntityChanged, entity_id);
+        self.record_modify_entity(entity_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn modify_entity_tick(&mut self, entity_id: EntityId, tick: u32) -> Result<(), DataflowError> {
+        let entity = self.entity_field.get(entity_id)?;
+        let prev = (entity.variant, entity.tick);
         self.entity_field.modify_tick(entity_id, tick)?;
+        self.event_queue.push(EventKind::EntityChanged, entity_id);
+        self.record_modify_entity(entity_id, prev);
         Ok(())
     }
 
     #[inline]
     pub fn move_entity(&mut self, entity_id: EntityId, new_coord: Vec2) -> Result<(), DataflowError> {
+        self.invalidate_light_with_entity(entity_id);
+        let prev_coord = self.entity_field.get(entity_id)?.coord;
         self.entity_field.r#move(entity_id, new_coord)?;
+        self.event_queue.push(EventKind::EntityChanged, entity_id);
+        self.record_move_entity(entity_id, prev_coord, new_coord);
+        self.invalidate_light_with_entity(entity_id);
         Ok(())
     }
 
@@ -315,6 +513,11 @@ impl Dataflow {
         self.entity_field.find_chunk_coord(point)
     }
 
+    #[inline]
+    pub fn get_entity_chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.entity_field.chunk_coords()
+    }
+
     #[inline]
     pub fn get_entity_chunk(&self, chunk_coord: IVec2) -> Result<&EntityChunk, DataflowError> {
         let chunk = self.entity_field.get_chunk(chunk_coord)?;
@@ -351,6 +554,159 @@ impl Dataflow {
         self.entity_field.find_with_hint_rect(rect)
     }
 
+    // fluid
+
+    #[inline]
+    pub fn fill_fluid(&mut self, coord: IVec2, fluid: Fluid) -> Result<(), DataflowError> {
+        self.fluid_field.fill(coord, fluid)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn drain_fluid(&mut self, coord: IVec2) -> Result<Fluid, DataflowError> {
+        let fluid = self.fluid_field.drain(coord)?;
+        Ok(fluid)
+    }
+
+    #[inline]
+    pub fn get_fluid(&self, coord: IVec2) -> Option<&Fluid> {
+        self.fluid_field.get(coord)
+    }
+
+    #[inline]
+    pub fn get_fluid_level(&self, coord: IVec2) -> u8 {
+        self.fluid_field.get_level(coord)
+    }
+
+    #[inline]
+    pub fn find_fluid_chunk_coord(&self, point: Vec2) -> IVec2 {
+        self.fluid_field.find_chunk_coord(point)
+    }
+
+    #[inline]
+    pub fn get_fluid_chunk(&self, chunk_coord: IVec2) -> Result<&FluidChunk, DataflowError> {
+        let chunk = self.fluid_field.get_chunk(chunk_coord)?;
+        Ok(chunk)
+    }
+
+    #[inline]
+    pub fn get_fluid_archetype(&self, archetype_id: u16) -> Result<&FluidArchetype, DataflowError> {
+        let archetype = self.fluid_field.get_archetype(archetype_id)?;
+        Ok(archetype)
+    }
+
+    // blocks with collision act as walls, so the fluid around them has to settle again
+    fn wake_fluid_with_block(&mut self, block_id: BlockId) {
+        let Ok(block) = self.block_field.get(block_id) else {
+            return;
+        };
+        let archetype = self.block_field.get_archetype(block.archetype_id).unwrap();
+        if archetype.collision_rect.is_none() {
+            return;
+        }
+
+        let rect = archetype.rect(block.coord);
+        for y in rect.min.y..=rect.max.y {
+            for x in rect.min.x..=rect.max.x {
+                self.fluid_field.wake(IVec2::new(x, y));
+            }
+        }
+    }
+
+    // light
+
+    #[inline]
+    pub fn get_light_ambient(&self) -> u8 {
+        self.light_field.get_ambient()
+    }
+
+    #[inline]
+    pub fn set_light_ambient(&mut self, ambient: u8) {
+        self.light_field.set_ambient(ambient);
+    }
+
+    #[inline]
+    pub fn get_light_level(&self, coord: IVec2) -> u8 {
+        self.light_field.get_level(coord)
+    }
+
+    #[inline]
+    pub fn find_light_chunk_coord(&self, point: Vec2) -> IVec2 {
+        self.light_field.find_chunk_coord(point)
+    }
+
+    #[inline]
+    pub fn get_light_chunk(&self, chunk_coord: IVec2) -> Result<&LightChunk, DataflowError> {
+        let chunk = self.light_field.get_chunk(chunk_coord)?;
+        Ok(chunk)
+    }
+
+    // only blocks and entities that emit or block light affect the light map
+    fn invalidate_light_with_block(&mut self, block_id: BlockId) {
+        let Ok(block) = self.block_field.get(block_id) else {
+            return;
+        };
+        let archetype = self.block_field.get_archetype(block.archetype_id).unwrap();
+        if archetype.light_emission == 0 && archetype.light_opacity == 0 {
+            return;
+        }
+
+        self.light_field.invalidate(archetype.rect(block.coord));
+    }
+
+    fn invalidate_light_with_entity(&mut self, entity_id: EntityId) {
+        let Ok(entity) = self.entity_field.get(entity_id) else {
+            return;
+        };
+        let archetype = self.entity_field.get_archetype(entity.archetype_id).unwrap();
+        if archetype.light_emission == 0 && archetype.light_opacity == 0 {
+            return;
+        }
+
+        let coord = entity.coord.floor().as_ivec2();
+        self.light_field.invalidate(IRect2::new(coord, coord));
+    }
+
+    // event handlers
+
+    #[inline]
+    pub fn set_tile_event_handler(&mut self, archetype_id: u16, event_handler: std::rc::Rc<dyn EventHandler<TileId>>) -> Result<(), DataflowError> {
+        let slot = self.event_handlers.tiles.get_mut(archetype_id as usize).ok_or(TileError::InvalidId)?;
+        *slot = event_handler;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn set_block_event_handler(&mut self, archetype_id: u16, event_handler: std::rc::Rc<dyn EventHandler<BlockId>>) -> Result<(), DataflowError> {
+        let slot = self.event_handlers.blocks.get_mut(archetype_id as usize).ok_or(BlockError::InvalidId)?;
+        *slot = event_handler;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn set_entity_event_handler(&mut self, archetype_id: u16, event_handler: std::rc::Rc<dyn EventHandler<EntityId>>) -> Result<(), DataflowError> {
+        let slot = self.event_handlers.entities.get_mut(archetype_id as usize).ok_or(EntityError::InvalidId)?;
+        *slot = event_handler;
+        Ok(())
+    }
+
+    // events
+
+    #[inline]
+    pub fn subscribe_event(&mut self, kind: EventKind) {
+        self.event_queue.subscribe(kind);
+    }
+
+    #[inline]
+    pub fn unsubscribe_event(&mut self, kind: EventKind) {
+        self.event_queue.unsubscribe(kind);
+    }
+
+    #[inline]
+    pub fn drain_events(&mut self) -> (Vec<Event>, usize) {
+        self.event_queue.drain()
+    }
+
     // resources
 
     #[inline]
@@ -382,6 +738,8 @@ pub enum DataflowError {
     TileError(TileError),
     BlockError(BlockError),
     EntityError(EntityError),
+    FluidError(FluidError),
+    LightError(LightError),
     ResourceError(ResourceError),
 }
 
@@ -391,6 +749,8 @@ impl std::fmt::Display for DataflowError {
             Self::TileError(e) => e.fmt(f),
             Self::BlockError(e) => e.fmt(f),
             Self::EntityError(e) => e.fmt(f),
+            Self::FluidError(e) => e.fmt(f),
+            Self::LightError(e) => e.fmt(f),
             Self::ResourceError(e) => e.fmt(f),
         }
     }
@@ -402,6 +762,8 @@ impl std::error::Error for DataflowError {
             Self::TileError(e) => Some(e),
             Self::BlockError(e) => Some(e),
             Self::EntityError(e) => Some(e),
+            Self::FluidError(e) => Some(e),
+            Self::LightError(e) => Some(e),
             Self::ResourceError(e) => Some(e),
         }
     }
@@ -425,8 +787,108 @@ impl From<EntityError> for DataflowError {
     }
 }
 
+impl From<FluidError> for DataflowError {
+    fn from(e: FluidError) -> Self {
+        Self::FluidError(e)
+    }
+}
+
+impl From<LightError> for DataflowError {
+    fn from(e: LightError) -> Self {
+        Self::LightError(e)
+    }
+}
+
 impl From<ResourceError> for DataflowError {
     fn from(e: ResourceError) -> Self {
         Self::ResourceError(e)
     }
 }
+
+// test
+
+// archetypes without names on a single tile layer
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct TestDataflowBuilder {
+    tiles: Vec<TileInfo>,
+    blocks: Vec<BlockInfo>,
+    entities: Vec<EntityInfo>,
+}
+
+#[cfg(test)]
+impl TestDataflowBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn tile(mut self, collision: bool) -> Self {
+        self.tiles.push(TileInfo {
+            display_name: "".into(),
+            description: "".into(),
+            collision,
+            layer_id: 0,
+        });
+        self
+    }
+
+    pub fn block(mut self, size: IVec2) -> Self {
+        self.blocks.push(BlockInfo {
+            display_name: "".into(),
+            description: "".into(),
+            size,
+            collision_rect: None,
+            hint_rect: Rect2::new(Vec2::ZERO, size.as_vec2()),
+            y_sorting: false,
+            light_emission: 0,
+            light_opacity: 0,
+        });
+        self
+    }
+
+    pub fn entity(mut self) -> Self {
+        self.entities.push(EntityInfo {
+            display_name: "".into(),
+            description: "".into(),
+            collision_rect: None,
+            hint_rect: Rect2::new(Vec2::ZERO, Vec2::ONE),
+            y_sorting: false,
+            light_emission: 0,
+            light_opacity: 0,
+        });
+        self
+    }
+
+    // every archetype gets a handler that does nothing
+    pub fn build(self) -> Dataflow {
+        Dataflow::new_detached(self.detached_info())
+    }
+
+    pub fn build_with(self, event_handlers: EventHandlers) -> Dataflow {
+        let info = self.detached_info();
+        Dataflow::new(DataflowInfo {
+            tile_field: info.tile_field,
+            block_field: info.block_field,
+            entity_field: info.entity_field,
+            fluid_field: info.fluid_field,
+            light_field: info.light_field,
+            event_handlers,
+        })
+    }
+
+    fn detached_info(self) -> DetachedInfo {
+        DetachedInfo {
+            tile_field: TileFieldInfo {
+                tiles: self.tiles,
+                layers: vec![TileLayerInfo {
+                    display_name: "".into(),
+                    description: "".into(),
+                }],
+            },
+            block_field: BlockFieldInfo { blocks: self.blocks },
+            entity_field: EntityFieldInfo { entities: self.entities },
+            fluid_field: FluidFieldInfo { fluids: vec![] },
+            light_field: LightFieldInfo { ambient: 0 },
+        }
+    }
+}
@@ -2,6 +2,8 @@ use glam::*;
 
 use crate::geom::*;
 
+use super::ValidationReport;
+
 pub type TileId = u64;
 
 #[inline]
@@ -19,11 +21,23 @@ fn encode_coord(coord: IVec2) -> u64 {
     (coord.x as u32 as u64) << 32 | coord.y as u32 as u64
 }
 
+#[inline]
+fn decode_coord(coord: u64) -> IVec2 {
+    IVec2::new((coord >> 32) as u32 as i32, coord as u32 as i32)
+}
+
 // locality of reference
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TileSpatialData {
     pub rect: IRect2,
     pub collision_rect: Option<Rect2>,
+    pub layer_id: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct TileLayerInfo {
+    pub display_name: String,
+    pub description: String,
 }
 
 #[derive(Debug, Clone)]
@@ -31,16 +45,19 @@ pub struct TileInfo {
     pub display_name: String,
     pub description: String,
     pub collision: bool,
+    pub layer_id: u16,
 }
 
 #[derive(Debug, Clone)]
 pub struct TileFieldInfo {
     pub tiles: Vec<TileInfo>,
+    pub layers: Vec<TileLayerInfo>,
 }
 
 #[derive(Debug, Clone)]
 pub struct TileArchetype {
     pub collision: bool,
+    pub layer_id: u16,
 }
 
 impl TileArchetype {
@@ -79,35 +96,122 @@ pub struct TileChunk {
     pub ids: Vec<TileId>,
 }
 
+// each layer owns its spatial index, so tiles only conflict within the same layer
+#[derive(Debug, Default)]
+struct TileLayer {
+    hgrid: HGrid<TileSpatialData>,
+}
+
+impl TileLayer {
+    #[inline]
+    fn find_with_rect(&self, rect: IRect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.hgrid.find(rect)
+            .filter(move |(_, data)| Intersects::intersects(&rect, &data.rect))
+    }
+
+    #[inline]
+    fn find_with_collision_rect(&self, rect: Rect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.hgrid.find(rect.trunc_over().as_irect2())
+            .filter(move |(_, data)| data.collision_rect.map(|obj_rect| Intersects::intersects(&rect, &obj_rect)).unwrap_or(false))
+    }
+}
+
 #[derive(Debug)]
 pub struct TileField {
     archetypes: Vec<TileArchetype>,
     chunks: Vec<TileChunk>,
     coord_index: ahash::AHashMap<u64, u32>,
     id_index: slab::Slab<u64>,
-    hgrid: HGrid<TileSpatialData>,
+    layers: Vec<TileLayer>,
 }
 
 impl TileField {
     const CHUNK_SIZE: u32 = 32;
 
     pub fn new(info: TileFieldInfo) -> Self {
-        let mut archetypes = vec![];
+        let mut layers = vec![];
 
-        assert!(info.tiles.len() <= u16::MAX as usize, "capacity overflow");
-        for tile in info.tiles {
-            archetypes.push(TileArchetype {
-                collision: tile.collision,
-            });
+        assert!(info.layers.len() <= u16::MAX as usize, "capacity overflow");
+        for _ in info.layers {
+            layers.push(TileLayer::default());
         }
 
+        assert!(info.tiles.len() <= u16::MAX as usize, "capacity overflow");
+        let archetypes = info.tiles.into_iter().map(|tile| Self::create_archetype(tile, layers.len())).collect();
+
         Self {
             archetypes,
             chunks: Default::default(),
             coord_index: Default::default(),
             id_index: Default::default(),
-            hgrid: Default::default(),
+            layers,
+        }
+    }
+
+    fn create_archetype(tile: TileInfo, layer_count: usize) -> TileArchetype {
+        if tile.layer_id as usize >= layer_count {
+            panic!("layer must be declared");
+        }
+
+        TileArchetype {
+            collision: tile.collision,
+            layer_id: tile.layer_id,
+        }
+    }
+
+    // replace archetype data in place, instances whose spatial data changed are re-indexed
+    // rejects archetype data that would put two tiles at the same coord in one layer
+    pub fn check_reload(&self, info: &TileFieldInfo) -> Result<(), TileError> {
+        assert!(info.layers.len() == self.layers.len(), "number of layer must not change");
+        assert!(info.tiles.len() == self.archetypes.len(), "number of archetype must not change");
+
+        if self.archetypes.iter().zip(&info.tiles).all(|(archetype, tile)| archetype.layer_id == tile.layer_id) {
+            return Ok(());
+        }
+
+        let mut occupied = ahash::AHashSet::new();
+        for chunk in &self.chunks {
+            for tile in &chunk.tiles {
+                let layer_id = info.tiles[tile.archetype_id as usize].layer_id;
+                if !occupied.insert((layer_id, tile.coord)) {
+                    return Err(TileError::Conflict);
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    // nothing changes when the reload is rejected
+    pub fn reload(&mut self, info: TileFieldInfo) -> Result<(), TileError> {
+        self.check_reload(&info)?;
+
+        let archetypes = info.tiles.into_iter().map(|tile| Self::create_archetype(tile, self.layers.len())).collect::<Vec<_>>();
+
+        for chunk in &self.chunks {
+            for (tile, id) in chunk.tiles.iter().zip(&chunk.ids) {
+                let archetype = &self.archetypes[tile.archetype_id as usize];
+                let new_archetype = &archetypes[tile.archetype_id as usize];
+                if archetype.collision == new_archetype.collision && archetype.layer_id == new_archetype.layer_id {
+                    continue;
+                }
+
+                let broad_rect = TileArchetype::broad_rect(tile.coord);
+
+                let layer = self.layers.get_mut(archetype.layer_id as usize).unwrap();
+                layer.hgrid.remove(broad_rect, *id);
+
+                let layer = self.layers.get_mut(new_archetype.layer_id as usize).unwrap();
+                layer.hgrid.insert(broad_rect, *id, TileSpatialData {
+                    rect: TileArchetype::rect(tile.coord),
+                    collision_rect: new_archetype.collision_rect(tile.coord),
+                    layer_id: new_archetype.layer_id,
+                });
+            }
+        }
+
+        self.archetypes = archetypes;
+        Ok(())
     }
 
     #[inline]
@@ -135,7 +239,7 @@ impl TileField {
 
         // check by spatial features
         let archetype = self.archetypes.get(tile.archetype_id as usize).ok_or(TileError::InvalidId)?;
-        if self.find_with_point(tile.coord).is_some() {
+        if self.find_with_point_in_layer(archetype.layer_id, tile.coord).is_some() {
             return Err(TileError::Conflict);
         }
 
@@ -146,10 +250,12 @@ impl TileField {
         let id = self.id_index.insert(address) as u64;
 
         // register spatial index
+        let layer = self.layers.get_mut(archetype.layer_id as usize).unwrap();
         let broad_rect = TileArchetype::broad_rect(tile.coord);
-        self.hgrid.insert(broad_rect, id, TileSpatialData {
+        layer.hgrid.insert(broad_rect, id, TileSpatialData {
             rect: TileArchetype::rect(tile.coord),
             collision_rect: archetype.collision_rect(tile.coord),
+            layer_id: archetype.layer_id,
         });
 
         chunk.tiles.push(tile);
@@ -171,8 +277,10 @@ impl TileField {
         }
 
         // unregister spatial index
+        let archetype = self.archetypes.get(tile.archetype_id as usize).unwrap();
+        let layer = self.layers.get_mut(archetype.layer_id as usize).unwrap();
         let broad_rect = TileArchetype::broad_rect(tile.coord);
-        self.hgrid.remove(broad_rect, id);
+        layer.hgrid.remove(broad_rect, id);
 
         chunk.version += 1;
         Ok(tile)
@@ -212,20 +320,25 @@ impl TileField {
 
         // check by spatial features
         let archetype = self.archetypes.get(tile.archetype_id as usize).unwrap();
-        if self.find_with_point(new_coord).is_some() {
+        if self.find_with_point_in_layer(archetype.layer_id, new_coord).is_some() {
             return Err(TileError::Conflict);
         }
 
         // update spatial index
+        let layer = self.layers.get_mut(archetype.layer_id as usize).unwrap();
         let broad_rect = TileArchetype::broad_rect(tile.coord);
         let new_broad_rect = TileArchetype::broad_rect(new_coord);
-        if self.hgrid.check_move(broad_rect, new_broad_rect) {
-            let value = TileSpatialData {
-                rect: TileArchetype::rect(new_coord),
-                collision_rect: archetype.collision_rect(new_coord),
-            };
-            self.hgrid.remove(broad_rect, id);
-            self.hgrid.insert(new_broad_rect, id, value);
+        let value = TileSpatialData {
+            rect: TileArchetype::rect(new_coord),
+            collision_rect: archetype.collision_rect(new_coord),
+            layer_id: archetype.layer_id,
+        };
+        if layer.hgrid.check_move(broad_rect, new_broad_rect) {
+            layer.hgrid.remove(broad_rect, id);
+            layer.hgrid.insert(new_broad_rect, id, value);
+        } else {
+            // same cells, but the stored rects still describe the old coordinate
+            layer.hgrid.modify(new_broad_rect, id, value);
         }
 
         // move owner
@@ -291,6 +404,12 @@ impl TileField {
         coord.div_euclid(IVec2::splat(Self::CHUNK_SIZE as i32))
     }
 
+    // chunks are never dropped, so a chunk once created stays in the iteration
+    #[inline]
+    pub fn chunk_coords(&self) -> impl Iterator<Item = IVec2> {
+        self.coord_index.keys().map(|chunk_coord| decode_coord(*chunk_coord))
+    }
+
     #[inline]
     pub fn get_chunk(&self, chunk_coord: IVec2) -> Result<&TileChunk, TileError> {
         let chunk_coord_ = encode_coord(chunk_coord);
@@ -299,17 +418,34 @@ impl TileField {
         Ok(chunk)
     }
 
+    // layer
+
+    #[inline]
+    pub fn get_layer_len(&self) -> u16 {
+        self.layers.len() as u16
+    }
+
     // spatial features
 
+    // returns the tile on the top-most layer, the last declared layer is the top
     #[inline]
     pub fn find_with_point(&self, point: IVec2) -> Option<(&TileId, &TileSpatialData)> {
-        self.find_with_rect(IRect2::new(point, point)).next()
+        self.layers.iter().rev().find_map(|layer| layer.find_with_rect(IRect2::new(point, point)).next())
     }
 
     #[inline]
     pub fn find_with_rect(&self, rect: IRect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
-        self.hgrid.find(rect)
-            .filter(move |(_, data)| Intersects::intersects(&rect, &data.rect))
+        self.layers.iter().flat_map(move |layer| layer.find_with_rect(rect))
+    }
+
+    #[inline]
+    pub fn find_with_point_in_layer(&self, layer_id: u16, point: IVec2) -> Option<(&TileId, &TileSpatialData)> {
+        self.find_with_rect_in_layer(layer_id, IRect2::new(point, point)).next()
+    }
+
+    #[inline]
+    pub fn find_with_rect_in_layer(&self, layer_id: u16, rect: IRect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.layers.get(layer_id as usize).into_iter().flat_map(move |layer| layer.find_with_rect(rect))
     }
 
     // collision features
@@ -321,8 +457,116 @@ impl TileField {
 
     #[inline]
     pub fn find_with_collision_rect(&self, rect: Rect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
-        self.hgrid.find(rect.trunc_over().as_irect2())
-            .filter(move |(_, data)| data.collision_rect.map(|obj_rect| Intersects::intersects(&rect, &obj_rect)).unwrap_or(false))
+        self.layers.iter().flat_map(move |layer| layer.find_with_collision_rect(rect))
+    }
+
+    #[inline]
+    pub fn find_with_collision_point_in_layer(&self, layer_id: u16, point: Vec2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.find_with_collision_rect_in_layer(layer_id, Rect2::new(point, point))
+    }
+
+    #[inline]
+    pub fn find_with_collision_rect_in_layer(&self, layer_id: u16, rect: Rect2) -> impl Iterator<Item = (&TileId, &TileSpatialData)> {
+        self.layers.get(layer_id as usize).into_iter().flat_map(move |layer| layer.find_with_collision_rect(rect))
+    }
+
+    // validation
+
+    // cross-check the chunk index, the id index and the spatial index against the chunks
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        // chunk index
+        let mut chunk_coords = vec![None; self.chunks.len()];
+        for (chunk_coord_, chunk_id) in &self.coord_index {
+            let chunk_coord = decode_coord(*chunk_coord_);
+            match chunk_coords.get_mut(*chunk_id as usize) {
+                Some(slot @ None) => *slot = Some(chunk_coord),
+                Some(Some(other)) => report.push(format!("chunk {} is indexed at both ({}, {}) and ({}, {})", chunk_id, other.x, other.y, chunk_coord.x, chunk_coord.y)),
+                None => report.push(format!("chunk ({}, {}) is indexed to missing chunk {}", chunk_coord.x, chunk_coord.y, chunk_id)),
+            }
+        }
+
+        // chunks against the other indices, counting the spatial cells each tile was found in
+        let mut found = ahash::AHashMap::<(u16, TileId), usize>::default();
+        for (chunk_id, chunk) in self.chunks.iter().enumerate() {
+            let chunk_coord = chunk_coords[chunk_id];
+            if chunk_coord.is_none() {
+                report.push(format!("chunk {} is not indexed by its coordinate", chunk_id));
+            }
+            if chunk.tiles.len() != chunk.ids.len() {
+                report.push(format!("chunk {} holds {} tiles but {} ids", chunk_id, chunk.tiles.len(), chunk.ids.len()));
+            }
+
+            for (local_id, (tile, id)) in chunk.tiles.iter().zip(&chunk.ids).enumerate() {
+                let at = format!("tile {} at ({}, {})", id, tile.coord.x, tile.coord.y);
+
+                let address = encode_address(chunk_id as u32, local_id as u32);
+                match self.id_index.get(*id as usize) {
+                    Some(other) if *other == address => {}
+                    Some(other) => {
+                        let (other_chunk_id, other_local_id) = decode_address(*other);
+                        report.push(format!("{}: stored at {}:{} but indexed at {}:{}", at, chunk_id, local_id, other_chunk_id, other_local_id));
+                    }
+                    None => report.push(format!("{}: stored at {}:{} but missing from the id index", at, chunk_id, local_id)),
+                }
+
+                let expected_chunk_coord = Self::find_chunk_coord_internal(tile.coord);
+                if let Some(chunk_coord) = chunk_coord && chunk_coord != expected_chunk_coord {
+                    report.push(format!("{}: stored in chunk ({}, {}) instead of ({}, {})", at, chunk_coord.x, chunk_coord.y, expected_chunk_coord.x, expected_chunk_coord.y));
+                }
+
+                let Some(archetype) = self.archetypes.get(tile.archetype_id as usize) else {
+                    report.push(format!("{}: invalid archetype {}", at, tile.archetype_id));
+                    continue;
+                };
+                let value = TileSpatialData {
+                    rect: TileArchetype::rect(tile.coord),
+                    collision_rect: archetype.collision_rect(tile.coord),
+                    layer_id: archetype.layer_id,
+                };
+                let layer = &self.layers[archetype.layer_id as usize];
+                let cells = layer.hgrid.get(TileArchetype::broad_rect(tile.coord), *id);
+                let missing = cells.iter().filter(|cell| cell.is_none()).count();
+                let stale = cells.iter().flatten().filter(|cell| ***cell != value).count();
+                if missing > 0 {
+                    report.push(format!("{}: missing from {} of {} spatial cells", at, missing, cells.len()));
+                }
+                if stale > 0 {
+                    report.push(format!("{}: stale spatial data in {} of {} spatial cells", at, stale, cells.len()));
+                }
+                found.insert((archetype.layer_id, *id), cells.len() - missing);
+            }
+        }
+
+        // ids that point at nothing
+        for (id, address) in &self.id_index {
+            let (chunk_id, local_id) = decode_address(*address);
+            let stored = self.chunks.get(chunk_id as usize).and_then(|chunk| chunk.ids.get(local_id as usize));
+            match stored {
+                Some(other) if *other == id as TileId => {}
+                Some(other) => report.push(format!("tile {}: indexed at {}:{} which holds tile {}", id, chunk_id, local_id, other)),
+                None => report.push(format!("tile {}: indexed at {}:{} which is empty", id, chunk_id, local_id)),
+            }
+        }
+
+        // entries left in cells the tile does not cover, of removed tiles or in another layer
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            let mut counts = ahash::AHashMap::<TileId, usize>::default();
+            for (id, _) in layer.hgrid.iter() {
+                *counts.entry(*id).or_default() += 1;
+            }
+            let mut counts = counts.into_iter().collect::<Vec<_>>();
+            counts.sort();
+            for (id, count) in counts {
+                let expected = found.get(&(layer_id as u16, id)).copied().unwrap_or_default();
+                if count > expected {
+                    report.push(format!("tile {}: {} stray entries in the spatial index of layer {}", id, count - expected, layer_id));
+                }
+            }
+        }
+
+        report
     }
 }
 
@@ -356,16 +600,51 @@ mod tests {
                     display_name: "tile_0".into(),
                     description: "tile_0_desc".into(),
                     collision: true,
+                    layer_id: 0,
                 },
                 TileInfo {
                     display_name: "tile_1".into(),
                     description: "tile_1_desc".into(),
                     collision: true,
+                    layer_id: 0,
+                },
+                TileInfo {
+                    display_name: "tile_2".into(),
+                    description: "tile_2_desc".into(),
+                    collision: false,
+                    layer_id: 1,
+                },
+            ],
+            layers: vec![
+                TileLayerInfo {
+                    display_name: "layer_0".into(),
+                    description: "layer_0_desc".into(),
+                },
+                TileLayerInfo {
+                    display_name: "layer_1".into(),
+                    description: "layer_1_desc".into(),
                 },
             ],
         })
     }
 
+    #[test]
+    #[should_panic]
+    fn tile_field_with_invalid_layer() {
+        TileField::new(TileFieldInfo {
+            tiles: vec![TileInfo {
+                display_name: "tile_0".into(),
+                description: "tile_0_desc".into(),
+                collision: true,
+                layer_id: 1,
+            }],
+            layers: vec![TileLayerInfo {
+                display_name: "layer_0".into(),
+                description: "layer_0_desc".into(),
+            }],
+        });
+    }
+
     #[test]
     fn crud_tile() {
         let mut field = make_tile_field();
@@ -401,7 +680,7 @@ mod tests {
 
         assert_eq!(
             field.insert(Tile {
-                archetype_id: 2,
+                archetype_id: 3,
                 coord: IVec2::new(-1, 3),
                 ..Default::default()
             }),
@@ -558,6 +837,63 @@ mod tests {
         assert!(vec.contains(&id1));
     }
 
+    #[test]
+    fn layer_tile() {
+        let mut field = make_tile_field();
+
+        let id0 = field
+            .insert(Tile {
+                archetype_id: 0,
+                coord: IVec2::new(-1, 3),
+                ..Default::default()
+            })
+            .unwrap();
+        let id1 = field
+            .insert(Tile {
+                archetype_id: 2,
+                coord: IVec2::new(-1, 3),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            field.insert(Tile {
+                archetype_id: 2,
+                coord: IVec2::new(-1, 3),
+                ..Default::default()
+            }),
+            Err(TileError::Conflict)
+        );
+
+        let query = field.find_with_point(IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id1));
+        let query = field.find_with_point_in_layer(0, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id0));
+        let query = field.find_with_point_in_layer(1, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id1));
+        let query = field.find_with_point_in_layer(2, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+
+        let vec = field.find_with_rect(IRect2::new(IVec2::new(-1, 3), IVec2::new(-1, 3))).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert!(vec.contains(&id0));
+        assert!(vec.contains(&id1));
+
+        let point = Vec2::new(-0.5, 3.5);
+        let vec = field.find_with_collision_point(point).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert!(vec.contains(&id0));
+        assert!(!vec.contains(&id1));
+        let vec = field.find_with_collision_point_in_layer(1, point).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert!(vec.is_empty());
+
+        field.r#move(id1, IVec2::new(-1, 1000)).unwrap();
+        field.r#move(id0, IVec2::new(-1, 1000)).unwrap();
+
+        field.remove(id0).unwrap();
+        let query = field.find_with_point_in_layer(0, IVec2::new(-1, 1000)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+        let query = field.find_with_point(IVec2::new(-1, 1000)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id1));
+    }
+
     #[test]
     fn tile_chunk() {
         let mut field = make_tile_field();
@@ -589,4 +925,149 @@ mod tests {
         let chunk = field.get_chunk(IVec2::new(-1, 0)).unwrap();
         assert_eq!(chunk.tiles.len(), 3);
     }
+
+    #[test]
+    fn reload_tile() {
+        let mut field = make_tile_field();
+
+        let id = field
+            .insert(Tile {
+                archetype_id: 0,
+                coord: IVec2::new(-1, 3),
+                ..Default::default()
+            })
+            .unwrap();
+
+        field.reload(TileFieldInfo {
+            tiles: vec![
+                TileInfo {
+                    display_name: "tile_0".into(),
+                    description: "tile_0_desc".into(),
+                    collision: false,
+                    layer_id: 1,
+                },
+                TileInfo {
+                    display_name: "tile_1".into(),
+                    description: "tile_1_desc".into(),
+                    collision: true,
+                    layer_id: 0,
+                },
+                TileInfo {
+                    display_name: "tile_2".into(),
+                    description: "tile_2_desc".into(),
+                    collision: false,
+                    layer_id: 1,
+                },
+            ],
+            layers: vec![
+                TileLayerInfo {
+                    display_name: "layer_0".into(),
+                    description: "layer_0_desc".into(),
+                },
+                TileLayerInfo {
+                    display_name: "layer_1".into(),
+                    description: "layer_1_desc".into(),
+                },
+            ],
+        })
+        .unwrap();
+
+        let tile = field.get(id).unwrap();
+        assert_eq!(tile.archetype_id, 0);
+        assert_eq!(tile.coord, IVec2::new(-1, 3));
+
+        let query = field.find_with_point_in_layer(0, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+        let query = field.find_with_point_in_layer(1, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id));
+        let vec = field.find_with_collision_point(Vec2::new(-0.5, 3.5)).map(|(id, _)| *id).collect::<Vec<_>>();
+        assert!(vec.is_empty());
+
+        field.remove(id).unwrap();
+        let query = field.find_with_point(IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+    }
+
+    #[test]
+    fn reload_tile_with_conflict() {
+        let mut field = make_tile_field();
+
+        let id = field.insert(Tile { archetype_id: 0, coord: IVec2::new(-1, 3), ..Default::default() }).unwrap();
+        field.insert(Tile { archetype_id: 2, coord: IVec2::new(-1, 3), ..Default::default() }).unwrap();
+
+        // tile_0 would move into the layer of tile_2
+        let info = TileFieldInfo {
+            tiles: ["tile_0", "tile_1", "tile_2"]
+                .into_iter()
+                .map(|name| TileInfo {
+                    display_name: name.into(),
+                    description: format!("{}_desc", name),
+                    collision: false,
+                    layer_id: 1,
+                })
+                .collect(),
+            layers: vec![
+                TileLayerInfo {
+                    display_name: "layer_0".into(),
+                    description: "layer_0_desc".into(),
+                },
+                TileLayerInfo {
+                    display_name: "layer_1".into(),
+                    description: "layer_1_desc".into(),
+                },
+            ],
+        };
+        assert_eq!(field.reload(info), Err(TileError::Conflict));
+
+        assert_eq!(field.get_archetype(0).unwrap().layer_id, 0);
+        let query = field.find_with_point_in_layer(0, IVec2::new(-1, 3)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id));
+        assert!(field.validate().is_valid());
+    }
+
+    #[test]
+    fn move_tile_in_same_cell() {
+        let mut field = make_tile_field();
+
+        let id = field.insert(Tile { archetype_id: 1, coord: IVec2::new(2, 0), ..Default::default() }).unwrap();
+        field.r#move(id, IVec2::new(3, 1)).unwrap();
+
+        let query = field.find_with_point(IVec2::new(3, 1)).map(|(id, _)| *id);
+        assert_eq!(query, Some(id));
+        let query = field.find_with_point(IVec2::new(2, 0)).map(|(id, _)| *id);
+        assert_eq!(query, None);
+        assert_eq!(field.find_with_collision_point(Vec2::new(3.5, 1.5)).count(), 1);
+        assert_eq!(field.find_with_collision_point(Vec2::new(2.5, 0.5)).count(), 0);
+    }
+
+    #[test]
+    fn validate_tile() {
+        let mut field = make_tile_field();
+
+        let mut ids = vec![];
+        for x in 0..4 {
+            ids.push(field.insert(Tile { archetype_id: 0, coord: IVec2::new(x, 0), ..Default::default() }).unwrap());
+        }
+        let id = field.insert(Tile { archetype_id: 2, coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        field.remove(ids[0]).unwrap();
+        field.r#move(ids[1], IVec2::new(40, 0)).unwrap();
+        field.r#move(ids[2], IVec2::new(2, 1)).unwrap();
+        assert_eq!(field.validate(), ValidationReport::default());
+
+        field.id_index[ids[3] as usize] = encode_address(0, 7);
+        let layer = field.layers.get_mut(1).unwrap();
+        layer.hgrid.remove(TileArchetype::broad_rect(IVec2::new(0, 0)), id);
+        layer.hgrid.insert(TileArchetype::broad_rect(IVec2::new(100, 100)), id, TileSpatialData {
+            rect: TileArchetype::rect(IVec2::new(100, 100)),
+            collision_rect: None,
+            layer_id: 1,
+        });
+        let report = field.validate();
+        assert_eq!(report.issues, [
+            format!("tile {} at (0, 0): missing from 1 of 1 spatial cells", id),
+            format!("tile {} at (3, 0): stored at 0:1 but indexed at 0:7", ids[3]),
+            format!("tile {}: indexed at 0:7 which is empty", ids[3]),
+            format!("tile {}: 1 stray entries in the spatial index of layer 1", id),
+        ]);
+    }
 }
@@ -0,0 +1,54 @@
+use super::*;
+
+// inconsistencies between the structures a field keeps in sync, one line each
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<String>,
+}
+
+impl ValidationReport {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn push(&mut self, issue: String) {
+        self.issues.push(issue);
+    }
+
+    fn extend(&mut self, prefix: &str, other: ValidationReport) {
+        self.issues.extend(other.issues.into_iter().map(|issue| format!("{}: {}", prefix, issue)));
+    }
+}
+
+impl std::fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "valid");
+        }
+        write!(f, "{}", self.issues.join("\n"))
+    }
+}
+
+impl Dataflow {
+    // validation
+
+    // walks every instance, so it is meant for tests, debug builds and offline tools rather than every frame
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        report.extend("tile field", self.tile_field.validate());
+        report.extend("block field", self.block_field.validate());
+        report.extend("entity field", self.entity_field.validate());
+        report
+    }
+
+    // panics with the report if the fields disagree, does nothing in release builds
+    #[inline]
+    pub fn debug_validate(&self) {
+        if cfg!(debug_assertions) {
+            let report = self.validate();
+            assert!(report.is_valid(), "dataflow is inconsistent\n{}", report);
+        }
+    }
+}
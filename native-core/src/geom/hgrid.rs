@@ -42,6 +42,18 @@ impl<T> Cell<T> {
         }
     }
 
+    #[inline]
+    fn modify(&mut self, key: u64, value: T) {
+        if let Some(i) = self.keys.iter().position(|k| *k == key) {
+            self.values[i] = value;
+        }
+    }
+
+    #[inline]
+    fn get(&self, key: u64) -> Option<&T> {
+        self.keys.iter().position(|k| *k == key).map(|i| &self.values[i])
+    }
+
     #[inline]
     fn iter(&self) -> impl Iterator<Item = (&u64, &T)> {
         Iterator::zip(self.keys.iter(), self.values.iter())
@@ -63,81 +75,53 @@ impl<T> Default for HGrid<T> {
 
 impl<T> HGrid<T> where T: Clone {
     pub fn insert(&mut self, rect: IRect2, key: u64, value: T) {
-        let size = rect.size().max_element();
-
-        match size {
-            ..BLOCK_SIZE => {
-                let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
-                let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
-                for y in min.y..=max.y {
-                    for x in min.x..=max.x {
-                        let coord = IVec2::new(x, y);
-                        let coord_ = encode_coord(coord);
-                        let [_, cells @ ..] = self.cells.entry(coord_).or_default();
-                        let min = (rect.min.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
-                        let max = (rect.max.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
-                        for v in min.y..=max.y {
-                            for u in min.x..=max.x {
-                                cells[(u + v * DIV_SIZE) as usize].insert(key, value.clone());
-                            }
-                        }
-                    }
-                }
-            }
-            BLOCK_SIZE.. => {
-                let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
-                let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
-                for y in min.y..=max.y {
-                    for x in min.x..=max.x {
-                        let coord = IVec2::new(x, y);
-                        let coord_ = encode_coord(coord);
-                        let [cell, ..] = self.cells.entry(coord_).or_default();
-                        cell.insert(key, value.clone());
-                    }
-                }
-            }
+        for (coord_, index) in Self::slots(rect) {
+            let cells = self.cells.entry(coord_).or_default();
+            cells[index].insert(key, value.clone());
         }
     }
 
     pub fn remove(&mut self, rect: IRect2, key: u64) {
-        let size = rect.size().max_element();
-
-        match size {
-            ..BLOCK_SIZE => {
-                let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
-                let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
-                for y in min.y..=max.y {
-                    for x in min.x..=max.x {
-                        let coord = IVec2::new(x, y);
-                        let coord_ = encode_coord(coord);
-                        if let Some([_, cells @ ..]) = self.cells.get_mut(&coord_) {
-                            let min = (rect.min.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
-                            let max = (rect.max.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
-                            for v in min.y..=max.y {
-                                for u in min.x..=max.x {
-                                    cells[(u + v * DIV_SIZE) as usize].remove(key);
-                                }
-                            }
-                        }
-                    }
-                }
+        for (coord_, index) in Self::slots(rect) {
+            if let Some(cells) = self.cells.get_mut(&coord_) {
+                cells[index].remove(key);
             }
-            BLOCK_SIZE.. => {
-                let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
-                let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
-                for y in min.y..=max.y {
-                    for x in min.x..=max.x {
-                        let coord = IVec2::new(x, y);
-                        let coord_ = encode_coord(coord);
-                        if let Some([cell, ..]) = self.cells.get_mut(&coord_) {
-                            cell.remove(key);
-                        }
-                    }
-                }
+        }
+    }
+
+    // replace the value of the key in the cells of the rect, for moves that stay in the same cells
+    pub fn modify(&mut self, rect: IRect2, key: u64, value: T) {
+        for (coord_, index) in Self::slots(rect) {
+            if let Some(cells) = self.cells.get_mut(&coord_) {
+                cells[index].modify(key, value.clone());
             }
         }
     }
 
+    // the cells a rect is stored in, as the encoded chunk coordinate and the index into its cells,
+    // small rects go to the blocks they touch and large ones to the shared cell of each chunk
+    fn slots(rect: IRect2) -> impl Iterator<Item = (u64, usize)> {
+        let small = rect.size().max_element() < BLOCK_SIZE;
+
+        let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
+        let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .flat_map(move |coord| {
+                let coord_ = encode_coord(coord);
+                let (min, max) = if small {
+                    let min = (rect.min.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
+                    let max = (rect.max.div_euclid(IVec2::splat(BLOCK_SIZE)) - coord * DIV_SIZE).clamp(IVec2::ZERO, IVec2::splat(DIV_SIZE - 1));
+                    (min, max)
+                } else {
+                    (IVec2::ZERO, IVec2::ZERO)
+                };
+                (min.y..=max.y).flat_map(move |v| {
+                    (min.x..=max.x).map(move |u| (coord_, if small { 1 + (u + v * DIV_SIZE) as usize } else { 0 }))
+                })
+            })
+    }
+
     #[inline]
     pub fn check_move(&self, rect: IRect2, new_rect: IRect2) -> bool {
         assert_eq!(rect.size(), new_rect.size(), "Rect size must be same.");
@@ -153,6 +137,18 @@ impl<T> HGrid<T> where T: Clone {
         min != new_min || max != new_max
     }
 
+    // the value of the key in each cell the rect was inserted into, None where it is missing
+    pub fn get(&self, rect: IRect2, key: u64) -> Vec<Option<&T>> {
+        Self::slots(rect)
+            .map(|(coord_, index)| self.cells.get(&coord_).and_then(|cells| cells[index].get(key)))
+            .collect()
+    }
+
+    // every entry, a key is listed once for each cell it was inserted into
+    pub fn iter(&self) -> impl Iterator<Item = (&u64, &T)> {
+        self.cells.values().flat_map(|cells| cells.iter().flat_map(|cell| cell.iter()))
+    }
+
     pub fn find(&self, rect: IRect2) -> impl Iterator<Item = (&u64, &T)> {
         let min = rect.min.div_euclid(IVec2::splat(CHUNK_SIZE));
         let max = rect.max.div_euclid(IVec2::splat(CHUNK_SIZE));
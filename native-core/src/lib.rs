@@ -1,37 +1,21 @@
 pub use glam::*;
 pub use geom::*;
+pub use pick::*;
+pub use registry::*;
+pub use replay::*;
+pub use schematic::*;
 
 pub mod dataflow;
+pub mod manifest;
+pub mod net;
+#[cfg(feature = "godot")]
 pub mod view;
 
 mod geom;
-
-// registry
-
-#[derive(Default)]
-pub struct Registry {
-    storage: ahash::AHashMap<String, u16>,
-}
-
-impl Registry {
-    pub fn new() -> Self {
-        Default::default()
-    }
-
-    pub fn set(&mut self, name: String, value: u16) {
-        if self.storage.contains_key(&name) {
-            panic!("Name {} already exists", name);
-        }
-
-        self.storage.insert(name, value);
-    }
-
-    pub fn get(&self, name: &str) -> u16 {
-        *self.storage
-            .get(name)
-            .unwrap_or_else(|| panic!("Name {} not found", name))
-    }
-}
+mod pick;
+mod registry;
+mod replay;
+mod schematic;
 
 // descriptor for building the context
 
@@ -49,19 +33,53 @@ impl<T> EventHandler<T> {
     }
 }
 
+// forwards events to godot callables with the id as the only argument,
+// the call is deferred so that the callee may call back into the context
+#[cfg(feature = "godot")]
+pub struct CallableEventHandler {
+    pub on_insert: godot::builtin::Callable,
+    pub on_remove: godot::builtin::Callable,
+}
+
+#[cfg(feature = "godot")]
+impl dataflow::EventHandler<u64> for CallableEventHandler {
+    fn on_insert(&self, _: &mut dataflow::Dataflow, id: u64) {
+        if self.on_insert.is_valid() {
+            self.on_insert.call_deferred(&[godot::meta::ToGodot::to_variant(&(id as i64))]);
+        }
+    }
+
+    fn on_remove(&self, _: &mut dataflow::Dataflow, id: u64) {
+        if self.on_remove.is_valid() {
+            self.on_remove.call_deferred(&[godot::meta::ToGodot::to_variant(&(id as i64))]);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct SpriteInfo {
+    #[cfg(feature = "godot")]
     pub images: Vec<godot::obj::Gd<godot::classes::Image>>,
     pub step_tick: u16,
     pub is_loop: bool,
 }
 
+#[derive(Default)]
+pub struct TileLayerInfo {
+    pub display_name: String,
+    pub description: String,
+    // only shifts the rendering depth, queries and picking treat later layers as drawn on top,
+    // so z offsets are expected to grow with the declaration order
+    pub z_offset: f32,
+}
+
 #[derive(Default)]
 pub struct TileInfo {
     pub display_name: String,
     pub description: String,
     pub sprites: Vec<SpriteInfo>,
     pub collision: bool,
+    pub layer_id: u16,
     pub event_handler: EventHandler<dataflow::TileId>,
 }
 
@@ -74,6 +92,8 @@ pub struct BlockInfo {
     pub size: IVec2,
     pub collision_rect: Option<Rect2>,
     pub rendering_rect: Rect2,
+    pub light_emission: u8,
+    pub light_opacity: u8,
     pub event_handler: EventHandler<dataflow::TileId>,
 }
 
@@ -85,23 +105,61 @@ pub struct EntityInfo {
     pub y_sorting: bool,
     pub collision_rect: Option<Rect2>,
     pub rendering_rect: Rect2,
+    pub light_emission: u8,
+    pub light_opacity: u8,
     pub event_handler: EventHandler<dataflow::TileId>,
 }
 
+#[derive(Default)]
+pub struct FluidInfo {
+    pub display_name: String,
+    pub description: String,
+    pub sprites: Vec<SpriteInfo>,
+    pub viscosity: u8,
+}
+
+#[cfg(feature = "godot")]
 pub struct BuildInfo {
     pub tile_shaders: Vec<godot::obj::Gd<godot::classes::Shader>>,
     pub block_shaders: Vec<godot::obj::Gd<godot::classes::Shader>>,
     pub entity_shaders: Vec<godot::obj::Gd<godot::classes::Shader>>,
+    pub fluid_shaders: Vec<godot::obj::Gd<godot::classes::Shader>>,
     pub viewport: godot::obj::Gd<godot::classes::Viewport>,
 }
 
+struct Definitions {
+    tile_field: dataflow::TileFieldInfo,
+    block_field: dataflow::BlockFieldInfo,
+    entity_field: dataflow::EntityFieldInfo,
+    event_handlers: dataflow::EventHandlers,
+    // the handlers of the archetypes before any replacement
+    #[cfg_attr(not(feature = "godot"), allow(dead_code))]
+    declared_event_handlers: dataflow::EventHandlers,
+    #[cfg(feature = "godot")]
+    tile_layers_view: Vec<view::TileLayerInfo>,
+    #[cfg(feature = "godot")]
+    tiles_view: Vec<view::TileInfo>,
+    #[cfg(feature = "godot")]
+    blocks_view: Vec<view::BlockInfo>,
+    #[cfg(feature = "godot")]
+    entities_view: Vec<view::EntityInfo>,
+}
+
 #[allow(clippy::type_complexity)]
 #[derive(Default)]
 pub struct ContextBuilder {
+    tile_layers: Vec<Box<dyn FnOnce(&Registry) -> TileLayerInfo>>,
     tiles: Vec<Box<dyn FnOnce(&Registry) -> TileInfo>>,
     blocks: Vec<Box<dyn FnOnce(&Registry) -> BlockInfo>>,
     entities: Vec<Box<dyn FnOnce(&Registry) -> EntityInfo>>,
+    fluids: Vec<Box<dyn FnOnce(&Registry) -> FluidInfo>>,
     resources: Vec<Box<dyn FnOnce(&Registry, &mut dataflow::Dataflow)>>,
+    tile_event_handlers: Vec<(u16, EventHandler<dataflow::TileId>)>,
+    block_event_handlers: Vec<(u16, EventHandler<dataflow::BlockId>)>,
+    entity_event_handlers: Vec<(u16, EventHandler<dataflow::EntityId>)>,
+    light_ambient: Option<u8>,
+    #[cfg_attr(not(feature = "godot"), allow(dead_code))]
+    skip_images: bool,
     registry: Registry,
 }
 
@@ -110,25 +168,39 @@ impl ContextBuilder {
         Default::default()
     }
 
-    pub fn add_tile<F>(&mut self, name: String, desc_fn: F) where F: FnOnce(&Registry) -> TileInfo + 'static
+    pub fn add_tile_layer<F>(&mut self, name: String, desc_fn: F) -> Result<u16, RegistryError> where F: FnOnce(&Registry) -> TileLayerInfo + 'static
+    {
+        let id = self.registry.tile_layers_mut().insert(&name)?;
+        self.tile_layers.push(Box::new(desc_fn));
+        Ok(id)
+    }
+
+    pub fn add_tile<F>(&mut self, name: String, desc_fn: F) -> Result<u16, RegistryError> where F: FnOnce(&Registry) -> TileInfo + 'static
     {
+        let id = self.registry.tiles_mut().insert(&name)?;
         self.tiles.push(Box::new(desc_fn));
-        let id = (self.tiles.len() - 1) as u16;
-        self.registry.set(name, id);
+        Ok(id)
     }
 
-    pub fn add_block<F>(&mut self, name: String, desc_fn: F) where F: FnOnce(&Registry) -> BlockInfo + 'static
+    pub fn add_block<F>(&mut self, name: String, desc_fn: F) -> Result<u16, RegistryError> where F: FnOnce(&Registry) -> BlockInfo + 'static
     {
+        let id = self.registry.blocks_mut().insert(&name)?;
         self.blocks.push(Box::new(desc_fn));
-        let id = (self.blocks.len() - 1) as u16;
-        self.registry.set(name, id);
+        Ok(id)
     }
 
-    pub fn add_entity<F>(&mut self, name: String, desc_fn: F) where F: FnOnce(&Registry) -> EntityInfo + 'static
+    pub fn add_entity<F>(&mut self, name: String, desc_fn: F) -> Result<u16, RegistryError> where F: FnOnce(&Registry) -> EntityInfo + 'static
     {
+        let id = self.registry.entities_mut().insert(&name)?;
         self.entities.push(Box::new(desc_fn));
-        let id = (self.entities.len() - 1) as u16;
-        self.registry.set(name, id);
+        Ok(id)
+    }
+
+    pub fn add_fluid<F>(&mut self, name: String, desc_fn: F) -> Result<u16, RegistryError> where F: FnOnce(&Registry) -> FluidInfo + 'static
+    {
+        let id = self.registry.fluids_mut().insert(&name)?;
+        self.fluids.push(Box::new(desc_fn));
+        Ok(id)
     }
 
     pub fn add_resource<F, R>(&mut self, desc_fn: F) where F: FnOnce(&Registry) -> R + 'static, R: dataflow::Resource + 'static
@@ -139,62 +211,111 @@ impl ContextBuilder {
         }));
     }
 
-    pub fn build(self, info: BuildInfo) -> Context {
-        let world = info
-            .viewport
-            .get_world_3d()
-            .unwrap_or_else(|| panic!("Failed to get World3D from {}", info.viewport));
+    // replace the event handler of an already added archetype
+    pub fn set_tile_event_handler(&mut self, name: &str, event_handler: EventHandler<dataflow::TileId>) -> Result<(), RegistryError> {
+        let id = self.registry.tiles().get(name)?;
+        self.tile_event_handlers.push((id, event_handler));
+        Ok(())
+    }
+
+    pub fn set_block_event_handler(&mut self, name: &str, event_handler: EventHandler<dataflow::BlockId>) -> Result<(), RegistryError> {
+        let id = self.registry.blocks().get(name)?;
+        self.block_event_handlers.push((id, event_handler));
+        Ok(())
+    }
+
+    pub fn set_entity_event_handler(&mut self, name: &str, event_handler: EventHandler<dataflow::EntityId>) -> Result<(), RegistryError> {
+        let id = self.registry.entities().get(name)?;
+        self.entity_event_handlers.push((id, event_handler));
+        Ok(())
+    }
+
+    pub fn set_light_ambient(&mut self, ambient: u8) {
+        self.light_ambient = Some(ambient);
+    }
+
+    // names added so far, to look archetypes up before build
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    // manifests loaded afterwards leave sprites without images, for contexts that never render
+    pub fn set_skip_images(&mut self, skip_images: bool) {
+        self.skip_images = skip_images;
+    }
+
+    // archetype definitions shared by build and reload, fluids are not reloadable
+    fn define(&mut self) -> Definitions {
+        // tile layer
+        let mut tile_layers = vec![];
+        #[cfg(feature = "godot")]
+        let mut tile_layers_view = vec![];
+        for tile_layer in std::mem::take(&mut self.tile_layers) {
+            let tile_layer_info = tile_layer(&self.registry);
+
+            tile_layers.push(dataflow::TileLayerInfo {
+                display_name: tile_layer_info.display_name,
+                description: tile_layer_info.description,
+            });
+
+            #[cfg(feature = "godot")]
+            tile_layers_view.push(view::TileLayerInfo {
+                z_offset: tile_layer_info.z_offset,
+            });
+        }
 
         // tile field
         let mut tiles = vec![];
         let mut tiles_event_handler = vec![];
+        #[cfg(feature = "godot")]
         let mut tiles_view = vec![];
-        for tile in self.tiles {
+        for tile in std::mem::take(&mut self.tiles) {
             let tile_info = tile(&self.registry);
 
             tiles.push(dataflow::TileInfo {
                 display_name: tile_info.display_name,
                 description: tile_info.description,
                 collision: tile_info.collision,
+                layer_id: tile_info.layer_id,
             });
 
-            let mut sprites = vec![];
-            for sprite in tile_info.sprites {
-                let mut images = vec![];
-                for image in sprite.images {
-                    images.push(image);
+            let EventHandler(handler) = tile_info.event_handler;
+            tiles_event_handler.push(handler.into());
+
+            #[cfg(feature = "godot")]
+            {
+                let mut sprites = vec![];
+                for sprite in tile_info.sprites {
+                    let mut images = vec![];
+                    for image in sprite.images {
+                        images.push(image);
+                    }
+
+                    sprites.push(view::TileSpriteInfo {
+                        images,
+                        tick_per_image: sprite.step_tick,
+                        is_loop: sprite.is_loop,
+                    });
                 }
 
-                sprites.push(view::TileSpriteInfo {
-                    images,
-                    tick_per_image: sprite.step_tick,
-                    is_loop: sprite.is_loop,
+                tiles_view.push(view::TileInfo {
+                    sprites,
+                    layer_id: tile_info.layer_id,
                 });
             }
-
-            let EventHandler(handler) = tile_info.event_handler;
-            tiles_event_handler.push(handler.into());
-
-            tiles_view.push(view::TileInfo { sprites });
         }
 
-        let tile_field_info = dataflow::TileFieldInfo { tiles };
-
-        let mut tile_shaders = vec![];
-        for shader in info.tile_shaders {
-            tile_shaders.push(shader);
-        }
-        let tile_field_view = view::TileField::new(view::TileFieldInfo {
-            tiles: tiles_view,
-            shaders: tile_shaders,
-            world: world.clone(),
-        });
+        let tile_field_info = dataflow::TileFieldInfo {
+            tiles,
+            layers: tile_layers,
+        };
 
         // block field
         let mut blocks = vec![];
         let mut blocks_event_handler = vec![];
+        #[cfg(feature = "godot")]
         let mut blocks_view = vec![];
-        for block in self.blocks {
+        for block in std::mem::take(&mut self.blocks) {
             let block_info = block(&self.registry);
 
             blocks.push(dataflow::BlockInfo {
@@ -204,49 +325,45 @@ impl ContextBuilder {
                 collision_rect: block_info.collision_rect,
                 hint_rect: block_info.rendering_rect,
                 y_sorting: block_info.y_sorting,
+                light_emission: block_info.light_emission,
+                light_opacity: block_info.light_opacity,
             });
 
-            let mut sprites = vec![];
-            for sprite in block_info.sprites {
-                let mut images = vec![];
-                for image in sprite.images {
-                    images.push(image);
+            let EventHandler(handler) = block_info.event_handler;
+            blocks_event_handler.push(handler.into());
+
+            #[cfg(feature = "godot")]
+            {
+                let mut sprites = vec![];
+                for sprite in block_info.sprites {
+                    let mut images = vec![];
+                    for image in sprite.images {
+                        images.push(image);
+                    }
+
+                    sprites.push(view::BlockSpriteInfo {
+                        images,
+                        ticks_per_image: sprite.step_tick,
+                        is_loop: sprite.is_loop,
+                    });
                 }
 
-                sprites.push(view::BlockSpriteInfo {
-                    images,
-                    ticks_per_image: sprite.step_tick,
-                    is_loop: sprite.is_loop,
+                blocks_view.push(view::BlockInfo {
+                    sprites,
+                    y_sorting: block_info.y_sorting,
+                    rendering_rect: block_info.rendering_rect,
                 });
             }
-
-            let EventHandler(handler) = block_info.event_handler;
-            blocks_event_handler.push(handler.into());
-
-            blocks_view.push(view::BlockInfo {
-                sprites,
-                y_sorting: block_info.y_sorting,
-                rendering_rect: block_info.rendering_rect,
-            });
         }
 
         let block_field_info = dataflow::BlockFieldInfo { blocks };
 
-        let mut block_shaders = vec![];
-        for shader in info.block_shaders {
-            block_shaders.push(shader);
-        }
-        let block_field_view = view::BlockField::new(view::BlockFieldInfo {
-            blocks: blocks_view,
-            shaders: block_shaders,
-            world: world.clone(),
-        });
-
         // entity filed
         let mut entities = vec![];
         let mut entities_event_handler = vec![];
+        #[cfg(feature = "godot")]
         let mut entities_view = vec![];
-        for entity in self.entities {
+        for entity in std::mem::take(&mut self.entities) {
             let entity_info = entity(&self.registry);
 
             entities.push(dataflow::EntityInfo {
@@ -255,55 +372,214 @@ impl ContextBuilder {
                 collision_rect: entity_info.collision_rect,
                 hint_rect: entity_info.rendering_rect,
                 y_sorting: entity_info.y_sorting,
+                light_emission: entity_info.light_emission,
+                light_opacity: entity_info.light_opacity,
             });
 
-            let mut sprites = vec![];
-            for image in entity_info.sprites {
-                let mut images = vec![];
-                for image in image.images {
-                    images.push(image);
+            let EventHandler(handler) = entity_info.event_handler;
+            entities_event_handler.push(handler.into());
+
+            #[cfg(feature = "godot")]
+            {
+                let mut sprites = vec![];
+                for image in entity_info.sprites {
+                    let mut images = vec![];
+                    for image in image.images {
+                        images.push(image);
+                    }
+
+                    sprites.push(view::EntitySpriteInfo {
+                        images,
+                        ticks_per_image: image.step_tick,
+                        is_loop: image.is_loop,
+                    });
                 }
 
-                sprites.push(view::EntitySpriteInfo {
-                    images,
-                    ticks_per_image: image.step_tick,
-                    is_loop: image.is_loop,
+                entities_view.push(view::EntityInfo {
+                    sprites,
+                    y_sorting: entity_info.y_sorting,
+                    rendering_rect: entity_info.rendering_rect,
                 });
             }
+        }
 
-            let EventHandler(handler) = entity_info.event_handler;
-            entities_event_handler.push(handler.into());
+        let entity_field_info = dataflow::EntityFieldInfo { entities };
 
-            entities_view.push(view::EntityInfo {
-                sprites,
-                y_sorting: entity_info.y_sorting,
-                rendering_rect: entity_info.rendering_rect,
+        let declared_event_handlers = dataflow::EventHandlers {
+            tiles: tiles_event_handler.clone(),
+            blocks: blocks_event_handler.clone(),
+            entities: entities_event_handler.clone(),
+        };
+
+        // handlers replaced after the archetype was added
+        for (id, EventHandler(handler)) in std::mem::take(&mut self.tile_event_handlers) {
+            tiles_event_handler[id as usize] = handler.into();
+        }
+        for (id, EventHandler(handler)) in std::mem::take(&mut self.block_event_handlers) {
+            blocks_event_handler[id as usize] = handler.into();
+        }
+        for (id, EventHandler(handler)) in std::mem::take(&mut self.entity_event_handlers) {
+            entities_event_handler[id as usize] = handler.into();
+        }
+
+        let event_handlers = dataflow::EventHandlers {
+            tiles: tiles_event_handler,
+            blocks: blocks_event_handler,
+            entities: entities_event_handler,
+        };
+
+        Definitions {
+            tile_field: tile_field_info,
+            block_field: block_field_info,
+            entity_field: entity_field_info,
+            event_handlers,
+            declared_event_handlers,
+            #[cfg(feature = "godot")]
+            tile_layers_view,
+            #[cfg(feature = "godot")]
+            tiles_view,
+            #[cfg(feature = "godot")]
+            blocks_view,
+            #[cfg(feature = "godot")]
+            entities_view,
+        }
+    }
+
+    // the dataflow and the registry without any rendering, for tests, servers and tools
+    pub fn build_headless(mut self) -> HeadlessContext {
+        let definitions = self.define();
+
+        let mut fluids = vec![];
+        for fluid in self.fluids {
+            let fluid_info = fluid(&self.registry);
+
+            fluids.push(dataflow::FluidInfo {
+                display_name: fluid_info.display_name,
+                description: fluid_info.description,
+                viscosity: fluid_info.viscosity,
             });
         }
 
-        let entity_field_info = dataflow::EntityFieldInfo { entities };
+        let light_field_info = dataflow::LightFieldInfo {
+            ambient: self.light_ambient.unwrap_or(dataflow::LightField::MAX_LEVEL),
+        };
+
+        let mut dataflow = dataflow::Dataflow::new(dataflow::DataflowInfo {
+            tile_field: definitions.tile_field,
+            block_field: definitions.block_field,
+            entity_field: definitions.entity_field,
+            fluid_field: dataflow::FluidFieldInfo { fluids },
+            light_field: light_field_info,
+            event_handlers: definitions.event_handlers,
+        });
+
+        for resource in self.resources {
+            resource(&self.registry, &mut dataflow);
+        }
+
+        HeadlessContext {
+            registry: self.registry,
+            dataflow,
+        }
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn build(mut self, info: BuildInfo) -> Context {
+        let world = info
+            .viewport
+            .get_world_3d()
+            .unwrap_or_else(|| panic!("Failed to get World3D from {}", info.viewport));
+
+        let definitions = self.define();
+
+        // tile field
+        let mut tile_shaders = vec![];
+        for shader in info.tile_shaders {
+            tile_shaders.push(shader);
+        }
+        let tile_field_view = view::TileField::new(view::TileFieldInfo {
+            tiles: definitions.tiles_view,
+            layers: definitions.tile_layers_view,
+            shaders: tile_shaders,
+            world: world.clone(),
+        });
+
+        // block field
+        let mut block_shaders = vec![];
+        for shader in info.block_shaders {
+            block_shaders.push(shader);
+        }
+        let block_field_view = view::BlockField::new(view::BlockFieldInfo {
+            blocks: definitions.blocks_view,
+            shaders: block_shaders,
+            world: world.clone(),
+        });
 
+        // entity filed
         let mut entity_shaders = vec![];
         for shader in info.entity_shaders {
             entity_shaders.push(shader);
         }
         let entity_field_view = view::EntityField::new(view::EntityFieldInfo {
-            entities: entities_view,
+            entities: definitions.entities_view,
             shaders: entity_shaders,
             world: world.clone(),
         });
 
-        // dataflow
-        let event_handlers = dataflow::EventHandlers {
-            tiles: tiles_event_handler,
-            blocks: blocks_event_handler,
-            entities: entities_event_handler,
+        // fluid field
+        let mut fluids = vec![];
+        let mut fluids_view = vec![];
+        for fluid in self.fluids {
+            let fluid_info = fluid(&self.registry);
+
+            fluids.push(dataflow::FluidInfo {
+                display_name: fluid_info.display_name,
+                description: fluid_info.description,
+                viscosity: fluid_info.viscosity,
+            });
+
+            let mut sprites = vec![];
+            for sprite in fluid_info.sprites {
+                let mut images = vec![];
+                for image in sprite.images {
+                    images.push(image);
+                }
+
+                sprites.push(view::FluidSpriteInfo {
+                    images,
+                    tick_per_image: sprite.step_tick,
+                    is_loop: sprite.is_loop,
+                });
+            }
+
+            fluids_view.push(view::FluidInfo { sprites });
+        }
+
+        let fluid_field_info = dataflow::FluidFieldInfo { fluids };
+
+        let mut fluid_shaders = vec![];
+        for shader in info.fluid_shaders {
+            fluid_shaders.push(shader);
+        }
+        let fluid_field_view = view::FluidField::new(view::FluidFieldInfo {
+            fluids: fluids_view,
+            shaders: fluid_shaders,
+            world: world.clone(),
+        });
+
+        // light field
+        let light_field_info = dataflow::LightFieldInfo {
+            ambient: self.light_ambient.unwrap_or(dataflow::LightField::MAX_LEVEL),
         };
+
+        // dataflow
         let mut dataflow = dataflow::Dataflow::new(dataflow::DataflowInfo {
-            tile_field: tile_field_info,
-            block_field: block_field_info,
-            entity_field: entity_field_info,
-            event_handlers,
+            tile_field: definitions.tile_field,
+            block_field: definitions.block_field,
+            entity_field: definitions.entity_field,
+            fluid_field: fluid_field_info,
+            light_field: light_field_info,
+            event_handlers: definitions.event_handlers,
         });
 
         // resources
@@ -312,17 +588,159 @@ impl ContextBuilder {
         }
 
         Context {
+            registry: self.registry,
             dataflow,
+            declared_event_handlers: definitions.declared_event_handlers,
             tile_field_view,
             block_field_view,
             entity_field_view,
+            fluid_field_view,
         }
     }
 }
 
+#[cfg(feature = "godot")]
 pub struct Context {
+    pub registry: Registry,
     pub dataflow: dataflow::Dataflow,
+    declared_event_handlers: dataflow::EventHandlers,
     pub tile_field_view: view::TileField,
     pub block_field_view: view::BlockField,
     pub entity_field_view: view::EntityField,
+    pub fluid_field_view: view::FluidField,
+}
+
+#[cfg(feature = "godot")]
+impl Context {
+    // swap archetype data and sprites of tiles, blocks and entities while keeping every instance,
+    // the builder must declare the same names in the same order as the one this context was built from
+    pub fn reload(&mut self, mut builder: ContextBuilder) -> Result<(), ReloadError> {
+        let tables = [
+            ("tile_layers", self.registry.tile_layers(), builder.registry.tile_layers()),
+            ("tiles", self.registry.tiles(), builder.registry.tiles()),
+            ("blocks", self.registry.blocks(), builder.registry.blocks()),
+            ("entities", self.registry.entities(), builder.registry.entities()),
+        ];
+        for (kind, table, new_table) in tables {
+            if !table.iter().eq(new_table.iter()) {
+                return Err(ReloadError::MismatchedNames(kind.to_string()));
+            }
+        }
+
+        let definitions = builder.define();
+
+        self.dataflow
+            .reload(dataflow::ReloadInfo {
+                tile_field: definitions.tile_field,
+                block_field: definitions.block_field,
+                entity_field: definitions.entity_field,
+                event_handlers: definitions.event_handlers,
+            })
+            .map_err(ReloadError::DataflowError)?;
+        self.declared_event_handlers = definitions.declared_event_handlers;
+        self.tile_field_view.reload(definitions.tiles_view, definitions.tile_layers_view);
+        self.block_field_view.reload(definitions.blocks_view);
+        self.entity_field_view.reload(definitions.entities_view);
+        Ok(())
+    }
+
+    // puts back the handler the archetype was declared with, undoing set_*_event_handler
+    pub fn restore_tile_event_handler(&mut self, archetype_id: u16) -> Result<(), dataflow::DataflowError> {
+        let handler = self.declared_event_handlers.tiles.get(archetype_id as usize).ok_or(dataflow::TileError::InvalidId)?;
+        self.dataflow.set_tile_event_handler(archetype_id, handler.clone())
+    }
+
+    pub fn restore_block_event_handler(&mut self, archetype_id: u16) -> Result<(), dataflow::DataflowError> {
+        let handler = self.declared_event_handlers.blocks.get(archetype_id as usize).ok_or(dataflow::BlockError::InvalidId)?;
+        self.dataflow.set_block_event_handler(archetype_id, handler.clone())
+    }
+
+    pub fn restore_entity_event_handler(&mut self, archetype_id: u16) -> Result<(), dataflow::DataflowError> {
+        let handler = self.declared_event_handlers.entities.get(archetype_id as usize).ok_or(dataflow::EntityError::InvalidId)?;
+        self.dataflow.set_entity_event_handler(archetype_id, handler.clone())
+    }
+}
+
+pub struct HeadlessContext {
+    pub registry: Registry,
+    pub dataflow: dataflow::Dataflow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReloadError {
+    MismatchedNames(String),
+    DataflowError(dataflow::DataflowError),
+}
+
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MismatchedNames(kind) => write!(f, "mismatched names error: {}", kind),
+            Self::DataflowError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CounterResource(u32);
+
+    impl dataflow::Resource for CounterResource {}
+
+    #[test]
+    fn build_headless() {
+        let mut builder = ContextBuilder::new();
+        builder.add_tile_layer("layer_ground".into(), |_| TileLayerInfo::default()).unwrap();
+        builder.add_tile("tile_dirt".into(), |_| TileInfo::default()).unwrap();
+        builder.add_entity("entity_pig".into(), |_| EntityInfo {
+            rendering_rect: Rect2::new(Vec2::ZERO, Vec2::ONE),
+            ..Default::default()
+        }).unwrap();
+        builder.add_fluid("fluid_water".into(), |_| FluidInfo::default()).unwrap();
+        builder.add_resource(|registry| CounterResource(registry.entities().len() as u32));
+        let mut context = builder.build_headless();
+
+        let archetype_id = context.registry.tiles().get("tile_dirt").unwrap();
+        context.dataflow.insert_tile(dataflow::Tile { archetype_id, coord: IVec2::new(3, 4), ..Default::default() }).unwrap();
+        assert!(context.dataflow.find_tile_with_point(IVec2::new(3, 4)).is_some());
+
+        let archetype_id = context.registry.entities().get("entity_pig").unwrap();
+        context.dataflow.insert_entity(dataflow::Entity { archetype_id, coord: Vec2::new(1.0, 1.0), ..Default::default() }).unwrap();
+        context.dataflow.process(1.0);
+        assert_eq!(context.dataflow.find_entity_with_hint_point(Vec2::new(1.5, 1.5)).count(), 1);
+
+        let resource = context.dataflow.find_resources::<CounterResource>().unwrap();
+        assert_eq!(resource.borrow().unwrap().0, 1);
+    }
+
+    struct NoopHandler;
+
+    impl dataflow::EventHandler<dataflow::TileId> for NoopHandler {
+        fn on_insert(&self, _: &mut dataflow::Dataflow, _: dataflow::TileId) { }
+        fn on_remove(&self, _: &mut dataflow::Dataflow, _: dataflow::TileId) { }
+    }
+
+    #[test]
+    fn keep_declared_event_handlers() {
+        let mut builder = ContextBuilder::new();
+        builder.add_tile_layer("layer_ground".into(), |_| TileLayerInfo::default()).unwrap();
+        builder.add_tile("tile_dirt".into(), |_| TileInfo {
+            event_handler: EventHandler::new(NoopHandler),
+            ..Default::default()
+        }).unwrap();
+        builder.add_tile("tile_grass".into(), |_| TileInfo::default()).unwrap();
+        builder.set_tile_event_handler("tile_dirt", EventHandler::new(NoopHandler)).unwrap();
+        let definitions = builder.define();
+
+        // only the replaced archetype differs from its declaration
+        let [dirt, grass] = [0, 1].map(|id| {
+            std::rc::Rc::ptr_eq(&definitions.event_handlers.tiles[id], &definitions.declared_event_handlers.tiles[id])
+        });
+        assert!(!dirt);
+        assert!(grass);
+    }
 }
@@ -0,0 +1,511 @@
+use glam::*;
+
+use crate::*;
+
+// handler registry
+
+#[allow(clippy::type_complexity)]
+#[derive(Default)]
+pub struct HandlerRegistry {
+    tiles: ahash::AHashMap<String, std::rc::Rc<dyn Fn() -> EventHandler<dataflow::TileId>>>,
+    blocks: ahash::AHashMap<String, std::rc::Rc<dyn Fn() -> EventHandler<dataflow::BlockId>>>,
+    entities: ahash::AHashMap<String, std::rc::Rc<dyn Fn() -> EventHandler<dataflow::EntityId>>>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_tile_handler<F>(&mut self, name: String, handler_fn: F) where F: Fn() -> EventHandler<dataflow::TileId> + 'static
+    {
+        self.tiles.insert(name, std::rc::Rc::new(handler_fn));
+    }
+
+    pub fn add_block_handler<F>(&mut self, name: String, handler_fn: F) where F: Fn() -> EventHandler<dataflow::BlockId> + 'static
+    {
+        self.blocks.insert(name, std::rc::Rc::new(handler_fn));
+    }
+
+    pub fn add_entity_handler<F>(&mut self, name: String, handler_fn: F) where F: Fn() -> EventHandler<dataflow::EntityId> + 'static
+    {
+        self.entities.insert(name, std::rc::Rc::new(handler_fn));
+    }
+}
+
+// manifest schema
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub tile_layers: Vec<TileLayerEntry>,
+    #[serde(default)]
+    pub tiles: Vec<TileEntry>,
+    #[serde(default)]
+    pub blocks: Vec<BlockEntry>,
+    #[serde(default)]
+    pub entities: Vec<EntityEntry>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpriteEntry {
+    pub images: Vec<String>,
+    #[serde(default)]
+    pub step_tick: u16,
+    #[serde(default)]
+    pub is_loop: bool,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RectEntry {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl From<RectEntry> for Rect2 {
+    fn from(rect: RectEntry) -> Self {
+        Rect2::new(Vec2::from(rect.min), Vec2::from(rect.max))
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileLayerEntry {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    // layers are drawn in declaration order, see TileLayerInfo
+    #[serde(default)]
+    pub z_offset: f32,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileEntry {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    pub sprites: Vec<SpriteEntry>,
+    #[serde(default)]
+    pub collision: bool,
+    pub layer: String,
+    #[serde(default)]
+    pub event_handler: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockEntry {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    pub sprites: Vec<SpriteEntry>,
+    #[serde(default)]
+    pub y_sorting: bool,
+    pub size: [i32; 2],
+    #[serde(default)]
+    pub collision_rect: Option<RectEntry>,
+    pub rendering_rect: RectEntry,
+    #[serde(default)]
+    pub light_emission: u8,
+    #[serde(default)]
+    pub light_opacity: u8,
+    #[serde(default)]
+    pub event_handler: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntityEntry {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    pub sprites: Vec<SpriteEntry>,
+    #[serde(default)]
+    pub y_sorting: bool,
+    #[serde(default)]
+    pub collision_rect: Option<RectEntry>,
+    pub rendering_rect: RectEntry,
+    #[serde(default)]
+    pub light_emission: u8,
+    #[serde(default)]
+    pub light_opacity: u8,
+    #[serde(default)]
+    pub event_handler: Option<String>,
+}
+
+impl Manifest {
+    pub fn parse(path: &str, source: &str) -> Result<Self, ManifestError> {
+        serde_json::from_str(source).map_err(|e| ManifestError::ParseError {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    // checks everything that can be checked without the engine,
+    // names already in the registry of the same kind count as declared
+    pub fn validate(&self, path: &str, registry: &Registry, handlers: &HandlerRegistry) -> Result<(), ManifestError> {
+        let invalid = |entry: &str, message: String| ManifestError::InvalidEntry {
+            path: path.to_string(),
+            entry: entry.to_string(),
+            message,
+        };
+
+        let mut tile_layer_names = ahash::AHashSet::new();
+        for tile_layer in &self.tile_layers {
+            Self::declare(registry.tile_layers(), &mut tile_layer_names, &tile_layer.name)
+                .map_err(|message| invalid(&tile_layer.name, message))?;
+        }
+
+        let mut tile_names = ahash::AHashSet::new();
+        for tile in &self.tiles {
+            Self::declare(registry.tiles(), &mut tile_names, &tile.name).map_err(|message| invalid(&tile.name, message))?;
+            Self::validate_sprites(&tile.sprites).map_err(|message| invalid(&tile.name, message))?;
+
+            let is_declared = Registry::qualify(&tile.layer)
+                .is_ok_and(|layer| tile_layer_names.contains(&layer) || registry.tile_layers().contains(&layer));
+            if !is_declared {
+                return Err(invalid(&tile.name, format!("layer {} is not declared", tile.layer)));
+            }
+
+            if let Some(handler) = &tile.event_handler
+                && !handlers.tiles.contains_key(handler)
+            {
+                return Err(invalid(&tile.name, format!("event handler {} is not registered", handler)));
+            }
+        }
+
+        let mut block_names = ahash::AHashSet::new();
+        for block in &self.blocks {
+            Self::declare(registry.blocks(), &mut block_names, &block.name).map_err(|message| invalid(&block.name, message))?;
+            Self::validate_sprites(&block.sprites).map_err(|message| invalid(&block.name, message))?;
+
+            if block.size[0] <= 0 || block.size[1] <= 0 {
+                return Err(invalid(&block.name, "size must be positive".into()));
+            }
+
+            Self::validate_rect(block.collision_rect, block.rendering_rect).map_err(|message| invalid(&block.name, message))?;
+
+            if let Some(handler) = &block.event_handler
+                && !handlers.blocks.contains_key(handler)
+            {
+                return Err(invalid(&block.name, format!("event handler {} is not registered", handler)));
+            }
+        }
+
+        let mut entity_names = ahash::AHashSet::new();
+        for entity in &self.entities {
+            Self::declare(registry.entities(), &mut entity_names, &entity.name).map_err(|message| invalid(&entity.name, message))?;
+            Self::validate_sprites(&entity.sprites).map_err(|message| invalid(&entity.name, message))?;
+            Self::validate_rect(entity.collision_rect, entity.rendering_rect).map_err(|message| invalid(&entity.name, message))?;
+
+            if let Some(handler) = &entity.event_handler
+                && !handlers.entities.contains_key(handler)
+            {
+                return Err(invalid(&entity.name, format!("event handler {} is not registered", handler)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn declare(table: &NameTable, names: &mut ahash::AHashSet<String>, name: &str) -> Result<(), String> {
+        let name = Registry::qualify(name).map_err(|e| e.to_string())?;
+        if table.contains(&name) || !names.insert(name) {
+            return Err("name is already declared".into());
+        }
+        Ok(())
+    }
+
+    fn validate_sprites(sprites: &[SpriteEntry]) -> Result<(), String> {
+        if sprites.is_empty() {
+            return Err("sprites must not be empty".into());
+        }
+        for (sprite_id, sprite) in sprites.iter().enumerate() {
+            if sprite.images.is_empty() {
+                return Err(format!("sprite {} has no images", sprite_id));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_rect(collision_rect: Option<RectEntry>, rendering_rect: RectEntry) -> Result<(), String> {
+        let is_valid = |rect: RectEntry| rect.min[0] <= rect.max[0] && rect.min[1] <= rect.max[1];
+
+        if collision_rect.is_some_and(|rect| !is_valid(rect)) {
+            return Err("collision rect min must not exceed max".into());
+        }
+        if !is_valid(rendering_rect) {
+            return Err("rendering rect min must not exceed max".into());
+        }
+        Ok(())
+    }
+}
+
+// loading into the builder
+
+impl ContextBuilder {
+    #[cfg(feature = "godot")]
+    pub fn load_manifest(&mut self, path: &str, handlers: &HandlerRegistry) -> Result<(), ManifestError> {
+        if !godot::classes::FileAccess::file_exists(path) {
+            return Err(ManifestError::ReadError { path: path.to_string() });
+        }
+        let source = godot::classes::FileAccess::get_file_as_string(path).to_string();
+
+        self.load_manifest_source(path, &source, handlers)
+    }
+
+    // without the godot feature or when images are skipped, sprites carry only their timing
+    pub fn load_manifest_source(&mut self, path: &str, source: &str, handlers: &HandlerRegistry) -> Result<(), ManifestError> {
+        let manifest = Manifest::parse(path, source)?;
+        manifest.validate(path, &self.registry, handlers)?;
+
+        // images are loaded up front so a missing file is reported before anything is added
+        #[cfg(feature = "godot")]
+        let skip_images = self.skip_images;
+        #[cfg_attr(not(feature = "godot"), allow(unused_variables))]
+        let load_sprites = |name: &str, sprites: &[SpriteEntry]| -> Result<Vec<SpriteInfo>, ManifestError> {
+            let mut sprite_infos = vec![];
+            for sprite in sprites {
+                #[cfg(feature = "godot")]
+                let mut images = vec![];
+                #[cfg(feature = "godot")]
+                for image in sprite.images.iter().filter(|_| !skip_images) {
+                    // bypass the resource cache so that a reload picks up edited images
+                    let image = <godot::classes::ResourceLoader as godot::obj::Singleton>::singleton()
+                        .load_ex(image)
+                        .cache_mode(godot::classes::resource_loader::CacheMode::REPLACE)
+                        .done()
+                        .and_then(|resource| resource.try_cast::<godot::classes::Image>().ok())
+                        .ok_or_else(|| ManifestError::InvalidEntry {
+                            path: path.to_string(),
+                            entry: name.to_string(),
+                            message: format!("failed to load image {}", image),
+                        })?;
+                    images.push(image);
+                }
+
+                sprite_infos.push(SpriteInfo {
+                    #[cfg(feature = "godot")]
+                    images,
+                    step_tick: sprite.step_tick,
+                    is_loop: sprite.is_loop,
+                });
+            }
+            Ok(sprite_infos)
+        };
+
+        let mut tiles = vec![];
+        for tile in manifest.tiles {
+            let sprites = load_sprites(&tile.name, &tile.sprites)?;
+            tiles.push((tile, sprites));
+        }
+        let mut blocks = vec![];
+        for block in manifest.blocks {
+            let sprites = load_sprites(&block.name, &block.sprites)?;
+            blocks.push((block, sprites));
+        }
+        let mut entities = vec![];
+        for entity in manifest.entities {
+            let sprites = load_sprites(&entity.name, &entity.sprites)?;
+            entities.push((entity, sprites));
+        }
+
+        let invalid = |entry: &str, e: RegistryError| ManifestError::InvalidEntry {
+            path: path.to_string(),
+            entry: entry.to_string(),
+            message: e.to_string(),
+        };
+
+        for tile_layer in manifest.tile_layers {
+            let name = tile_layer.name.clone();
+            self.add_tile_layer(tile_layer.name, move |_| TileLayerInfo {
+                display_name: tile_layer.display_name,
+                description: tile_layer.description,
+                z_offset: tile_layer.z_offset,
+            })
+            .map_err(|e| invalid(&name, e))?;
+        }
+
+        for (tile, sprites) in tiles {
+            let name = tile.name.clone();
+            let layer_id = self.registry.tile_layers().get(&tile.layer).map_err(|e| invalid(&name, e))?;
+            let handler_fn = tile.event_handler.map(|handler| handlers.tiles[&handler].clone());
+            self.add_tile(tile.name, move |_| TileInfo {
+                display_name: tile.display_name,
+                description: tile.description,
+                sprites,
+                collision: tile.collision,
+                layer_id,
+                event_handler: handler_fn.map(|handler_fn| handler_fn()).unwrap_or_default(),
+            })
+            .map_err(|e| invalid(&name, e))?;
+        }
+
+        for (block, sprites) in blocks {
+            let name = block.name.clone();
+            let handler_fn = block.event_handler.map(|handler| handlers.blocks[&handler].clone());
+            self.add_block(block.name, move |_| BlockInfo {
+                display_name: block.display_name,
+                description: block.description,
+                sprites,
+                y_sorting: block.y_sorting,
+                size: IVec2::from(block.size),
+                collision_rect: block.collision_rect.map(Rect2::from),
+                rendering_rect: block.rendering_rect.into(),
+                light_emission: block.light_emission,
+                light_opacity: block.light_opacity,
+                event_handler: handler_fn.map(|handler_fn| handler_fn()).unwrap_or_default(),
+            })
+            .map_err(|e| invalid(&name, e))?;
+        }
+
+        for (entity, sprites) in entities {
+            let name = entity.name.clone();
+            let handler_fn = entity.event_handler.map(|handler| handlers.entities[&handler].clone());
+            self.add_entity(entity.name, move |_| EntityInfo {
+                display_name: entity.display_name,
+                description: entity.description,
+                sprites,
+                y_sorting: entity.y_sorting,
+                collision_rect: entity.collision_rect.map(Rect2::from),
+                rendering_rect: entity.rendering_rect.into(),
+                light_emission: entity.light_emission,
+                light_opacity: entity.light_opacity,
+                event_handler: handler_fn.map(|handler_fn| handler_fn()).unwrap_or_default(),
+            })
+            .map_err(|e| invalid(&name, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestError {
+    ReadError { path: String },
+    ParseError { path: String, message: String },
+    InvalidEntry { path: String, entry: String, message: String },
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadError { path } => write!(f, "{}: failed to read manifest", path),
+            Self::ParseError { path, message } => write!(f, "{}: {}", path, message),
+            Self::InvalidEntry { path, entry, message } => write!(f, "{}: {}: {}", path, entry, message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handlers() -> HandlerRegistry {
+        let mut handlers = HandlerRegistry::new();
+        handlers.add_entity_handler("animal".into(), || EventHandler::new(()));
+        handlers
+    }
+
+    const MANIFEST: &str = r#"{
+        "tile_layers": [
+            { "name": "layer_ground", "display_name": "Ground", "z_offset": -0.0625 }
+        ],
+        "tiles": [
+            { "name": "tile_dirt", "sprites": [{ "images": ["res://dirt.webp"] }], "layer": "layer_ground" }
+        ],
+        "blocks": [
+            {
+                "name": "block_tree",
+                "sprites": [{ "images": ["res://tree_0.webp", "res://tree_1.webp"], "step_tick": 48, "is_loop": true }],
+                "y_sorting": true,
+                "size": [4, 2],
+                "collision_rect": { "min": [-0.5, 0.0], "max": [0.5, 2.0] },
+                "rendering_rect": { "min": [-2.0, 0.0], "max": [2.0, 6.0] }
+            }
+        ],
+        "entities": [
+            {
+                "name": "entity_pig",
+                "sprites": [{ "images": ["res://pig.webp"] }],
+                "rendering_rect": { "min": [-1.0, 0.0], "max": [1.0, 2.0] },
+                "event_handler": "animal"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn valid_manifest() {
+        let manifest = Manifest::parse("content.json", MANIFEST).unwrap();
+        manifest.validate("content.json", &Registry::new(), &handlers()).unwrap();
+
+        assert_eq!(manifest.tiles.len(), 1);
+        assert_eq!(manifest.blocks[0].size, [4, 2]);
+        assert_eq!(manifest.entities[0].event_handler.as_deref(), Some("animal"));
+    }
+
+    #[test]
+    fn parse_error() {
+        let error = Manifest::parse("content.json", r#"{ "tiles": [ { "name": 1 } ] }"#).unwrap_err();
+        let ManifestError::ParseError { path, message } = error else {
+            panic!("unexpected error");
+        };
+        assert_eq!(path, "content.json");
+        assert!(message.contains("line 1"));
+    }
+
+    #[test]
+    fn invalid_entry() {
+        let manifest = Manifest::parse("content.json", &MANIFEST.replace("\"animal\"", "\"unknown\"")).unwrap();
+        let error = manifest.validate("content.json", &Registry::new(), &handlers()).unwrap_err();
+        assert_eq!(error, ManifestError::InvalidEntry {
+            path: "content.json".into(),
+            entry: "entity_pig".into(),
+            message: "event handler unknown is not registered".into(),
+        });
+
+        let manifest = Manifest::parse("content.json", &MANIFEST.replace("\"layer\": \"layer_ground\"", "\"layer\": \"layer_sky\"")).unwrap();
+        let error = manifest.validate("content.json", &Registry::new(), &handlers()).unwrap_err();
+        assert_eq!(error.to_string(), "content.json: tile_dirt: layer layer_sky is not declared");
+
+        let manifest = Manifest::parse("content.json", &MANIFEST.replace("[4, 2]", "[0, 2]")).unwrap();
+        let error = manifest.validate("content.json", &Registry::new(), &handlers()).unwrap_err();
+        assert_eq!(error.to_string(), "content.json: block_tree: size must be positive");
+
+        let mut registry = Registry::new();
+        registry.tiles_mut().insert("tile_dirt").unwrap();
+        let manifest = Manifest::parse("content.json", MANIFEST).unwrap();
+        let error = manifest.validate("content.json", &registry, &handlers()).unwrap_err();
+        assert_eq!(error.to_string(), "content.json: tile_dirt: name is already declared");
+    }
+
+    // images need the engine, so they are skipped to load the whole manifest in tests
+    #[test]
+    fn load_manifest_headless() {
+        let mut builder = ContextBuilder::new();
+        builder.set_skip_images(true);
+        builder.load_manifest_source("content.json", MANIFEST, &handlers()).unwrap();
+        let mut context = builder.build_headless();
+
+        let archetype_id = context.registry.blocks().get("block_tree").unwrap();
+        let block_id = context.dataflow.insert_block(dataflow::Block { archetype_id, ..Default::default() }).unwrap();
+        assert_eq!(context.dataflow.get_block(block_id).unwrap().archetype_id, archetype_id);
+        assert!(context.dataflow.find_block_collision_point(Vec2::new(0.0, 1.0)).next().is_some());
+    }
+}
@@ -0,0 +1,92 @@
+use glam::*;
+
+use crate::dataflow;
+
+use super::*;
+
+// keeps a replica dataflow in sync with the server, the replica should run no systems or handlers of its own
+pub struct Client {
+    connection: Box<dyn Connection>,
+    client_id: Option<ClientId>,
+    tick: Option<u64>,
+    player: Option<dataflow::EntityId>,
+}
+
+impl Client {
+    pub fn new(connection: impl Connection + 'static) -> Self {
+        Self {
+            connection: Box::new(connection),
+            client_id: None,
+            tick: None,
+            player: None,
+        }
+    }
+
+    #[inline]
+    pub fn client_id(&self) -> Option<ClientId> {
+        self.client_id
+    }
+
+    // tick of the server at the last applied message
+    #[inline]
+    pub fn tick(&self) -> Option<u64> {
+        self.tick
+    }
+
+    // id of the player entity of this client in the replica
+    #[inline]
+    pub fn player(&self) -> Option<dataflow::EntityId> {
+        self.player
+    }
+
+    pub fn send_input(&mut self, input: Vec2) -> Result<(), NetError> {
+        self.connection.send(&encode(&ClientMessage::Input { input: input.to_array() }))
+    }
+
+    // apply every message received so far to the replica
+    pub fn poll(&mut self, dataflow: &mut dataflow::Dataflow) -> Result<(), NetError> {
+        while let Some(frame) = self.connection.recv()? {
+            match decode::<ServerMessage>(&frame)? {
+                ServerMessage::Welcome { client_id } => {
+                    self.client_id = Some(client_id);
+                }
+                ServerMessage::Snapshot { tick, chunks, player } => {
+                    let mut stale = vec![];
+                    stale.extend(dataflow.get_tile_chunk_coords().map(|chunk_coord| (ChunkKind::Tile, chunk_coord)));
+                    stale.extend(dataflow.get_block_chunk_coords().map(|chunk_coord| (ChunkKind::Block, chunk_coord)));
+                    stale.extend(dataflow.get_entity_chunk_coords().map(|chunk_coord| (ChunkKind::Entity, chunk_coord)));
+                    for (kind, chunk_coord) in stale {
+                        let chunk = ChunkSnapshot { kind, chunk_coord: chunk_coord.to_array(), version: 0, objects: vec![] };
+                        chunk.clear(dataflow)?;
+                    }
+
+                    Self::apply(dataflow, &chunks)?;
+                    self.tick = Some(tick);
+                    self.player = Self::find_player(dataflow, player);
+                }
+                ServerMessage::Delta { tick, chunks, player } => {
+                    Self::apply(dataflow, &chunks)?;
+                    self.tick = Some(tick);
+                    self.player = Self::find_player(dataflow, player);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_player(dataflow: &dataflow::Dataflow, location: Option<PlayerLocation>) -> Option<dataflow::EntityId> {
+        let (chunk_coord, index) = location?;
+        let chunk = dataflow.get_entity_chunk(IVec2::from_array(chunk_coord)).ok()?;
+        chunk.ids.get(index as usize).copied()
+    }
+
+    fn apply(dataflow: &mut dataflow::Dataflow, chunks: &[ChunkSnapshot]) -> Result<(), NetError> {
+        for chunk in chunks {
+            chunk.clear(dataflow)?;
+        }
+        for chunk in chunks {
+            chunk.fill(dataflow)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+pub use client::*;
+pub use protocol::*;
+pub use server::*;
+pub use transport::*;
+
+mod client;
+mod protocol;
+mod server;
+mod transport;
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetError {
+    IoError(String),
+    DecodeError(String),
+    DataflowError(crate::dataflow::DataflowError),
+    InvalidFrame,
+    Closed,
+}
+
+impl std::fmt::Display for NetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(message) => write!(f, "io error: {}", message),
+            Self::DecodeError(message) => write!(f, "decode error: {}", message),
+            Self::DataflowError(e) => e.fmt(f),
+            Self::InvalidFrame => write!(f, "invalid frame error"),
+            Self::Closed => write!(f, "closed error"),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<std::io::Error> for NetError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value.to_string())
+    }
+}
+
+impl From<crate::dataflow::DataflowError> for NetError {
+    fn from(value: crate::dataflow::DataflowError) -> Self {
+        Self::DataflowError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use glam::*;
+
+    use crate::{dataflow, Rect2};
+
+    fn make_dataflow() -> dataflow::Dataflow {
+        dataflow::TestDataflowBuilder::new().tile(false).block(IVec2::new(2, 1)).entity().build()
+    }
+
+    fn contents(dataflow: &dataflow::Dataflow) -> (Vec<IVec2>, Vec<IVec2>, Vec<Vec2>) {
+        let rect = crate::IRect2::new(IVec2::splat(-256), IVec2::splat(256));
+        let mut tiles = dataflow.find_tile_with_rect(rect).map(|(id, _)| dataflow.get_tile(*id).unwrap().coord).collect::<Vec<_>>();
+        let mut blocks = dataflow.find_block_with_rect(rect).map(|(id, _)| dataflow.get_block(*id).unwrap().coord).collect::<Vec<_>>();
+        let mut entities = dataflow
+            .find_entity_with_hint_rect(Rect2::new(rect.min.as_vec2(), rect.max.as_vec2()))
+            .map(|(id, _)| dataflow.get_entity(*id).unwrap().coord)
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|coord| coord.to_array());
+        tiles.dedup();
+        blocks.sort_by_key(|coord| coord.to_array());
+        blocks.dedup();
+        entities.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        entities.dedup();
+        (tiles, blocks, entities)
+    }
+
+    #[test]
+    fn replicate_over_loopback() {
+        let (listener, connector) = LoopbackListener::new();
+        let mut server = Server::new(listener);
+        let mut server_dataflow = make_dataflow();
+        let mut client = Client::new(connector.connect().unwrap());
+        let mut client_dataflow = make_dataflow();
+
+        // stale content of the replica is dropped by the first snapshot
+        client_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(100, 100), ..Default::default() }).unwrap();
+
+        server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(-40, 3), variant: 2, ..Default::default() }).unwrap();
+        let block_id = server_dataflow.insert_block(dataflow::Block { coord: IVec2::new(30, 0), ..Default::default() }).unwrap();
+        server_dataflow.insert_entity(dataflow::Entity { coord: Vec2::new(1.0, 2.0), ..Default::default() }).unwrap();
+        let entity_id = server_dataflow.insert_entity(dataflow::Entity { coord: Vec2::new(1.5, 2.25), ..Default::default() }).unwrap();
+        let find_player = |client_id| (client_id == 1).then_some(entity_id);
+
+        assert_eq!(server.poll(), vec![ServerEvent::Connected(1)]);
+        server.replicate(&server_dataflow, find_player);
+        client.poll(&mut client_dataflow).unwrap();
+        assert_eq!(client.client_id(), Some(1));
+        assert_eq!(contents(&client_dataflow), contents(&server_dataflow));
+        assert_eq!(client_dataflow.get_entity(client.player().unwrap()).unwrap().coord, Vec2::new(1.5, 2.25));
+
+        // the block crosses into the next chunk
+        server_dataflow.move_block(block_id, IVec2::new(33, 0)).unwrap();
+        server_dataflow.move_entity(entity_id, Vec2::new(-70.0, 2.0)).unwrap();
+        server.replicate(&server_dataflow, find_player);
+        client.poll(&mut client_dataflow).unwrap();
+        assert_eq!(contents(&client_dataflow), contents(&server_dataflow));
+        assert_eq!(client_dataflow.get_entity(client.player().unwrap()).unwrap().coord, Vec2::new(-70.0, 2.0));
+
+        client.send_input(Vec2::new(0.0, -1.0)).unwrap();
+        assert_eq!(server.poll(), vec![ServerEvent::Input(1, Vec2::new(0.0, -1.0))]);
+
+        drop(client);
+        assert_eq!(server.poll(), vec![ServerEvent::Disconnected(1)]);
+        assert_eq!(server.client_ids().count(), 0);
+    }
+
+    #[test]
+    fn delta_with_dirty_chunks() {
+        let mut server_dataflow = make_dataflow();
+        let (listener, connector) = LoopbackListener::new();
+        let mut server = Server::new(listener);
+        let mut connection = connector.connect().unwrap();
+
+        server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(0, 0), ..Default::default() }).unwrap();
+        server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(40, 0), ..Default::default() }).unwrap();
+        server.poll();
+        server.replicate(&server_dataflow, |_| None);
+        let tile_id = server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(41, 0), ..Default::default() }).unwrap();
+        server.replicate(&server_dataflow, |_| None);
+        server_dataflow.modify_tile_variant(tile_id, 1).unwrap();
+        server.replicate(&server_dataflow, |_| None);
+        server.replicate(&server_dataflow, |_| None);
+
+        let mut messages = vec![];
+        while let Some(frame) = connection.recv().unwrap() {
+            messages.push(decode::<ServerMessage>(&frame).unwrap());
+        }
+        assert_eq!(messages.len(), 5);
+        assert!(matches!(&messages[1], ServerMessage::Snapshot { chunks, .. } if chunks.len() == 2));
+        assert!(matches!(&messages[2], ServerMessage::Delta { chunks, .. } if chunks.len() == 1 && chunks[0].objects.len() == 2));
+        assert!(matches!(&messages[3], ServerMessage::Delta { chunks, .. } if chunks.len() == 1 && chunks[0].chunk_coord == [1, 0]));
+        assert!(matches!(&messages[4], ServerMessage::Delta { chunks, .. } if chunks.is_empty()));
+    }
+
+    #[test]
+    fn replicate_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_address().unwrap().to_string();
+        let mut server = Server::new(listener);
+        let mut server_dataflow = make_dataflow();
+
+        // the client runs as a separate instance with its own dataflow
+        let handle = std::thread::spawn(move || {
+            let mut client = Client::new(TcpConnection::connect(&address).unwrap());
+            let mut client_dataflow = make_dataflow();
+            client.send_input(Vec2::new(1.0, 0.0)).unwrap();
+
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+            while client.tick().is_none() || client_dataflow.find_entity_with_hint_point(Vec2::new(4.5, 4.5)).count() == 0 {
+                assert!(std::time::Instant::now() < deadline, "timed out");
+                client.poll(&mut client_dataflow).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            contents(&client_dataflow)
+        });
+
+        for x in -40..40 {
+            server_dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(x, 0), ..Default::default() }).unwrap();
+        }
+        let entity_id = server_dataflow.insert_entity(dataflow::Entity { coord: Vec2::new(0.5, 0.5), ..Default::default() }).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let mut input = None;
+        while input.is_none() {
+            assert!(std::time::Instant::now() < deadline, "timed out");
+            for event in server.poll() {
+                if let ServerEvent::Input(client_id, value) = event {
+                    input = Some((client_id, value));
+                }
+            }
+            server.replicate(&server_dataflow, |_| None);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(input, Some((1, Vec2::new(1.0, 0.0))));
+
+        server_dataflow.move_entity(entity_id, Vec2::new(4.0, 4.0)).unwrap();
+        while !handle.is_finished() {
+            assert!(std::time::Instant::now() < deadline, "timed out");
+            server.poll();
+            server.replicate(&server_dataflow, |_| None);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(handle.join().unwrap(), contents(&server_dataflow));
+    }
+}
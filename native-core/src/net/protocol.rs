@@ -0,0 +1,131 @@
+use glam::*;
+
+use crate::dataflow;
+
+use super::*;
+
+pub type ClientId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ChunkKind {
+    Tile,
+    Block,
+    Entity,
+}
+
+// archetype id, coordinate, variant and tick of an object,
+// cell coordinates are exact as floats within the playable range
+pub type ObjectState = (u16, [f32; 2], u16, u32);
+
+// the whole content of one chunk of one field, it replaces what the client has in that chunk
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkSnapshot {
+    pub kind: ChunkKind,
+    pub chunk_coord: [i32; 2],
+    pub version: u64,
+    pub objects: Vec<ObjectState>,
+}
+
+// entity chunk coordinate and index in that chunk of the player of the receiving client,
+// the replica fills its chunks in the sent order, so its copy of the player sits at the same place
+pub type PlayerLocation = ([i32; 2], u32);
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ServerMessage {
+    Welcome { client_id: ClientId },
+    // every chunk, the client drops anything it had before
+    Snapshot { tick: u64, chunks: Vec<ChunkSnapshot>, player: Option<PlayerLocation> },
+    // only the chunks whose version changed since the last message to this client
+    Delta { tick: u64, chunks: Vec<ChunkSnapshot>, player: Option<PlayerLocation> },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ClientMessage {
+    Input { input: [f32; 2] },
+}
+
+pub fn encode<T>(message: &T) -> Vec<u8> where T: serde::Serialize {
+    serde_json::to_vec(message).unwrap()
+}
+
+pub fn decode<'a, T>(frame: &'a [u8]) -> Result<T, NetError> where T: serde::Deserialize<'a> {
+    serde_json::from_slice(frame).map_err(|e| NetError::DecodeError(e.to_string()))
+}
+
+impl ChunkSnapshot {
+    // None if the chunk was never created
+    pub fn capture(dataflow: &dataflow::Dataflow, kind: ChunkKind, chunk_coord: IVec2) -> Option<Self> {
+        let (version, objects) = match kind {
+            ChunkKind::Tile => {
+                let chunk = dataflow.get_tile_chunk(chunk_coord).ok()?;
+                let objects = chunk.tiles.iter().map(|tile| (tile.archetype_id, tile.coord.as_vec2().to_array(), tile.variant, tile.tick)).collect();
+                (chunk.version, objects)
+            }
+            ChunkKind::Block => {
+                let chunk = dataflow.get_block_chunk(chunk_coord).ok()?;
+                let objects = chunk.blocks.iter().map(|block| (block.archetype_id, block.coord.as_vec2().to_array(), block.variant, block.tick)).collect();
+                (chunk.version, objects)
+            }
+            ChunkKind::Entity => {
+                let chunk = dataflow.get_entity_chunk(chunk_coord).ok()?;
+                let objects = chunk.entities.iter().map(|entity| (entity.archetype_id, entity.coord.to_array(), entity.variant, entity.tick)).collect();
+                (chunk.version, objects)
+            }
+        };
+
+        Some(Self {
+            kind,
+            chunk_coord: chunk_coord.to_array(),
+            version,
+            objects,
+        })
+    }
+
+    // a message is applied by clearing all of its chunks before filling any,
+    // so an object that crossed into another chunk never meets its old copy
+
+    pub fn clear(&self, dataflow: &mut dataflow::Dataflow) -> Result<(), dataflow::DataflowError> {
+        let chunk_coord = IVec2::from_array(self.chunk_coord);
+
+        match self.kind {
+            ChunkKind::Tile => {
+                let ids = dataflow.get_tile_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+                for id in ids {
+                    dataflow.remove_til(id)?;
+                }
+            }
+            ChunkKind::Block => {
+                let ids = dataflow.get_block_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+                for id in ids {
+                    dataflow.remove_block(id)?;
+                }
+            }
+            ChunkKind::Entity => {
+                let ids = dataflow.get_entity_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+                for id in ids {
+                    dataflow.remove_entity(id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ids of the replica are its own and unrelated to the ids of the server
+    pub fn fill(&self, dataflow: &mut dataflow::Dataflow) -> Result<(), dataflow::DataflowError> {
+        for &(archetype_id, coord, variant, tick) in &self.objects {
+            let coord = Vec2::from_array(coord);
+            match self.kind {
+                ChunkKind::Tile => {
+                    dataflow.insert_tile(dataflow::Tile { coord: coord.as_ivec2(), archetype_id, variant, tick })?;
+                }
+                ChunkKind::Block => {
+                    dataflow.insert_block(dataflow::Block { coord: coord.as_ivec2(), archetype_id, variant, tick })?;
+                }
+                ChunkKind::Entity => {
+                    dataflow.insert_entity(dataflow::Entity { coord, archetype_id, variant, tick })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
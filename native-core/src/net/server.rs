@@ -0,0 +1,132 @@
+use glam::*;
+
+use crate::dataflow;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerEvent {
+    Connected(ClientId),
+    Disconnected(ClientId),
+    Input(ClientId, Vec2),
+}
+
+struct RemoteClient {
+    id: ClientId,
+    connection: Box<dyn Connection>,
+    // versions of the chunks as last sent, empty until the first snapshot
+    versions: Option<ahash::AHashMap<(ChunkKind, IVec2), u64>>,
+}
+
+// the server owns the authoritative dataflow, clients only send inputs and receive chunks
+pub struct Server {
+    listener: Box<dyn Listener>,
+    clients: Vec<RemoteClient>,
+    next_client_id: ClientId,
+}
+
+impl Server {
+    // ids start at 1, 0 is left for the player of the server itself
+    pub fn new(listener: impl Listener + 'static) -> Self {
+        Self {
+            listener: Box::new(listener),
+            clients: vec![],
+            next_client_id: 1,
+        }
+    }
+
+    pub fn client_ids(&self) -> impl Iterator<Item = ClientId> {
+        self.clients.iter().map(|client| client.id)
+    }
+
+    // accept new clients and collect their inputs in arrival order
+    pub fn poll(&mut self) -> Vec<ServerEvent> {
+        let mut events = vec![];
+
+        while let Ok(Some(mut connection)) = self.listener.accept() {
+            let id = self.next_client_id;
+            self.next_client_id += 1;
+
+            if connection.send(&encode(&ServerMessage::Welcome { client_id: id })).is_ok() {
+                self.clients.push(RemoteClient { id, connection, versions: None });
+                events.push(ServerEvent::Connected(id));
+            }
+        }
+
+        self.clients.retain_mut(|client| {
+            loop {
+                let message = match client.connection.recv() {
+                    Ok(Some(frame)) => decode::<ClientMessage>(&frame),
+                    Ok(None) => return true,
+                    Err(e) => Err(e),
+                };
+
+                match message {
+                    Ok(ClientMessage::Input { input }) => events.push(ServerEvent::Input(client.id, Vec2::from_array(input))),
+                    Err(_) => {
+                        events.push(ServerEvent::Disconnected(client.id));
+                        return false;
+                    }
+                }
+            }
+        });
+
+        events
+    }
+
+    // a new client gets every chunk, the others only the chunks whose version changed since the last call,
+    // find_player gives the player entity of a client if it has one
+    pub fn replicate(&mut self, dataflow: &dataflow::Dataflow, find_player: impl Fn(ClientId) -> Option<dataflow::EntityId>) {
+        let mut current = ahash::AHashMap::new();
+        for chunk_coord in dataflow.get_tile_chunk_coords() {
+            current.insert((ChunkKind::Tile, chunk_coord), dataflow.get_tile_chunk(chunk_coord).unwrap().version);
+        }
+        for chunk_coord in dataflow.get_block_chunk_coords() {
+            current.insert((ChunkKind::Block, chunk_coord), dataflow.get_block_chunk(chunk_coord).unwrap().version);
+        }
+        for chunk_coord in dataflow.get_entity_chunk_coords() {
+            current.insert((ChunkKind::Entity, chunk_coord), dataflow.get_entity_chunk(chunk_coord).unwrap().version);
+        }
+
+        let capture = |(kind, chunk_coord): (ChunkKind, IVec2)| {
+            ChunkSnapshot::capture(dataflow, kind, chunk_coord).unwrap_or(ChunkSnapshot {
+                kind,
+                chunk_coord: chunk_coord.to_array(),
+                version: 0,
+                objects: vec![],
+            })
+        };
+
+        let tick = dataflow.get_tick();
+        let mut snapshot = None;
+        self.clients.retain_mut(|client| {
+            let player = find_player(client.id).and_then(|id| Self::locate_player(dataflow, id));
+            let message = match &client.versions {
+                None => {
+                    let chunks = snapshot.get_or_insert_with(|| current.keys().copied().map(capture).collect::<Vec<_>>());
+                    ServerMessage::Snapshot { tick, chunks: chunks.clone(), player }
+                }
+                Some(versions) => {
+                    // chunks that vanished are sent empty
+                    let chunks = current
+                        .iter()
+                        .filter(|(key, version)| versions.get(key) != Some(version))
+                        .map(|(key, _)| *key)
+                        .chain(versions.keys().filter(|key| !current.contains_key(key)).copied())
+                        .map(capture)
+                        .collect();
+                    ServerMessage::Delta { tick, chunks, player }
+                }
+            };
+
+            client.versions = Some(current.clone());
+            client.connection.send(&encode(&message)).is_ok()
+        });
+    }
+
+    fn locate_player(dataflow: &dataflow::Dataflow, id: dataflow::EntityId) -> Option<PlayerLocation> {
+        let chunk_coord = dataflow.find_entity_chunk_coord(dataflow.get_entity(id).ok()?.coord);
+        let index = dataflow.get_entity_chunk(chunk_coord).ok()?.ids.iter().position(|&other| other == id)?;
+        Some((chunk_coord.to_array(), index as u32))
+    }
+}
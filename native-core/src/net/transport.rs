@@ -0,0 +1,185 @@
+use super::*;
+
+// frames are opaque byte messages, every call is non-blocking
+
+pub trait Connection {
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError>;
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, NetError>;
+}
+
+pub trait Listener {
+    fn accept(&mut self) -> Result<Option<Box<dyn Connection>>, NetError>;
+}
+
+// loopback, both ends may live on different threads
+
+pub struct LoopbackConnection {
+    tx: std::sync::mpsc::Sender<Vec<u8>>,
+    rx: std::sync::mpsc::Receiver<Vec<u8>>,
+}
+
+impl LoopbackConnection {
+    pub fn pair() -> (Self, Self) {
+        let (a_tx, a_rx) = std::sync::mpsc::channel();
+        let (b_tx, b_rx) = std::sync::mpsc::channel();
+        (Self { tx: a_tx, rx: b_rx }, Self { tx: b_tx, rx: a_rx })
+    }
+}
+
+impl Connection for LoopbackConnection {
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        self.tx.send(frame.to_vec()).map_err(|_| NetError::Closed)
+    }
+
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, NetError> {
+        match self.rx.try_recv() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(std::sync::mpsc::TryRecvError::Empty) => Ok(None),
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err(NetError::Closed),
+        }
+    }
+}
+
+pub struct LoopbackListener {
+    rx: std::sync::mpsc::Receiver<LoopbackConnection>,
+}
+
+#[derive(Clone)]
+pub struct LoopbackConnector {
+    tx: std::sync::mpsc::Sender<LoopbackConnection>,
+}
+
+impl LoopbackListener {
+    pub fn new() -> (Self, LoopbackConnector) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        (Self { rx }, LoopbackConnector { tx })
+    }
+}
+
+impl Listener for LoopbackListener {
+    fn accept(&mut self) -> Result<Option<Box<dyn Connection>>, NetError> {
+        match self.rx.try_recv() {
+            Ok(connection) => Ok(Some(Box::new(connection))),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl LoopbackConnector {
+    pub fn connect(&self) -> Result<LoopbackConnection, NetError> {
+        let (local, remote) = LoopbackConnection::pair();
+        self.tx.send(remote).map_err(|_| NetError::Closed)?;
+        Ok(local)
+    }
+}
+
+// tcp, frames are prefixed with their length as little endian u32
+
+pub struct TcpConnection {
+    stream: std::net::TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl TcpConnection {
+    pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+    pub fn connect(address: &str) -> Result<Self, NetError> {
+        let stream = std::net::TcpStream::connect(address)?;
+        Self::from_stream(stream)
+    }
+
+    fn from_stream(stream: std::net::TcpStream) -> Result<Self, NetError> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            incoming: vec![],
+            outgoing: vec![],
+            closed: false,
+        })
+    }
+
+    // writes as much of the pending output as the socket takes
+    fn flush(&mut self) -> Result<(), NetError> {
+        while !self.outgoing.is_empty() {
+            match std::io::Write::write(&mut self.stream, &self.outgoing) {
+                Ok(0) => return Err(NetError::Closed),
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Connection for TcpConnection {
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        self.outgoing.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.outgoing.extend_from_slice(frame);
+        self.flush()
+    }
+
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, NetError> {
+        self.flush()?;
+
+        let mut buffer = [0u8; 16 * 1024];
+        while !self.closed {
+            match std::io::Read::read(&mut self.stream, &mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(len) => self.incoming.extend_from_slice(&buffer[..len]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // frames that arrived before the peer closed are still delivered
+        let len = match self.incoming.first_chunk::<4>() {
+            Some(header) => u32::from_le_bytes(*header) as usize,
+            None if self.closed => return Err(NetError::Closed),
+            None => return Ok(None),
+        };
+        if len > Self::MAX_FRAME_LEN {
+            return Err(NetError::InvalidFrame);
+        }
+        if self.incoming.len() < 4 + len {
+            return if self.closed { Err(NetError::Closed) } else { Ok(None) };
+        }
+
+        let frame = self.incoming[4..4 + len].to_vec();
+        self.incoming.drain(..4 + len);
+        Ok(Some(frame))
+    }
+}
+
+pub struct TcpListener {
+    listener: std::net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind(address: &str) -> Result<Self, NetError> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener })
+    }
+
+    pub fn local_address(&self) -> Result<std::net::SocketAddr, NetError> {
+        Ok(self.listener.local_addr()?)
+    }
+}
+
+impl Listener for TcpListener {
+    fn accept(&mut self) -> Result<Option<Box<dyn Connection>>, NetError> {
+        match self.listener.accept() {
+            Ok((stream, _)) => Ok(Some(Box::new(TcpConnection::from_stream(stream)?))),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use glam::*;
+
+use crate::{dataflow, Registry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickKind {
+    Tile,
+    Block,
+    Entity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickHit {
+    pub kind: PickKind,
+    pub id: u64,
+    pub name: String,
+    pub display_name: String,
+}
+
+// depth of a sprite at the point, y-sorted sprites lean towards the camera from their base
+#[inline]
+fn depth(y_sorting: bool, hint_rect: crate::Rect2, point: Vec2) -> f32 {
+    if y_sorting { point.y - hint_rect.min.y } else { 0.0 }
+}
+
+// hits in draw order from front to back: entities, blocks, then the top-most tile
+pub fn pick(registry: &Registry, dataflow: &dataflow::Dataflow, point: Vec2) -> Vec<PickHit> {
+    let info = dataflow.get_detached_info();
+    let mut hits = vec![];
+
+    let mut entities = dataflow
+        .find_entity_with_hint_point(point)
+        .map(|(id, data)| {
+            let entity = dataflow.get_entity(*id).unwrap();
+            let archetype = dataflow.get_entity_archetype(entity.archetype_id).unwrap();
+            (*id, entity.archetype_id, depth(archetype.y_sorting, data.hint_rect, point))
+        })
+        .collect::<Vec<_>>();
+    entities.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+    entities.dedup_by_key(|(id, _, _)| *id);
+    for (id, archetype_id, _) in entities {
+        hits.push(PickHit {
+            kind: PickKind::Entity,
+            id,
+            name: registry.entities().get_name(archetype_id).unwrap_or_default().to_string(),
+            display_name: info.entity_field.entities[archetype_id as usize].display_name.clone(),
+        });
+    }
+
+    let mut blocks = dataflow
+        .find_block_with_hint_point(point)
+        .map(|(id, data)| {
+            let block = dataflow.get_block(*id).unwrap();
+            let archetype = dataflow.get_block_archetype(block.archetype_id).unwrap();
+            (*id, block.archetype_id, depth(archetype.y_sorting, data.hint_rect, point))
+        })
+        .collect::<Vec<_>>();
+    blocks.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+    blocks.dedup_by_key(|(id, _, _)| *id);
+    for (id, archetype_id, _) in blocks {
+        hits.push(PickHit {
+            kind: PickKind::Block,
+            id,
+            name: registry.blocks().get_name(archetype_id).unwrap_or_default().to_string(),
+            display_name: info.block_field.blocks[archetype_id as usize].display_name.clone(),
+        });
+    }
+
+    if let Some((id, _)) = dataflow.find_tile_with_point(point.floor().as_ivec2()) {
+        let tile = dataflow.get_tile(*id).unwrap();
+        hits.push(PickHit {
+            kind: PickKind::Tile,
+            id: *id,
+            name: registry.tiles().get_name(tile.archetype_id).unwrap_or_default().to_string(),
+            display_name: info.tile_field.tiles[tile.archetype_id as usize].display_name.clone(),
+        });
+    }
+
+    hits
+}
+
+#[cfg(feature = "godot")]
+impl crate::Context {
+    #[inline]
+    pub fn pick(&self, point: Vec2) -> Vec<PickHit> {
+        pick(&self.registry, &self.dataflow, point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Rect2;
+
+    fn make_registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.tile_layers_mut().insert("layer").unwrap();
+        registry.tiles_mut().insert("tile_grass").unwrap();
+        registry.blocks_mut().insert("block_rock").unwrap();
+        registry.blocks_mut().insert("block_tree").unwrap();
+        registry.entities_mut().insert("entity_bird").unwrap();
+        registry
+    }
+
+    fn make_dataflow() -> dataflow::Dataflow {
+        let block = |display_name: &str, y_sorting: bool| dataflow::BlockInfo {
+            display_name: display_name.into(),
+            description: "".into(),
+            size: IVec2::new(1, 1),
+            collision_rect: None,
+            hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 2.0)),
+            y_sorting,
+            light_emission: 0,
+            light_opacity: 0,
+        };
+
+        dataflow::Dataflow::new(dataflow::DataflowInfo {
+            tile_field: dataflow::TileFieldInfo {
+                tiles: vec![dataflow::TileInfo {
+                    display_name: "Grass".into(),
+                    description: "".into(),
+                    collision: false,
+                    layer_id: 0,
+                }],
+                layers: vec![dataflow::TileLayerInfo {
+                    display_name: "Layer".into(),
+                    description: "".into(),
+                }],
+            },
+            block_field: dataflow::BlockFieldInfo {
+                blocks: vec![block("Rock", false), block("Tree", true)],
+            },
+            entity_field: dataflow::EntityFieldInfo {
+                entities: vec![dataflow::EntityInfo {
+                    display_name: "Bird".into(),
+                    description: "".into(),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
+                    y_sorting: true,
+                    light_emission: 0,
+                    light_opacity: 0,
+                }],
+            },
+            fluid_field: dataflow::FluidFieldInfo { fluids: vec![] },
+            light_field: dataflow::LightFieldInfo { ambient: 0 },
+            event_handlers: dataflow::EventHandlers {
+                tiles: vec![std::rc::Rc::new(())],
+                blocks: vec![std::rc::Rc::new(()), std::rc::Rc::new(())],
+                entities: vec![std::rc::Rc::new(())],
+            },
+        })
+    }
+
+    #[test]
+    fn pick_in_draw_order() {
+        let registry = make_registry();
+        let mut dataflow = make_dataflow();
+
+        let tile_id = dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(0, 1), archetype_id: 0, ..Default::default() }).unwrap();
+        let rock_id = dataflow.insert_block(dataflow::Block { coord: IVec2::new(0, 0), archetype_id: 0, ..Default::default() }).unwrap();
+        let tree_id = dataflow.insert_block(dataflow::Block { coord: IVec2::new(0, 1), archetype_id: 1, ..Default::default() }).unwrap();
+        let bird_id = dataflow.insert_entity(dataflow::Entity { coord: Vec2::new(0.0, 1.0), archetype_id: 0, ..Default::default() }).unwrap();
+
+        let hits = pick(&registry, &dataflow, Vec2::new(0.5, 1.5));
+        let ids = hits.iter().map(|hit| (hit.kind, hit.id)).collect::<Vec<_>>();
+        assert_eq!(ids, vec![
+            (PickKind::Entity, bird_id),
+            (PickKind::Block, tree_id),
+            (PickKind::Block, rock_id),
+            (PickKind::Tile, tile_id),
+        ]);
+
+        assert_eq!(hits[0].name, "core:entity_bird");
+        assert_eq!(hits[0].display_name, "Bird");
+        assert_eq!(hits[3].name, "core:tile_grass");
+
+        assert!(pick(&registry, &dataflow, Vec2::new(5.5, 5.5)).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+// name table for a single kind of archetype, ids are assigned in insertion order
+
+#[derive(Debug, Clone, Default)]
+pub struct NameTable {
+    ids: ahash::AHashMap<String, u16>,
+    names: Vec<String>,
+}
+
+impl NameTable {
+    pub fn insert(&mut self, name: &str) -> Result<u16, RegistryError> {
+        let name = Registry::qualify(name)?;
+        if self.ids.contains_key(&name) {
+            return Err(RegistryError::AlreadyExist(name));
+        }
+
+        assert!(self.names.len() <= u16::MAX as usize, "capacity overflow");
+        let id = self.names.len() as u16;
+        self.ids.insert(name.clone(), id);
+        self.names.push(name);
+        Ok(id)
+    }
+
+    pub fn get(&self, name: &str) -> Result<u16, RegistryError> {
+        let name = Registry::qualify(name)?;
+        self.ids.get(&name).copied().ok_or(RegistryError::NotFound(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_ok()
+    }
+
+    pub fn get_name(&self, id: u16) -> Result<&str, RegistryError> {
+        self.names.get(id as usize).map(String::as_str).ok_or(RegistryError::InvalidId(id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.names.iter().enumerate().map(|(id, name)| (id as u16, name.as_str()))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+// registry
+
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    tile_layers: NameTable,
+    tiles: NameTable,
+    blocks: NameTable,
+    entities: NameTable,
+    fluids: NameTable,
+}
+
+impl Registry {
+    // names without a namespace belong to this one
+    pub const DEFAULT_NAMESPACE: &str = "core";
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // "name" and "core:name" refer to the same entry
+    pub fn qualify(name: &str) -> Result<String, RegistryError> {
+        let (namespace, path) = name.split_once(':').unwrap_or((Self::DEFAULT_NAMESPACE, name));
+
+        let is_valid = |value: &str| {
+            !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '/')
+        };
+        if !is_valid(namespace) || !is_valid(path) {
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+
+        Ok(format!("{}:{}", namespace, path))
+    }
+
+    #[inline]
+    pub fn tile_layers(&self) -> &NameTable {
+        &self.tile_layers
+    }
+
+    #[inline]
+    pub fn tiles(&self) -> &NameTable {
+        &self.tiles
+    }
+
+    #[inline]
+    pub fn blocks(&self) -> &NameTable {
+        &self.blocks
+    }
+
+    #[inline]
+    pub fn entities(&self) -> &NameTable {
+        &self.entities
+    }
+
+    #[inline]
+    pub fn fluids(&self) -> &NameTable {
+        &self.fluids
+    }
+
+    #[inline]
+    pub(crate) fn tile_layers_mut(&mut self) -> &mut NameTable {
+        &mut self.tile_layers
+    }
+
+    #[inline]
+    pub(crate) fn tiles_mut(&mut self) -> &mut NameTable {
+        &mut self.tiles
+    }
+
+    #[inline]
+    pub(crate) fn blocks_mut(&mut self) -> &mut NameTable {
+        &mut self.blocks
+    }
+
+    #[inline]
+    pub(crate) fn entities_mut(&mut self) -> &mut NameTable {
+        &mut self.entities
+    }
+
+    #[inline]
+    pub(crate) fn fluids_mut(&mut self) -> &mut NameTable {
+        &mut self.fluids
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    InvalidName(String),
+    AlreadyExist(String),
+    NotFound(String),
+    InvalidId(u16),
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "invalid name error: {}", name),
+            Self::AlreadyExist(name) => write!(f, "already exist error: {}", name),
+            Self::NotFound(name) => write!(f, "not found error: {}", name),
+            Self::InvalidId(id) => write!(f, "invalid id error: {}", id),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualify_name() {
+        assert_eq!(Registry::qualify("tile_grass"), Ok("core:tile_grass".into()));
+        assert_eq!(Registry::qualify("mod:tile_grass"), Ok("mod:tile_grass".into()));
+        assert_eq!(Registry::qualify("mod:"), Err(RegistryError::InvalidName("mod:".into())));
+        assert_eq!(Registry::qualify("a:b:c"), Err(RegistryError::InvalidName("a:b:c".into())));
+        assert_eq!(Registry::qualify(""), Err(RegistryError::InvalidName("".into())));
+    }
+
+    #[test]
+    fn name_table() {
+        let mut table = NameTable::default();
+
+        assert_eq!(table.insert("tile_dirt"), Ok(0));
+        assert_eq!(table.insert("mod:tile_dirt"), Ok(1));
+        assert_eq!(table.insert("core:tile_dirt"), Err(RegistryError::AlreadyExist("core:tile_dirt".into())));
+
+        assert_eq!(table.get("tile_dirt"), Ok(0));
+        assert_eq!(table.get("core:tile_dirt"), Ok(0));
+        assert_eq!(table.get("mod:tile_dirt"), Ok(1));
+        assert_eq!(table.get("tile_grass"), Err(RegistryError::NotFound("core:tile_grass".into())));
+
+        assert_eq!(table.get_name(1), Ok("mod:tile_dirt"));
+        assert_eq!(table.get_name(2), Err(RegistryError::InvalidId(2)));
+
+        let entries = table.iter().collect::<Vec<_>>();
+        assert_eq!(entries, vec![(0, "core:tile_dirt"), (1, "mod:tile_dirt")]);
+    }
+
+    #[test]
+    fn separate_kinds() {
+        let mut registry = Registry::new();
+
+        assert_eq!(registry.tiles_mut().insert("dirt"), Ok(0));
+        assert_eq!(registry.blocks_mut().insert("dirt"), Ok(0));
+        assert_eq!(registry.blocks_mut().insert("tree"), Ok(1));
+
+        assert_eq!(registry.tiles().get("tree"), Err(RegistryError::NotFound("core:tree".into())));
+        assert_eq!(registry.blocks().get("tree"), Ok(1));
+    }
+}
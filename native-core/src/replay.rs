@@ -0,0 +1,347 @@
+use glam::*;
+
+use crate::dataflow;
+
+// replay schema, everything the session fed into the world between two process calls
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replay {
+    pub seed: u64,
+    #[serde(default)]
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayFrame {
+    #[serde(default)]
+    pub commands: Vec<ReplayCommand>,
+    pub delta_secs: f32,
+    // state after the process call of this frame
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ReplayCommand {
+    SpawnPlayer,
+    SpawnBulkAnimal,
+    QueueInput { input: [f32; 2] },
+    // generation finishes on another thread, so the chunks that were committed are kept too
+    GenerateField { rect: [f32; 4], focus: [f32; 2], chunks: Vec<[i32; 2]> },
+}
+
+impl Replay {
+    pub fn parse(path: &str, source: &str) -> Result<Self, ReplayError> {
+        serde_json::from_str(source).map_err(|e| ReplayError::ParseError {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn load(path: &str) -> Result<Self, ReplayError> {
+        if !godot::classes::FileAccess::file_exists(path) {
+            return Err(ReplayError::ReadError { path: path.to_string() });
+        }
+        let source = godot::classes::FileAccess::get_file_as_string(path).to_string();
+
+        Self::parse(path, &source)
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn save(&self, path: &str) -> Result<(), ReplayError> {
+        let mut file = godot::classes::FileAccess::open(path, godot::classes::file_access::ModeFlags::WRITE)
+            .ok_or_else(|| ReplayError::WriteError { path: path.to_string() })?;
+        file.store_string(&self.to_json());
+        Ok(())
+    }
+}
+
+// recorder
+
+pub struct ReplayRecorder {
+    replay: Replay,
+    commands: Vec<ReplayCommand>,
+}
+
+impl ReplayRecorder {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            replay: Replay { seed, frames: vec![] },
+            commands: vec![],
+        }
+    }
+
+    pub fn record(&mut self, command: ReplayCommand) {
+        self.commands.push(command);
+    }
+
+    // closes the frame, call right after the process call
+    pub fn record_process(&mut self, dataflow: &dataflow::Dataflow, delta_secs: f32) {
+        self.replay.frames.push(ReplayFrame {
+            commands: std::mem::take(&mut self.commands),
+            delta_secs,
+            hash: hash_state(dataflow),
+        });
+    }
+
+    // commands after the last process call change nothing that was checked and are dropped
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+// driver
+
+pub struct ReplayDriver {
+    replay: Replay,
+    cursor: usize,
+}
+
+impl ReplayDriver {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.replay.seed
+    }
+
+    #[inline]
+    pub fn frame_index(&self) -> usize {
+        self.cursor
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.replay.frames.len()
+    }
+
+    // the frame to feed next, its commands go before the process call
+    pub fn next_frame(&self) -> Option<&ReplayFrame> {
+        self.replay.frames.get(self.cursor)
+    }
+
+    // compares the state after the process call of the current frame and moves on
+    pub fn verify(&mut self, dataflow: &dataflow::Dataflow) -> Result<(), ReplayError> {
+        let frame = self.replay.frames.get(self.cursor).ok_or(ReplayError::Finished)?;
+
+        let actual = hash_state(dataflow);
+        if frame.hash != actual {
+            return Err(ReplayError::Desync {
+                frame: self.cursor,
+                expected: frame.hash,
+                actual,
+            });
+        }
+
+        self.cursor += 1;
+        Ok(())
+    }
+}
+
+// hash of the tick and every tile, block and entity,
+// objects are hashed by content and in sorted order so that storage order and ids do not matter
+pub fn hash_state(dataflow: &dataflow::Dataflow) -> u64 {
+    let mut hasher = StateHasher::new();
+    hasher.write(dataflow.get_tick());
+
+    let mut chunk_coords = dataflow.get_tile_chunk_coords().collect::<Vec<_>>();
+    chunk_coords.sort_by_key(|chunk_coord| chunk_coord.to_array());
+    for chunk_coord in chunk_coords {
+        let chunk = dataflow.get_tile_chunk(chunk_coord).unwrap();
+        let objects = chunk.tiles.iter().map(|tile| (tile.archetype_id, tile.coord.as_vec2(), tile.variant, tile.tick));
+        hasher.write_objects(0, chunk_coord, objects);
+    }
+
+    let mut chunk_coords = dataflow.get_block_chunk_coords().collect::<Vec<_>>();
+    chunk_coords.sort_by_key(|chunk_coord| chunk_coord.to_array());
+    for chunk_coord in chunk_coords {
+        let chunk = dataflow.get_block_chunk(chunk_coord).unwrap();
+        let objects = chunk.blocks.iter().map(|block| (block.archetype_id, block.coord.as_vec2(), block.variant, block.tick));
+        hasher.write_objects(1, chunk_coord, objects);
+    }
+
+    let mut chunk_coords = dataflow.get_entity_chunk_coords().collect::<Vec<_>>();
+    chunk_coords.sort_by_key(|chunk_coord| chunk_coord.to_array());
+    for chunk_coord in chunk_coords {
+        let chunk = dataflow.get_entity_chunk(chunk_coord).unwrap();
+        let objects = chunk.entities.iter().map(|entity| (entity.archetype_id, entity.coord, entity.variant, entity.tick));
+        hasher.write_objects(2, chunk_coord, objects);
+    }
+
+    hasher.finish()
+}
+
+// fnv-1a over u64 words, stable across processes and platforms unlike the ahash seeds
+struct StateHasher(u64);
+
+impl StateHasher {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_objects(&mut self, kind: u64, chunk_coord: IVec2, objects: impl Iterator<Item = (u16, Vec2, u16, u32)>) {
+        let mut objects = objects
+            .map(|(archetype_id, coord, variant, tick)| (archetype_id, coord.x.to_bits(), coord.y.to_bits(), variant, tick))
+            .collect::<Vec<_>>();
+        if objects.is_empty() {
+            return;
+        }
+        objects.sort_unstable();
+
+        self.write(kind);
+        self.write(chunk_coord.x as u32 as u64);
+        self.write(chunk_coord.y as u32 as u64);
+        self.write(objects.len() as u64);
+        for (archetype_id, x, y, variant, tick) in objects {
+            self.write(archetype_id as u64);
+            self.write(x as u64);
+            self.write(y as u64);
+            self.write(variant as u64);
+            self.write(tick as u64);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    ReadError { path: String },
+    WriteError { path: String },
+    ParseError { path: String, message: String },
+    Desync { frame: usize, expected: u64, actual: u64 },
+    Finished,
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadError { path } => write!(f, "{}: failed to read replay", path),
+            Self::WriteError { path } => write!(f, "{}: failed to write replay", path),
+            Self::ParseError { path, message } => write!(f, "{}: {}", path, message),
+            Self::Desync { frame, expected, actual } => write!(f, "desync at frame {}: expected {:016x}, got {:016x}", frame, expected, actual),
+            Self::Finished => write!(f, "replay is finished"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dataflow() -> dataflow::Dataflow {
+        dataflow::TestDataflowBuilder::new().tile(false).entity().build()
+    }
+
+    // a tiny session driven only by the commands, like the context does
+    fn run_frame(dataflow: &mut dataflow::Dataflow, entity_id: &mut Option<dataflow::EntityId>, frame_commands: &[ReplayCommand], delta_secs: f32) {
+        for command in frame_commands {
+            match command {
+                ReplayCommand::SpawnPlayer => {
+                    *entity_id = Some(dataflow.insert_entity(dataflow::Entity::default()).unwrap());
+                }
+                ReplayCommand::SpawnBulkAnimal => {}
+                ReplayCommand::QueueInput { input } => {
+                    let entity_id = entity_id.unwrap();
+                    let coord = dataflow.get_entity(entity_id).unwrap().coord + Vec2::from_array(*input) * delta_secs;
+                    dataflow.move_entity(entity_id, coord).unwrap();
+                }
+                ReplayCommand::GenerateField { chunks, .. } => {
+                    for chunk_coord in chunks {
+                        let coord = IVec2::from_array(*chunk_coord) * 32;
+                        dataflow.insert_tile(dataflow::Tile { coord, ..Default::default() }).unwrap();
+                    }
+                }
+            }
+        }
+        dataflow.process(delta_secs);
+    }
+
+    #[test]
+    fn replay_round_trip() {
+        let sessions = [
+            vec![ReplayCommand::SpawnPlayer, ReplayCommand::GenerateField { rect: [0.0, 0.0, 64.0, 64.0], focus: [0.0, 0.0], chunks: vec![[0, 0], [1, 0]] }],
+            vec![ReplayCommand::QueueInput { input: [1.0, 0.0] }],
+            vec![],
+            vec![ReplayCommand::QueueInput { input: [0.0, -1.0] }, ReplayCommand::GenerateField { rect: [0.0, 0.0, 64.0, 64.0], focus: [0.0, 0.0], chunks: vec![[1, 1]] }],
+        ];
+
+        let mut dataflow = make_dataflow();
+        let mut entity_id = None;
+        let mut recorder = ReplayRecorder::new(7);
+        for commands in &sessions {
+            for command in commands {
+                recorder.record(command.clone());
+            }
+            run_frame(&mut dataflow, &mut entity_id, commands, 0.25);
+            recorder.record_process(&dataflow, 0.25);
+        }
+        recorder.record(ReplayCommand::QueueInput { input: [1.0, 1.0] });
+
+        let replay = Replay::parse("test.json", &recorder.finish().to_json()).unwrap();
+        assert_eq!(replay.seed, 7);
+        assert_eq!(replay.frames.len(), 4);
+
+        let mut driver = ReplayDriver::new(replay);
+        let mut dataflow = make_dataflow();
+        let mut entity_id = None;
+        while let Some(frame) = driver.next_frame() {
+            let frame = frame.clone();
+            run_frame(&mut dataflow, &mut entity_id, &frame.commands, frame.delta_secs);
+            driver.verify(&dataflow).unwrap();
+        }
+        assert!(driver.is_finished());
+        assert_eq!(driver.verify(&dataflow), Err(ReplayError::Finished));
+    }
+
+    #[test]
+    fn replay_detects_desync() {
+        let mut dataflow = make_dataflow();
+        let mut entity_id = None;
+        let mut recorder = ReplayRecorder::new(0);
+        let commands = [ReplayCommand::SpawnPlayer, ReplayCommand::QueueInput { input: [1.0, 0.0] }];
+        for command in &commands {
+            recorder.record(command.clone());
+        }
+        run_frame(&mut dataflow, &mut entity_id, &commands, 0.5);
+        recorder.record_process(&dataflow, 0.5);
+
+        // the same frame fed with a different input
+        let mut driver = ReplayDriver::new(recorder.finish());
+        let mut dataflow = make_dataflow();
+        let mut entity_id = None;
+        run_frame(&mut dataflow, &mut entity_id, &[ReplayCommand::SpawnPlayer, ReplayCommand::QueueInput { input: [0.0, 1.0] }], 0.5);
+        assert!(matches!(driver.verify(&dataflow), Err(ReplayError::Desync { frame: 0, .. })));
+        assert_eq!(driver.frame_index(), 0);
+
+        // the hash ignores storage order
+        let mut a = make_dataflow();
+        let mut b = make_dataflow();
+        for x in 0..4 {
+            a.insert_tile(dataflow::Tile { coord: IVec2::new(x, 0), ..Default::default() }).unwrap();
+            b.insert_tile(dataflow::Tile { coord: IVec2::new(3 - x, 0), ..Default::default() }).unwrap();
+        }
+        assert_eq!(hash_state(&a), hash_state(&b));
+    }
+}
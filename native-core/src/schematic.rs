@@ -0,0 +1,275 @@
+use glam::*;
+
+use crate::*;
+
+// schematic schema, archetypes are referred to by registry name so a file outlives id assignment
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schematic {
+    pub size: [i32; 2],
+    // where the region was copied from and the tick of the world at that time
+    #[serde(default)]
+    pub origin: [i32; 2],
+    #[serde(default)]
+    pub tick: u64,
+    #[serde(default)]
+    pub tiles: Vec<SchematicTile>,
+    #[serde(default)]
+    pub blocks: Vec<SchematicBlock>,
+    #[serde(default)]
+    pub entities: Vec<SchematicEntity>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchematicTile {
+    pub archetype: String,
+    pub coord: [i32; 2],
+    #[serde(default)]
+    pub variant: u16,
+    #[serde(default)]
+    pub tick: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchematicBlock {
+    pub archetype: String,
+    pub coord: [i32; 2],
+    #[serde(default)]
+    pub variant: u16,
+    #[serde(default)]
+    pub tick: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchematicEntity {
+    pub archetype: String,
+    pub coord: [f32; 2],
+    #[serde(default)]
+    pub variant: u16,
+    #[serde(default)]
+    pub tick: u32,
+}
+
+impl Schematic {
+    pub fn parse(path: &str, source: &str) -> Result<Self, SchematicError> {
+        serde_json::from_str(source).map_err(|e| SchematicError::ParseError {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn load(path: &str) -> Result<Self, SchematicError> {
+        if !godot::classes::FileAccess::file_exists(path) {
+            return Err(SchematicError::ReadError { path: path.to_string() });
+        }
+        let source = godot::classes::FileAccess::get_file_as_string(path).to_string();
+
+        Self::parse(path, &source)
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn save(&self, path: &str) -> Result<(), SchematicError> {
+        let mut file = godot::classes::FileAccess::open(path, godot::classes::file_access::ModeFlags::WRITE)
+            .ok_or_else(|| SchematicError::WriteError { path: path.to_string() })?;
+        file.store_string(&self.to_json());
+        Ok(())
+    }
+
+    pub fn from_clipboard(registry: &Registry, clipboard: &dataflow::Clipboard) -> Result<Self, SchematicError> {
+        let invalid = |e: RegistryError| SchematicError::InvalidEntry {
+            entry: "archetype".to_string(),
+            message: e.to_string(),
+        };
+
+        let mut tiles = vec![];
+        for tile in &clipboard.tiles {
+            tiles.push(SchematicTile {
+                archetype: registry.tiles().get_name(tile.archetype_id).map_err(invalid)?.to_string(),
+                coord: tile.coord.to_array(),
+                variant: tile.variant,
+                tick: tile.tick,
+            });
+        }
+        let mut blocks = vec![];
+        for block in &clipboard.blocks {
+            blocks.push(SchematicBlock {
+                archetype: registry.blocks().get_name(block.archetype_id).map_err(invalid)?.to_string(),
+                coord: block.coord.to_array(),
+                variant: block.variant,
+                tick: block.tick,
+            });
+        }
+        let mut entities = vec![];
+        for entity in &clipboard.entities {
+            entities.push(SchematicEntity {
+                archetype: registry.entities().get_name(entity.archetype_id).map_err(invalid)?.to_string(),
+                coord: entity.coord.to_array(),
+                variant: entity.variant,
+                tick: entity.tick,
+            });
+        }
+
+        Ok(Self {
+            size: clipboard.size.to_array(),
+            origin: [0, 0],
+            tick: 0,
+            tiles,
+            blocks,
+            entities,
+        })
+    }
+
+    // every name is resolved before anything is returned, so an unknown archetype fails the whole schematic
+    pub fn to_clipboard(&self, registry: &Registry) -> Result<dataflow::Clipboard, SchematicError> {
+        let invalid = |name: &str, e: RegistryError| SchematicError::InvalidEntry {
+            entry: name.to_string(),
+            message: e.to_string(),
+        };
+
+        let mut tiles = vec![];
+        for tile in &self.tiles {
+            tiles.push(dataflow::Tile {
+                coord: IVec2::from_array(tile.coord),
+                archetype_id: registry.tiles().get(&tile.archetype).map_err(|e| invalid(&tile.archetype, e))?,
+                variant: tile.variant,
+                tick: tile.tick,
+            });
+        }
+        let mut blocks = vec![];
+        for block in &self.blocks {
+            blocks.push(dataflow::Block {
+                coord: IVec2::from_array(block.coord),
+                archetype_id: registry.blocks().get(&block.archetype).map_err(|e| invalid(&block.archetype, e))?,
+                variant: block.variant,
+                tick: block.tick,
+            });
+        }
+        let mut entities = vec![];
+        for entity in &self.entities {
+            entities.push(dataflow::Entity {
+                coord: Vec2::from_array(entity.coord),
+                archetype_id: registry.entities().get(&entity.archetype).map_err(|e| invalid(&entity.archetype, e))?,
+                variant: entity.variant,
+                tick: entity.tick,
+            });
+        }
+
+        Ok(dataflow::Clipboard {
+            size: IVec2::from_array(self.size),
+            tiles,
+            blocks,
+            entities,
+        })
+    }
+}
+
+#[cfg(feature = "godot")]
+impl Context {
+    pub fn copy_schematic(&self, region: IRect2) -> Result<Schematic, SchematicError> {
+        let mut schematic = Schematic::from_clipboard(&self.registry, &self.dataflow.copy_region(region))?;
+        schematic.origin = region.min.to_array();
+        schematic.tick = self.dataflow.get_tick();
+        Ok(schematic)
+    }
+
+    pub fn paste_schematic(&mut self, schematic: &Schematic, origin: IVec2, policy: dataflow::ConflictPolicy) -> Result<dataflow::EditSummary, SchematicError> {
+        let clipboard = schematic.to_clipboard(&self.registry)?;
+        let summary = self.dataflow.paste_region(&clipboard, origin, policy)?;
+        Ok(summary)
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchematicError {
+    ReadError { path: String },
+    WriteError { path: String },
+    ParseError { path: String, message: String },
+    InvalidEntry { entry: String, message: String },
+    DataflowError(dataflow::DataflowError),
+}
+
+impl std::fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadError { path } => write!(f, "{}: failed to read schematic", path),
+            Self::WriteError { path } => write!(f, "{}: failed to write schematic", path),
+            Self::ParseError { path, message } => write!(f, "{}: {}", path, message),
+            Self::InvalidEntry { entry, message } => write!(f, "{}: {}", entry, message),
+            Self::DataflowError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {}
+
+impl From<dataflow::DataflowError> for SchematicError {
+    fn from(value: dataflow::DataflowError) -> Self {
+        Self::DataflowError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.tile_layers_mut().insert("layer").unwrap();
+        registry.tiles_mut().insert("tile_grass").unwrap();
+        registry.blocks_mut().insert("block_rock").unwrap();
+        registry.entities_mut().insert("entity_bird").unwrap();
+        registry
+    }
+
+    #[test]
+    fn schematic_round_trip() {
+        let registry = make_registry();
+        let clipboard = dataflow::Clipboard {
+            size: IVec2::new(2, 2),
+            tiles: vec![dataflow::Tile { coord: IVec2::new(1, 0), variant: 2, ..Default::default() }],
+            blocks: vec![dataflow::Block { coord: IVec2::new(0, 1), tick: 3, ..Default::default() }],
+            entities: vec![dataflow::Entity { coord: Vec2::new(0.5, 0.5), ..Default::default() }],
+        };
+
+        let schematic = Schematic::from_clipboard(&registry, &clipboard).unwrap();
+        assert_eq!(schematic.tiles[0].archetype, "core:tile_grass");
+        assert_eq!(schematic.blocks[0].archetype, "core:block_rock");
+
+        let schematic = Schematic::parse("test.json", &schematic.to_json()).unwrap();
+        let result = schematic.to_clipboard(&registry).unwrap();
+        assert_eq!(result.size, clipboard.size);
+        assert_eq!(result.tiles[0].coord, IVec2::new(1, 0));
+        assert_eq!(result.tiles[0].variant, 2);
+        assert_eq!(result.blocks[0].tick, 3);
+        assert_eq!(result.entities[0].coord, Vec2::new(0.5, 0.5));
+    }
+
+    #[test]
+    fn schematic_with_unknown_archetype() {
+        let registry = make_registry();
+        let source = r#"{
+            "size": [1, 1],
+            "tiles": [{ "archetype": "tile_grass", "coord": [0, 0] }],
+            "blocks": [{ "archetype": "mod:block_tree", "coord": [0, 0] }]
+        }"#;
+
+        let schematic = Schematic::parse("test.json", source).unwrap();
+        assert_eq!(schematic.to_clipboard(&registry).unwrap_err(), SchematicError::InvalidEntry {
+            entry: "mod:block_tree".into(),
+            message: "not found error: mod:block_tree".into(),
+        });
+
+        assert!(matches!(Schematic::parse("test.json", r#"{ "size": [1, 1], "scale": 2 }"#), Err(SchematicError::ParseError { .. })));
+    }
+}
@@ -1,6 +1,8 @@
 use glam::*;
 
 use crate::dataflow;
+
+use super::light;
 use crate::geom::*;
 
 pub struct BlockSpriteInfo {
@@ -38,6 +40,7 @@ struct DeadChunk {
     materials: Vec<godot::builtin::Rid>,
     multimesh: godot::builtin::Rid,
     instance: godot::builtin::Rid,
+    light_texture: godot::builtin::Rid,
 }
 
 impl DeadChunk {
@@ -47,6 +50,8 @@ impl DeadChunk {
             materials: self.materials,
             multimesh: self.multimesh,
             instance: self.instance,
+            light_version: None,
+            light_texture: self.light_texture,
         }
     }
 }
@@ -56,6 +61,8 @@ struct LiveChunk {
     materials: Vec<godot::builtin::Rid>,
     multimesh: godot::builtin::Rid,
     instance: godot::builtin::Rid,
+    light_version: Option<u64>,
+    light_texture: godot::builtin::Rid,
 }
 
 impl LiveChunk {
@@ -64,6 +71,7 @@ impl LiveChunk {
             materials: self.materials,
             multimesh: self.multimesh,
             instance: self.instance,
+            light_texture: self.light_texture,
         }
     }
 }
@@ -71,6 +79,8 @@ impl LiveChunk {
 pub struct BlockField {
     layouts: Vec<RenderLayout>,
     sprite_addrs: Vec<Vec<ImageAddress>>,
+    texture_array: godot::builtin::Rid,
+    coord_texture: godot::builtin::Rid,
     dead_chunks: Vec<DeadChunk>,
     live_chunks: ahash::AHashMap<IVec2, LiveChunk>,
     free_handles: Vec<godot::builtin::Rid>,
@@ -91,18 +101,115 @@ impl BlockField {
 
         let mut free_handles = vec![];
 
+        let layouts = Self::create_layouts(&info.blocks);
+        let (sprite_addrs, texture_array, coord_texture) = Self::create_atlas(&mut rendering_server, info.blocks);
+
+        let mut mesh_data = godot::builtin::VarArray::new();
+        mesh_data.resize(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::MAX) as usize,
+            &godot::builtin::Variant::nil()
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::VERTEX) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector3Array::from(&[
+                godot::builtin::Vector3::new(0.0, 0.0, 0.0),
+                godot::builtin::Vector3::new(0.0, 1.0, 1.0),
+                godot::builtin::Vector3::new(1.0, 1.0, 1.0),
+                godot::builtin::Vector3::new(1.0, 0.0, 0.0),
+            ])),
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::TEX_UV) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector2Array::from(&[
+                godot::builtin::Vector2::new(0.0, 1.0),
+                godot::builtin::Vector2::new(0.0, 0.0),
+                godot::builtin::Vector2::new(1.0, 0.0),
+                godot::builtin::Vector2::new(1.0, 1.0),
+            ])),
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::INDEX) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedInt32Array::from(&[0, 1, 2, 0, 2, 3])),
+        );
+
+        let mut dead_chunks = vec![];
+        for _ in 0..Self::CHUNK_CAPACITY {
+            let light_texture = light::create_light_texture(&mut rendering_server);
+            free_handles.push(light_texture);
+
+            let mut materials = vec![];
+            for shader in &info.shaders {
+                let material = rendering_server.material_create();
+                rendering_server.material_set_shader(material, shader.get_rid());
+                rendering_server.material_set_param(material, "texture_array", &godot::meta::ToGodot::to_variant(&texture_array));
+                rendering_server.material_set_param(material, "bake_texture", &godot::meta::ToGodot::to_variant(&coord_texture));
+                rendering_server.material_set_param(material, "light_texture", &godot::meta::ToGodot::to_variant(&light_texture));
+                free_handles.push(material);
+
+                materials.push(material)
+            }
+
+            for i in 0..materials.len() - 1 {
+                let material = materials[i];
+                let next_material = materials[i + 1];
+                rendering_server.material_set_next_pass(material, next_material);
+            }
+
+            let mesh = rendering_server.mesh_create();
+            rendering_server.mesh_add_surface_from_arrays(mesh, godot::classes::rendering_server::PrimitiveType::TRIANGLES, &mesh_data);
+            rendering_server.mesh_surface_set_material(mesh, 0, materials[0]);
+            free_handles.push(mesh);
+
+            let multimesh = rendering_server.multimesh_create();
+            rendering_server.multimesh_set_mesh(multimesh, mesh);
+            rendering_server.multimesh_allocate_data(multimesh, Self::BUFFER_LEN as i32, godot::classes::rendering_server::MultimeshTransformFormat::TRANSFORM_3D);
+            free_handles.push(multimesh);
+
+            let instance = rendering_server.instance_create2(multimesh, info.world.get_scenario());
+            rendering_server.instance_set_visible(instance, false);
+            free_handles.push(instance);
+
+            dead_chunks.push(DeadChunk {
+                materials,
+                multimesh,
+                instance,
+                light_texture,
+            });
+        }
+
+        Self {
+            layouts,
+            sprite_addrs,
+            texture_array,
+            coord_texture,
+            dead_chunks,
+            live_chunks: Default::default(),
+            free_handles,
+            rect: None,
+            instance_buffer: vec![0.0; Self::BUFFER_LEN * 12],
+            address_buffer: vec![0; Self::BUFFER_LEN * 4],
+        }
+    }
+
+    fn create_layouts(blocks: &[BlockInfo]) -> Vec<RenderLayout> {
         let mut layouts = vec![];
-        for block in &info.blocks {
+        for block in blocks {
             layouts.push(RenderLayout {
                 y_sorting: block.y_sorting,
                 rendering_size: block.rendering_rect.size(),
                 rendering_offset: block.rendering_rect.min,
             });
         }
+        layouts
+    }
 
+    fn create_atlas(
+        rendering_server: &mut godot::obj::Gd<godot::classes::RenderingServer>,
+        blocks: Vec<BlockInfo>,
+    ) -> (Vec<Vec<ImageAddress>>, godot::builtin::Rid, godot::builtin::Rid) {
         let mut sprite_addrs = vec![];
         let mut images = vec![];
-        for block in info.blocks {
+        for block in blocks {
             let mut sprite_addr = vec![];
 
             for sprite in block.sprites {
@@ -172,7 +279,6 @@ impl BlockField {
             &godot::builtin::Array::from(images.as_slice()),
             godot::classes::rendering_server::TextureLayeredType::LAYERED_2D_ARRAY,
         );
-        free_handles.push(texture_array);
 
         if atlas_output.texcoords.len() * 2 > Self::COORD_BUFFER_WIDTH * Self::COORD_BUFFER_WIDTH {
             panic!("number of (image * 2) must be less than (COORD_BUFFER_WIDTH ^ 2)");
@@ -199,85 +305,35 @@ impl BlockField {
         )
         .unwrap();
         let coord_texture = rendering_server.texture_2d_create(&coord_image);
-        free_handles.push(coord_texture);
-
-        let mut mesh_data = godot::builtin::VarArray::new();
-        mesh_data.resize(
-            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::MAX) as usize,
-            &godot::builtin::Variant::nil()
-        );
-        mesh_data.set(
-            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::VERTEX) as usize,
-            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector3Array::from(&[
-                godot::builtin::Vector3::new(0.0, 0.0, 0.0),
-                godot::builtin::Vector3::new(0.0, 1.0, 1.0),
-                godot::builtin::Vector3::new(1.0, 1.0, 1.0),
-                godot::builtin::Vector3::new(1.0, 0.0, 0.0),
-            ])),
-        );
-        mesh_data.set(
-            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::TEX_UV) as usize,
-            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector2Array::from(&[
-                godot::builtin::Vector2::new(0.0, 1.0),
-                godot::builtin::Vector2::new(0.0, 0.0),
-                godot::builtin::Vector2::new(1.0, 0.0),
-                godot::builtin::Vector2::new(1.0, 1.0),
-            ])),
-        );
-        mesh_data.set(
-            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::INDEX) as usize,
-            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedInt32Array::from(&[0, 1, 2, 0, 2, 3])),
-        );
-
-        let mut dead_chunks = vec![];
-        for _ in 0..Self::CHUNK_CAPACITY {
-            let mut materials = vec![];
-            for shader in &info.shaders {
-                let material = rendering_server.material_create();
-                rendering_server.material_set_shader(material, shader.get_rid());
-                rendering_server.material_set_param(material, "texture_array", &godot::meta::ToGodot::to_variant(&texture_array));
-                rendering_server.material_set_param(material, "bake_texture", &godot::meta::ToGodot::to_variant(&coord_texture));
-                free_handles.push(material);
 
-                materials.push(material)
-            }
+        (sprite_addrs, texture_array, coord_texture)
+    }
 
-            for i in 0..materials.len() - 1 {
-                let material = materials[i];
-                let next_material = materials[i + 1];
-                rendering_server.material_set_next_pass(material, next_material);
-            }
+    // rebuild the atlas and layouts in place, live chunks are re-uploaded on the next update
+    pub fn reload(&mut self, blocks: Vec<BlockInfo>) {
+        let mut rendering_server = <godot::classes::RenderingServer as godot::obj::Singleton>::singleton();
 
-            let mesh = rendering_server.mesh_create();
-            rendering_server.mesh_add_surface_from_arrays(mesh, godot::classes::rendering_server::PrimitiveType::TRIANGLES, &mesh_data);
-            rendering_server.mesh_surface_set_material(mesh, 0, materials[0]);
-            free_handles.push(mesh);
+        let layouts = Self::create_layouts(&blocks);
+        let (sprite_addrs, texture_array, coord_texture) = Self::create_atlas(&mut rendering_server, blocks);
 
-            let multimesh = rendering_server.multimesh_create();
-            rendering_server.multimesh_set_mesh(multimesh, mesh);
-            rendering_server.multimesh_allocate_data(multimesh, Self::BUFFER_LEN as i32, godot::classes::rendering_server::MultimeshTransformFormat::TRANSFORM_3D);
-            free_handles.push(multimesh);
+        let materials = self.dead_chunks.iter().map(|chunk| &chunk.materials)
+            .chain(self.live_chunks.values().map(|chunk| &chunk.materials))
+            .flatten();
+        for material in materials {
+            rendering_server.material_set_param(*material, "texture_array", &godot::meta::ToGodot::to_variant(&texture_array));
+            rendering_server.material_set_param(*material, "bake_texture", &godot::meta::ToGodot::to_variant(&coord_texture));
+        }
 
-            let instance = rendering_server.instance_create2(multimesh, info.world.get_scenario());
-            rendering_server.instance_set_visible(instance, false);
-            free_handles.push(instance);
+        rendering_server.free_rid(self.texture_array);
+        rendering_server.free_rid(self.coord_texture);
 
-            dead_chunks.push(DeadChunk {
-                materials,
-                multimesh,
-                instance,
-            });
-        }
+        self.layouts = layouts;
+        self.sprite_addrs = sprite_addrs;
+        self.texture_array = texture_array;
+        self.coord_texture = coord_texture;
 
-        Self {
-            layouts,
-            sprite_addrs,
-            dead_chunks,
-            live_chunks: Default::default(),
-            free_handles,
-            rect: None,
-            instance_buffer: vec![0.0; Self::BUFFER_LEN * 12],
-            address_buffer: vec![0; Self::BUFFER_LEN * 4],
+        for live_chunk in self.live_chunks.values_mut() {
+            live_chunk.version = Default::default();
         }
     }
 
@@ -333,6 +389,8 @@ impl BlockField {
 
         // update view chunk
 
+        let light_ambient = dataflow.get_light_ambient() as f32 / dataflow::LightField::MAX_LEVEL as f32;
+
         for (chunk_coord, live_chunk) in &mut self.live_chunks {
             let Ok(chunk) = dataflow.get_block_chunk(*chunk_coord) else {
                 continue;
@@ -341,6 +399,13 @@ impl BlockField {
             for material in &live_chunk.materials {
                 let tick = dataflow.get_tick() as i32;
                 rendering_server.material_set_param(*material, "tick", &godot::meta::ToGodot::to_variant(&tick));
+                rendering_server.material_set_param(*material, "light_ambient", &godot::meta::ToGodot::to_variant(&light_ambient));
+            }
+
+            let light_version = dataflow.get_light_chunk(*chunk_coord).map(|chunk| chunk.version).unwrap_or_default();
+            if live_chunk.light_version != Some(light_version) {
+                light::update_light_texture(&mut rendering_server, dataflow, *chunk_coord, live_chunk.light_texture, &live_chunk.materials);
+                live_chunk.light_version = Some(light_version);
             }
 
             if chunk.version <= live_chunk.version {
@@ -397,5 +462,7 @@ impl Drop for BlockField {
         for free_handle in &self.free_handles {
             rendering_server.free_rid(*free_handle);
         }
+        rendering_server.free_rid(self.texture_array);
+        rendering_server.free_rid(self.coord_texture);
     }
 }
@@ -0,0 +1,419 @@
+use glam::*;
+
+use crate::dataflow;
+
+use super::light;
+use crate::geom::*;
+
+pub struct FluidSpriteInfo {
+    pub images: Vec<godot::obj::Gd<godot::classes::Image>>,
+    pub tick_per_image: u16,
+    pub is_loop: bool,
+}
+
+// sprites are ordered from the lowest fill level to the highest
+pub struct FluidInfo {
+    pub sprites: Vec<FluidSpriteInfo>,
+}
+
+pub struct FluidFieldInfo {
+    pub fluids: Vec<FluidInfo>,
+    pub shaders: Vec<godot::obj::Gd<godot::classes::Shader>>,
+    pub world: godot::obj::Gd<godot::classes::World3D>,
+}
+
+struct ImageAddress {
+    atlas_start_index: u32,
+    atlas_end_index: u32,
+    tick_per_image: u16,
+    is_loop: bool,
+}
+
+struct DeadChunk {
+    materials: Vec<godot::builtin::Rid>,
+    multimesh: godot::builtin::Rid,
+    instance: godot::builtin::Rid,
+    light_texture: godot::builtin::Rid,
+}
+
+impl DeadChunk {
+    fn spawn(self) -> LiveChunk {
+        LiveChunk {
+            version: Default::default(),
+            materials: self.materials,
+            multimesh: self.multimesh,
+            instance: self.instance,
+            light_version: None,
+            light_texture: self.light_texture,
+        }
+    }
+}
+
+struct LiveChunk {
+    version: u64,
+    materials: Vec<godot::builtin::Rid>,
+    multimesh: godot::builtin::Rid,
+    instance: godot::builtin::Rid,
+    light_version: Option<u64>,
+    light_texture: godot::builtin::Rid,
+}
+
+impl LiveChunk {
+    fn despawn(self) -> DeadChunk {
+        DeadChunk {
+            materials: self.materials,
+            multimesh: self.multimesh,
+            instance: self.instance,
+            light_texture: self.light_texture,
+        }
+    }
+}
+
+pub struct FluidField {
+    sprite_addrs: Vec<Vec<ImageAddress>>,
+    dead_chunks: Vec<DeadChunk>,
+    live_chunks: ahash::AHashMap<IVec2, LiveChunk>,
+    free_handles: Vec<godot::builtin::Rid>,
+    rect: Option<IRect2>,
+    instance_buffer: Vec<f32>,
+    address_buffer: Vec<u32>,
+}
+
+impl FluidField {
+    const CHUNK_CAPACITY: usize = 512;
+    const ATLAS_WIDTH: usize = 1024;
+    const ATLAS_PAGE: usize = 8;
+    const COORD_BUFFER_WIDTH: usize = 1024;
+    const BUFFER_LEN: usize = 1024;
+
+    const CHUNK_SIZE: i32 = 32;
+    const Z_OFFSET: f32 = -0.03125;
+
+    pub fn new(info: FluidFieldInfo) -> Self {
+        let mut rendering_server = <godot::classes::RenderingServer as godot::obj::Singleton>::singleton();
+
+        let mut free_handles = vec![];
+
+        let mut sprite_addrs = vec![];
+        let mut images = vec![];
+        for fluid in info.fluids {
+            let mut sprite_addr = vec![];
+
+            if fluid.sprites.is_empty() {
+                panic!("at least one sprite is required");
+            }
+
+            for sprite in fluid.sprites {
+                if images.len() + sprite.images.len() >= i32::MAX as usize {
+                    panic!("number of frame must be less than i32::MAX");
+                }
+
+                sprite_addr.push(ImageAddress {
+                    atlas_start_index: images.len() as u32,
+                    atlas_end_index: images.len() as u32 + sprite.images.len() as u32,
+                    tick_per_image: sprite.tick_per_image,
+                    is_loop: sprite.is_loop,
+                });
+
+                for frame in sprite.images {
+                    let width = frame.get_width() as u32;
+                    let height = frame.get_height() as u32;
+
+                    let mut image_rgba8 = image::RgbaImage::new(width, height);
+                    for y in 0..height {
+                        for x in 0..width {
+                            let color = frame.get_pixel(x as i32, y as i32);
+                            let rgba8 = image::Rgba([color.r8(), color.g8(), color.b8(), color.a8()]);
+                            image_rgba8.put_pixel(x, y, rgba8);
+                        }
+                    }
+
+                    images.push(image_rgba8);
+                }
+            }
+
+            sprite_addrs.push(sprite_addr);
+        }
+
+        let mut atlas_input = vec![];
+        for image in images {
+            atlas_input.push(image_atlas::AtlasEntry {
+                texture: image,
+                mip: image_atlas::AtlasEntryMipOption::Clamp,
+            });
+        }
+        let atlas_output = image_atlas::create_atlas(&image_atlas::AtlasDescriptor {
+            size: Self::ATLAS_WIDTH as u32,
+            max_page_count: Self::ATLAS_PAGE as u32,
+            mip: image_atlas::AtlasMipOption::NoMipWithPadding(1),
+            entries: &atlas_input,
+        })
+        .unwrap();
+
+        let mut images = vec![];
+        for texture in &atlas_output.textures {
+            let image = &texture.mip_maps[0];
+
+            let image = godot::classes::Image::create_from_data(
+                Self::ATLAS_WIDTH as i32,
+                Self::ATLAS_WIDTH as i32,
+                false,
+                godot::classes::image::Format::RGBA8,
+                &godot::builtin::PackedByteArray::from(image.to_vec()),
+            )
+            .unwrap();
+
+            images.push(image);
+        }
+
+        let texture_array = rendering_server.texture_2d_layered_create(
+            &godot::builtin::Array::from(images.as_slice()),
+            godot::classes::rendering_server::TextureLayeredType::LAYERED_2D_ARRAY,
+        );
+        free_handles.push(texture_array);
+
+        if atlas_output.texcoords.len() * 2 > Self::COORD_BUFFER_WIDTH * Self::COORD_BUFFER_WIDTH {
+            panic!("number of (image * 2) must be less than (COORD_BUFFER_WIDTH ^ 2)");
+        }
+        let mut coord_buffer = vec![0.0; Self::COORD_BUFFER_WIDTH * Self::COORD_BUFFER_WIDTH * 4];
+        for (i, coord) in atlas_output.texcoords.into_iter().enumerate() {
+            let coord = coord.to_f32();
+            coord_buffer[i * 8] = coord.min_x;
+            coord_buffer[i * 8 + 1] = coord.min_y;
+            coord_buffer[i * 8 + 2] = coord.max_x - coord.min_x;
+            coord_buffer[i * 8 + 3] = coord.max_y - coord.min_y;
+            coord_buffer[i * 8 + 4] = coord.page as f32;
+            coord_buffer[i * 8 + 5] = 0.0;
+            coord_buffer[i * 8 + 6] = 0.0;
+            coord_buffer[i * 8 + 7] = 0.0;
+        }
+        let coord_buffer = bytemuck::cast_slice::<_, u8>(coord_buffer.as_slice());
+        let coord_image = godot::classes::Image::create_from_data(
+            Self::COORD_BUFFER_WIDTH as i32,
+            Self::COORD_BUFFER_WIDTH as i32,
+            false,
+            godot::classes::image::Format::RGBAF,
+            &godot::builtin::PackedByteArray::from(coord_buffer),
+        )
+        .unwrap();
+        let coord_texture = rendering_server.texture_2d_create(&coord_image);
+        free_handles.push(coord_texture);
+
+        let mut mesh_data = godot::builtin::VarArray::new();
+        mesh_data.resize(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::MAX) as usize,
+            &godot::builtin::Variant::nil()
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::VERTEX) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector3Array::from(&[
+                godot::builtin::Vector3::new(0.0, 0.0, 0.0),
+                godot::builtin::Vector3::new(0.0, 1.0, 0.0),
+                godot::builtin::Vector3::new(1.0, 1.0, 0.0),
+                godot::builtin::Vector3::new(1.0, 0.0, 0.0),
+            ])),
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::TEX_UV) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedVector2Array::from(&[
+                godot::builtin::Vector2::new(0.0, 1.0),
+                godot::builtin::Vector2::new(0.0, 0.0),
+                godot::builtin::Vector2::new(1.0, 0.0),
+                godot::builtin::Vector2::new(1.0, 1.0),
+            ])),
+        );
+        mesh_data.set(
+            godot::obj::EngineEnum::ord(godot::classes::rendering_server::ArrayType::INDEX) as usize,
+            &godot::meta::ToGodot::to_variant(&godot::builtin::PackedInt32Array::from(&[0, 1, 2, 0, 2, 3])),
+        );
+
+        let mut dead_chunks = vec![];
+        for _ in 0..Self::CHUNK_CAPACITY {
+            let light_texture = light::create_light_texture(&mut rendering_server);
+            free_handles.push(light_texture);
+
+            let mut materials = vec![];
+            for shader in &info.shaders {
+                let material = rendering_server.material_create();
+                rendering_server.material_set_shader(material, shader.get_rid());
+                rendering_server.material_set_param(material, "texture_array", &godot::meta::ToGodot::to_variant(&texture_array));
+                rendering_server.material_set_param(material, "bake_texture", &godot::meta::ToGodot::to_variant(&coord_texture));
+                rendering_server.material_set_param(material, "light_texture", &godot::meta::ToGodot::to_variant(&light_texture));
+                free_handles.push(material);
+
+                materials.push(material)
+            }
+
+            for i in 0..materials.len() - 1 {
+                let material = materials[i];
+                let next_material = materials[i + 1];
+                rendering_server.material_set_next_pass(material, next_material);
+            }
+
+            let mesh = rendering_server.mesh_create();
+            rendering_server.mesh_add_surface_from_arrays(mesh, godot::classes::rendering_server::PrimitiveType::TRIANGLES, &mesh_data);
+            rendering_server.mesh_surface_set_material(mesh, 0, materials[0]);
+            free_handles.push(mesh);
+
+            let multimesh = rendering_server.multimesh_create();
+            rendering_server.multimesh_set_mesh(multimesh, mesh);
+            rendering_server.multimesh_allocate_data(multimesh, Self::BUFFER_LEN as i32, godot::classes::rendering_server::MultimeshTransformFormat::TRANSFORM_3D);
+            free_handles.push(multimesh);
+
+            let instance = rendering_server.instance_create2(multimesh, info.world.get_scenario());
+            rendering_server.instance_set_visible(instance, false);
+            free_handles.push(instance);
+
+            dead_chunks.push(DeadChunk {
+                materials,
+                multimesh,
+                instance,
+                light_texture,
+            });
+        }
+
+        Self {
+            sprite_addrs,
+            dead_chunks,
+            live_chunks: Default::default(),
+            free_handles,
+            rect: Default::default(),
+            instance_buffer: vec![0.0; Self::BUFFER_LEN * 12],
+            address_buffer: vec![0; Self::BUFFER_LEN * 4],
+        }
+    }
+
+    pub fn update_view(&mut self, dataflow: &dataflow::Dataflow, rect: Rect2) {
+        let mut rendering_server = <godot::classes::RenderingServer as godot::obj::Singleton>::singleton();
+
+        let rect = IRect2::new(
+            dataflow.find_fluid_chunk_coord(rect.min),
+            dataflow.find_fluid_chunk_coord(rect.max),
+        );
+
+        // remove / insert view chunk
+
+        if Some(rect) != self.rect {
+            let mut chunk_coords = vec![];
+            for (chunk_coord, _) in &self.live_chunks {
+                if !Intersects::intersects(chunk_coord, &rect) {
+                    chunk_coords.push(*chunk_coord);
+                }
+            }
+            for chunk_coord in chunk_coords {
+                let live_chunk = self.live_chunks.remove(&chunk_coord).unwrap();
+
+                rendering_server.instance_set_visible(live_chunk.instance, false);
+
+                self.dead_chunks.push(live_chunk.despawn());
+            }
+
+            for y in rect.min.y..=rect.max.y {
+                for x in rect.min.x..=rect.max.x {
+                    let chunk_coord = IVec2::new(x, y);
+
+                    if self.live_chunks.contains_key(&chunk_coord) {
+                        continue;
+                    }
+
+                    let Some(dead_chunk) = self.dead_chunks.pop() else {
+                        let live_count = self.live_chunks.len();
+                        let dead_count = self.dead_chunks.len();
+                        panic!("no chunk available in pool (live:{}, dead:{})", live_count, dead_count);
+                    };
+
+                    rendering_server.instance_set_visible(dead_chunk.instance, true);
+
+                    self.live_chunks.insert(chunk_coord, dead_chunk.spawn());
+                }
+            }
+
+            self.rect = Some(rect);
+        }
+
+        // update view chunk
+
+        let light_ambient = dataflow.get_light_ambient() as f32 / dataflow::LightField::MAX_LEVEL as f32;
+
+        for (chunk_coord, live_chunk) in &mut self.live_chunks {
+            let Ok(chunk) = dataflow.get_fluid_chunk(*chunk_coord) else {
+                continue;
+            };
+
+            for material in &live_chunk.materials {
+                let tick = dataflow.get_tick() as i32;
+                rendering_server.material_set_param(*material, "tick", &godot::meta::ToGodot::to_variant(&tick));
+                rendering_server.material_set_param(*material, "light_ambient", &godot::meta::ToGodot::to_variant(&light_ambient));
+            }
+
+            let light_version = dataflow.get_light_chunk(*chunk_coord).map(|chunk| chunk.version).unwrap_or_default();
+            if live_chunk.light_version != Some(light_version) {
+                light::update_light_texture(&mut rendering_server, dataflow, *chunk_coord, live_chunk.light_texture, &live_chunk.materials);
+                live_chunk.light_version = Some(light_version);
+            }
+
+            if chunk.version <= live_chunk.version {
+                continue;
+            }
+
+            let origin = *chunk_coord * Self::CHUNK_SIZE;
+
+            let mut count = 0;
+            for (local_id, fluid) in chunk.fluids.iter().enumerate().take(Self::BUFFER_LEN) {
+                if fluid.level == 0 {
+                    continue;
+                }
+
+                let i = count;
+                let coord = origin + IVec2::new(local_id as i32 % Self::CHUNK_SIZE, local_id as i32 / Self::CHUNK_SIZE);
+
+                self.instance_buffer[i * 12] = 1.0;
+                self.instance_buffer[i * 12 + 1] = 0.0;
+                self.instance_buffer[i * 12 + 2] = 0.0;
+                self.instance_buffer[i * 12 + 3] = coord.x as f32;
+
+                self.instance_buffer[i * 12 + 4] = 0.0;
+                self.instance_buffer[i * 12 + 5] = 1.0;
+                self.instance_buffer[i * 12 + 6] = 0.0;
+                self.instance_buffer[i * 12 + 7] = coord.y as f32;
+
+                self.instance_buffer[i * 12 + 8] = 0.0;
+                self.instance_buffer[i * 12 + 9] = 0.0;
+                self.instance_buffer[i * 12 + 10] = 1.0;
+                self.instance_buffer[i * 12 + 11] = Self::Z_OFFSET;
+
+                // pick the sprite by fill level
+                let sprite_addr = &self.sprite_addrs[fluid.archetype_id as usize];
+                let variant = (fluid.level as usize - 1) * sprite_addr.len() / dataflow::FluidField::MAX_LEVEL as usize;
+                let image_addr = &sprite_addr[variant];
+                self.address_buffer[i * 4] = image_addr.atlas_start_index;
+                self.address_buffer[i * 4 + 1] = image_addr.atlas_end_index;
+                self.address_buffer[i * 4 + 2] = image_addr.tick_per_image as u32 | ((image_addr.is_loop as u32) << 16);
+                self.address_buffer[i * 4 + 3] = 0;
+
+                count += 1;
+            }
+
+            let instance_buffer = godot::builtin::PackedFloat32Array::from(self.instance_buffer.as_slice());
+            rendering_server.multimesh_set_buffer(live_chunk.multimesh, &instance_buffer);
+            rendering_server.multimesh_set_visible_instances(live_chunk.multimesh, count as i32);
+
+            let address_buffer = bytemuck::cast_slice::<_, i32>(self.address_buffer.as_slice());
+            let address_buffer = godot::builtin::PackedInt32Array::from(address_buffer);
+            for material in &live_chunk.materials {
+                rendering_server.material_set_param(*material, "head_buffer", &godot::meta::ToGodot::to_variant(&address_buffer));
+            }
+
+            live_chunk.version = chunk.version;
+        }
+    }
+}
+
+impl Drop for FluidField {
+    fn drop(&mut self) {
+        let mut rendering_server = <godot::classes::RenderingServer as godot::obj::Singleton>::singleton();
+        for free_handle in &self.free_handles {
+            rendering_server.free_rid(*free_handle);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use glam::*;
+
+use crate::dataflow;
+
+const CHUNK_SIZE: i32 = 32;
+
+// per-chunk light map sampled by field.gdshader, one texel per cell
+pub(super) fn create_light_texture(rendering_server: &mut godot::obj::Gd<godot::classes::RenderingServer>) -> godot::builtin::Rid {
+    let image = godot::classes::Image::create_empty(CHUNK_SIZE, CHUNK_SIZE, false, godot::classes::image::Format::L8).unwrap();
+    rendering_server.texture_2d_create(&image)
+}
+
+pub(super) fn update_light_texture(
+    rendering_server: &mut godot::obj::Gd<godot::classes::RenderingServer>,
+    dataflow: &dataflow::Dataflow,
+    chunk_coord: IVec2,
+    light_texture: godot::builtin::Rid,
+    materials: &[godot::builtin::Rid],
+) {
+    let mut buffer = vec![0u8; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+    if let Ok(chunk) = dataflow.get_light_chunk(chunk_coord) {
+        for (value, level) in buffer.iter_mut().zip(chunk.levels.iter()) {
+            *value = (*level as u32 * 255 / dataflow::LightField::MAX_LEVEL as u32) as u8;
+        }
+    }
+
+    let image = godot::classes::Image::create_from_data(
+        CHUNK_SIZE,
+        CHUNK_SIZE,
+        false,
+        godot::classes::image::Format::L8,
+        &godot::builtin::PackedByteArray::from(buffer),
+    )
+    .unwrap();
+    rendering_server.texture_2d_update(light_texture, &image, 0);
+
+    let light_origin = chunk_coord * CHUNK_SIZE;
+    let light_origin = godot::builtin::Vector2::new(light_origin.x as f32, light_origin.y as f32);
+    for material in materials {
+        rendering_server.material_set_param(*material, "light_origin", &godot::meta::ToGodot::to_variant(&light_origin));
+    }
+}
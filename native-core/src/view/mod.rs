@@ -1,7 +1,10 @@
 pub use block::*;
 pub use entity::*;
+pub use fluid::*;
 pub use tile::*;
 
 mod block;
 mod entity;
+mod fluid;
+mod light;
 mod tile;
@@ -26,11 +26,16 @@ pub struct AnimalData {
 
 pub struct AnimalResource {
     storage: Vec<AnimalData>,
+    // seeded per world so that a replay makes the same decisions
+    rng: rand::rngs::StdRng,
 }
 
 impl AnimalResource {
-    pub fn new() -> Self {
-        Self { storage: Default::default() }
+    pub fn new(seed: u64) -> Self {
+        Self {
+            storage: Default::default(),
+            rng: rand::SeedableRng::seed_from_u64(seed),
+        }
     }
 }
 
@@ -60,8 +65,9 @@ impl dataflow::EventHandler<dataflow::EntityId> for AnimalEventHandler {
         let resource = dataflow.find_resources::<AnimalResource>().unwrap();
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from).unwrap();
 
-        let index = resource.storage.iter().position(|data| data.entity_id == id).unwrap();
-        resource.storage.swap_remove(index);
+        if let Some(index) = resource.storage.iter().position(|data| data.entity_id == id) {
+            resource.storage.swap_remove(index);
+        }
     }
 }
 
@@ -73,8 +79,12 @@ impl AnimalSystem {
     pub fn process(dataflow: &mut dataflow::Dataflow, delta_secs: f32) -> Result<(), dataflow::DataflowError> {
         let resource = dataflow.find_resources::<AnimalResource>()?;
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
+        let resource = &mut *resource;
+
+        // entities removed while handlers were silenced, as by undo, are forgotten
+        resource.storage.retain(|data| dataflow.get_entity(data.entity_id).is_ok());
 
-        let mut rng = rand::thread_rng();
+        let rng = &mut resource.rng;
         for data in resource.storage.iter_mut() {
             let entity = dataflow.get_entity(data.entity_id).unwrap().clone();
 
@@ -85,7 +95,7 @@ impl AnimalSystem {
                 AnimalDataState::WaitStart => {
                     dataflow.modify_entity_variant(data.entity_id, IDLE_VARIANT).unwrap();
                     dataflow.modify_entity_tick(data.entity_id, dataflow.get_tick() as u32).unwrap();
-                    let wait_secs = rand::Rng::gen_range(&mut rng, data.min_rest_secs..data.max_rest_secs);
+                    let wait_secs = rand::Rng::gen_range(rng, data.min_rest_secs..data.max_rest_secs);
                     data.state = AnimalDataState::Wait(wait_secs);
                 }
                 AnimalDataState::Wait(wait_secs) => {
@@ -99,8 +109,8 @@ impl AnimalSystem {
                 AnimalDataState::TripStart => {
                     dataflow.modify_entity_variant(data.entity_id, WALK_VARIANT).unwrap();
                     dataflow.modify_entity_tick(data.entity_id, dataflow.get_tick() as u32).unwrap();
-                    let angle = rand::Rng::gen_range(&mut rng, 0.0..std::f32::consts::PI * 2.0);
-                    let distance = rand::Rng::gen_range(&mut rng, data.min_distance..data.max_distance);
+                    let angle = rand::Rng::gen_range(rng, 0.0..std::f32::consts::PI * 2.0);
+                    let distance = rand::Rng::gen_range(rng, data.min_distance..data.max_distance);
                     let destination = entity.coord + Vec2::from_angle(angle) * distance;
                     data.state = AnimalDataState::Trip(destination);
                 }
@@ -0,0 +1,111 @@
+use glam::*;
+use native_core::*;
+
+use super::{FractalNoise, FractalNoiseInfo, Generator, GeneratorStage, GeneratorSystem};
+
+#[derive(Debug, Clone)]
+pub struct BiomeSpawn {
+    pub archetype_id: u16,
+    pub probability: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Biome {
+    pub temperature: f32,
+    pub humidity: f32,
+    pub tiles: Vec<BiomeSpawn>,
+    pub blocks: Vec<BiomeSpawn>,
+    pub entities: Vec<BiomeSpawn>,
+}
+
+pub struct BiomeGenerator {
+    temperature: FractalNoise,
+    humidity: FractalNoise,
+    biomes: Vec<Biome>,
+}
+
+impl BiomeGenerator {
+    pub fn new(seed: u64, climate: FractalNoiseInfo, biomes: Vec<Biome>) -> Self {
+        assert!(!biomes.is_empty(), "biome must be declared");
+
+        Self {
+            temperature: FractalNoise::new(seed, climate.clone()),
+            humidity: FractalNoise::new(seed ^ 0x5f3759df5f3759df, climate),
+            biomes,
+        }
+    }
+
+    // pick the biome nearest to the sampled climate
+    pub fn find_biome(&self, coord: IVec2) -> &Biome {
+        let point = coord.as_vec2() + 0.5;
+        let climate = Vec2::new(self.temperature.sample(point), self.humidity.sample(point));
+
+        self.biomes
+            .iter()
+            .min_by(|a, b| {
+                let a = climate.distance_squared(Vec2::new(a.temperature, a.humidity));
+                let b = climate.distance_squared(Vec2::new(b.temperature, b.humidity));
+                a.total_cmp(&b)
+            })
+            .unwrap()
+    }
+
+    fn spawn_tiles(dataflow: &mut dataflow::Dataflow, biome: &Biome, coord: IVec2, rng: &mut rand::rngs::StdRng) {
+        for spawn in &biome.tiles {
+            if spawn.probability < rand::Rng::gen_range(rng, 0.0..1.0) {
+                continue;
+            }
+
+            let tile = dataflow::Tile { archetype_id: spawn.archetype_id, coord, ..Default::default() };
+            let _ = dataflow.insert_tile(tile);
+        }
+    }
+
+    fn spawn_blocks(dataflow: &mut dataflow::Dataflow, biome: &Biome, coord: IVec2, broad_rect: IRect2, rng: &mut rand::rngs::StdRng) {
+        for spawn in &biome.blocks {
+            if spawn.probability < rand::Rng::gen_range(rng, 0.0..1.0) {
+                continue;
+            }
+
+            let block = dataflow::Block { archetype_id: spawn.archetype_id, coord, ..Default::default() };
+            GeneratorSystem::insert_block_inside(dataflow, broad_rect, block);
+        }
+    }
+
+    fn spawn_entities(dataflow: &mut dataflow::Dataflow, biome: &Biome, coord: IVec2, rng: &mut rand::rngs::StdRng) {
+        for spawn in &biome.entities {
+            if spawn.probability < rand::Rng::gen_range(rng, 0.0..1.0) {
+                continue;
+            }
+
+            let offset = Vec2::new(rand::Rng::gen_range(rng, 0.0..1.0), rand::Rng::gen_range(rng, 0.0..1.0));
+            let coord = coord.as_vec2() + offset;
+            let entity = dataflow::Entity { archetype_id: spawn.archetype_id, coord, ..Default::default() };
+            let _ = dataflow.insert_entity(entity);
+        }
+    }
+}
+
+impl Generator for BiomeGenerator {
+    // tiles are laid as terrain, blocks as decoration and entities last
+    fn generate(&mut self, dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, rng: &mut rand::rngs::StdRng) {
+        // sampling the climate is the expensive part, skip the stages without spawns
+        if !matches!(stage, GeneratorStage::Terrain | GeneratorStage::Decoration | GeneratorStage::Entities) {
+            return;
+        }
+
+        for y in broad_rect.min.y..broad_rect.max.y {
+            for x in broad_rect.min.x..broad_rect.max.x {
+                let coord = IVec2::new(x, y);
+                let biome = self.find_biome(coord);
+
+                match stage {
+                    GeneratorStage::Terrain => Self::spawn_tiles(dataflow, biome, coord, rng),
+                    GeneratorStage::Decoration => Self::spawn_blocks(dataflow, biome, coord, broad_rect, rng),
+                    GeneratorStage::Entities => Self::spawn_entities(dataflow, biome, coord, rng),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -1,20 +1,44 @@
 use glam::*;
 use native_core::*;
 
-pub trait Generator {
-    fn generate(&self, dataflow: &mut dataflow::Dataflow, broad_rect: IRect2);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GeneratorStage {
+    Terrain,
+    Carving,
+    Features,
+    Decoration,
+    Entities,
+}
+
+impl GeneratorStage {
+    pub const ALL: [GeneratorStage; 5] = [
+        GeneratorStage::Terrain,
+        GeneratorStage::Carving,
+        GeneratorStage::Features,
+        GeneratorStage::Decoration,
+        GeneratorStage::Entities,
+    ];
+}
+
+// neighboring chunks run the same stage in any order, so a generator writes
+// only inside broad_rect and leaves cross-chunk placement to earlier stages
+pub trait Generator: Send {
+    fn generate(&mut self, dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, rng: &mut rand::rngs::StdRng);
 }
 
 // method for generating
 
 pub struct DiscreteGenerator<F> where F: Fn(&mut dataflow::Dataflow, IVec2) {
+    pub stage: GeneratorStage,
     pub probability: f32,
     pub sample_fn: F,
 }
 
-impl<F> Generator for DiscreteGenerator<F> where F: Fn(&mut dataflow::Dataflow, IVec2)  {
-    fn generate(&self, dataflow: &mut dataflow::Dataflow, broad_rect: IRect2) {
-        let rng = &mut rand::thread_rng();
+impl<F> Generator for DiscreteGenerator<F> where F: Fn(&mut dataflow::Dataflow, IVec2) + Send {
+    fn generate(&mut self, dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, rng: &mut rand::rngs::StdRng) {
+        if stage != self.stage {
+            return;
+        }
 
         for y in broad_rect.min.y..broad_rect.max.y {
             for x in broad_rect.min.x..broad_rect.max.x {
@@ -32,13 +56,16 @@ impl<F> Generator for DiscreteGenerator<F> where F: Fn(&mut dataflow::Dataflow,
 }
 
 pub struct RandomGenerator<F> where F: Fn(&mut dataflow::Dataflow, Vec2) {
+    pub stage: GeneratorStage,
     pub probability: f32,
     pub sample_fn: F,
 }
 
-impl<F> Generator for RandomGenerator<F> where F: Fn(&mut dataflow::Dataflow, Vec2) {
-    fn generate(&self, dataflow: &mut dataflow::Dataflow, broad_rect: IRect2) {
-        let rng = &mut rand::thread_rng();
+impl<F> Generator for RandomGenerator<F> where F: Fn(&mut dataflow::Dataflow, Vec2) + Send {
+    fn generate(&mut self, dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, rng: &mut rand::rngs::StdRng) {
+        if stage != self.stage {
+            return;
+        }
 
         let generate_count = (broad_rect.volume() as f32 * self.probability) as i32;
         for _ in 0..generate_count {
@@ -51,20 +78,225 @@ impl<F> Generator for RandomGenerator<F> where F: Fn(&mut dataflow::Dataflow, Ve
     }
 }
 
+// worker
+
+#[derive(Debug, Clone, Default)]
+struct ChunkData {
+    chunk_coord: IVec2,
+    tiles: Vec<dataflow::Tile>,
+    blocks: Vec<dataflow::Block>,
+    entities: Vec<dataflow::Entity>,
+}
+
+// the active area in chunks and the chunks in it that still have to be generated
+#[derive(Debug, Clone)]
+struct GeneratorRequest {
+    rect: IRect2,
+    chunk_coords: Vec<IVec2>,
+}
+
+// generation runs on a detached dataflow owned by the worker thread,
+// finished chunks are copied out and committed on the main thread
+struct GeneratorWorker {
+    request_tx: Option<std::sync::mpsc::Sender<GeneratorRequest>>,
+    reload_tx: std::sync::mpsc::Sender<dataflow::DetachedInfo>,
+    result_rx: std::sync::mpsc::Receiver<ChunkData>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl GeneratorWorker {
+    fn spawn(seed: u64, info: dataflow::DetachedInfo, generators: Vec<Box<dyn Generator>>) -> Self {
+        let (request_tx, request_rx) = std::sync::mpsc::channel::<GeneratorRequest>();
+        let (reload_tx, reload_rx) = std::sync::mpsc::channel();
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+
+        let handle = std::thread::spawn(move || {
+            let mut state = GeneratorWorkerState {
+                seed,
+                dataflow: dataflow::Dataflow::new_detached(info),
+                generators,
+                stages: Default::default(),
+            };
+            let mut queue = std::collections::VecDeque::new();
+
+            loop {
+                // reloaded archetypes apply before the next chunk
+                while let Ok(info) = reload_rx.try_recv() {
+                    state.reload(info);
+                }
+
+                // block only when there is nothing left to generate
+                let request = if queue.is_empty() {
+                    match request_rx.recv() {
+                        Ok(request) => Some(request),
+                        Err(_) => return,
+                    }
+                } else {
+                    match request_rx.try_recv() {
+                        Ok(request) => Some(request),
+                        Err(std::sync::mpsc::TryRecvError::Empty) => None,
+                        Err(std::sync::mpsc::TryRecvError::Disconnected) => return,
+                    }
+                };
+
+                // the latest request supersedes older ones
+                if let Some(mut request) = request {
+                    while let Ok(newer) = request_rx.try_recv() {
+                        request = newer;
+                    }
+                    state.evict(request.rect.extends(GeneratorWorkerState::NEIGHBOR_MARGIN));
+                    queue = request.chunk_coords.into();
+                    continue;
+                }
+
+                let Some(chunk_coord) = queue.pop_front() else {
+                    continue;
+                };
+
+                let chunk = state.generate(chunk_coord);
+                if result_tx.send(chunk).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            request_tx: Some(request_tx),
+            reload_tx,
+            result_rx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for GeneratorWorker {
+    fn drop(&mut self) {
+        // closing the request channel stops the worker after the current chunk
+        self.request_tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct GeneratorWorkerState {
+    seed: u64,
+    dataflow: dataflow::Dataflow,
+    generators: Vec<Box<dyn Generator>>,
+    stages: ahash::AHashMap<IVec2, usize>,
+}
+
+impl GeneratorWorkerState {
+    // the last stage of a chunk needs the chunks this far away to have started
+    const NEIGHBOR_MARGIN: i32 = GeneratorStage::ALL.len() as i32 - 1;
+
+    // a chunk that was generated before is copied out again as is
+    fn generate(&mut self, chunk_coord: IVec2) -> ChunkData {
+        self.advance(chunk_coord, GeneratorStage::ALL.len() - 1);
+
+        ChunkData {
+            chunk_coord,
+            tiles: self.dataflow.get_tile_chunk(chunk_coord).map(|chunk| chunk.tiles.clone()).unwrap_or_default(),
+            blocks: self.dataflow.get_block_chunk(chunk_coord).map(|chunk| chunk.blocks.clone()).unwrap_or_default(),
+            entities: self.dataflow.get_entity_chunk(chunk_coord).map(|chunk| chunk.entities.clone()).unwrap_or_default(),
+        }
+    }
+
+    // a chunk runs stage N only after all of its neighbors finished stage N - 1
+    fn advance(&mut self, chunk_coord: IVec2, stage_id: usize) {
+        let stage_count = GeneratorStage::ALL.len();
+
+        loop {
+            let completed = self.stages.get(&chunk_coord).copied().unwrap_or_default();
+            if completed > stage_id {
+                return;
+            }
+
+            if completed > 0 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let neighbor = chunk_coord + IVec2::new(x, y);
+                        if neighbor != chunk_coord {
+                            self.advance(neighbor, completed - 1);
+                        }
+                    }
+                }
+            }
+
+            let chunk_rect = IRect2::new(
+                chunk_coord * GeneratorSystem::CHUNK_SIZE as i32,
+                chunk_coord * GeneratorSystem::CHUNK_SIZE as i32 + GeneratorSystem::CHUNK_SIZE as i32,
+            );
+            let stage = GeneratorStage::ALL[completed];
+            for (generator_id, generator) in self.generators.iter_mut().enumerate() {
+                let stream_id = (generator_id * stage_count + completed) as u32;
+                let rng = &mut GeneratorSystem::derive_rng(self.seed, chunk_coord, stream_id);
+                generator.generate(&mut self.dataflow, stage, chunk_rect, rng);
+            }
+
+            self.stages.insert(chunk_coord, completed + 1);
+        }
+    }
+
+    // the archetypes changed, so everything generated so far is forgotten
+    fn reload(&mut self, info: dataflow::DetachedInfo) {
+        self.dataflow = dataflow::Dataflow::new_detached(info);
+        self.stages.clear();
+    }
+
+    // forgets the chunks outside keep_rect, they are generated from scratch when asked for again
+    fn evict(&mut self, keep_rect: IRect2) {
+        let chunk_coords = self
+            .stages
+            .keys()
+            .copied()
+            .filter(|chunk_coord| !GeneratorSystem::contains(keep_rect, *chunk_coord))
+            .collect::<Vec<_>>();
+
+        for chunk_coord in chunk_coords {
+            self.stages.remove(&chunk_coord);
+
+            let tile_ids = self.dataflow.get_tile_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+            for tile_id in tile_ids {
+                let _ = self.dataflow.remove_til(tile_id);
+            }
+            let block_ids = self.dataflow.get_block_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+            for block_id in block_ids {
+                let _ = self.dataflow.remove_block(block_id);
+            }
+            let entity_ids = self.dataflow.get_entity_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+            for entity_id in entity_ids {
+                let _ = self.dataflow.remove_entity(entity_id);
+            }
+        }
+    }
+}
+
 // resource
 
 pub struct GeneratorResource {
-    generators: Vec<Box<dyn Generator>>,
+    seed: u64,
+    generators: Option<Vec<Box<dyn Generator>>>,
+    worker: Option<GeneratorWorker>,
     rect: Option<IRect2>,
-    visited: ahash::AHashSet<IVec2>,
+    loaded_chunks: ahash::AHashMap<IVec2, [Option<u64>; 3]>,
+    stored_chunks: ahash::AHashMap<IVec2, ChunkData>,
+    restore_queue: std::collections::VecDeque<IVec2>,
+    // results kept by exact generation until the chunk is asked for
+    received_chunks: ahash::AHashMap<IVec2, ChunkData>,
 }
 
 impl GeneratorResource {
-    pub fn new(generators: Vec<Box<dyn Generator>>) -> Self {
+    pub fn new(seed: u64, generators: Vec<Box<dyn Generator>>) -> Self {
         Self {
-            generators,
+            seed,
+            generators: Some(generators),
+            worker: Default::default(),
             rect: Default::default(),
-            visited: Default::default(),
+            loaded_chunks: Default::default(),
+            stored_chunks: Default::default(),
+            restore_queue: Default::default(),
+            received_chunks: Default::default(),
         }
     }
 }
@@ -76,11 +308,128 @@ impl dataflow::Resource for GeneratorResource {}
 pub struct GeneratorSystem;
 
 impl GeneratorSystem {
-    const CHUNK_SIZE: u32 = 32;
+    pub const CHUNK_SIZE: u32 = 32;
+
+    // chunks are kept loaded this many chunks beyond the requested rect
+    const UNLOAD_MARGIN: i32 = 1;
+
+    // exact generation gives up on a chunk the worker did not send within this time
+    const EXACT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    // returns the chunks that were committed in this call
+    pub fn generate(dataflow: &mut dataflow::Dataflow, rect: Rect2, focus: Vec2, budget: std::time::Duration) -> Result<Vec<IVec2>, dataflow::DataflowError> {
+        let start = std::time::Instant::now();
 
-    pub fn generate(dataflow: &mut dataflow::Dataflow, rect: Rect2) -> Result<(), dataflow::DataflowError> {
         let resource = dataflow.find_resources::<GeneratorResource>()?;
         let mut resource = resource.borrow_mut()?;
+        let resource = &mut *resource;
+
+        let Some(rect) = Self::request(dataflow, resource, rect, focus) else {
+            return Ok(vec![]);
+        };
+        let worker = resource.worker.as_ref().unwrap();
+
+        // commit whole chunks until the frame budget is spent
+        let mut committed = vec![];
+        while start.elapsed() < budget {
+            if let Some(chunk_coord) = resource.restore_queue.pop_front() {
+                if let Some(chunk) = resource.stored_chunks.remove(&chunk_coord) {
+                    let versions = Self::load_chunk(dataflow, chunk);
+                    resource.loaded_chunks.insert(chunk_coord, versions);
+                    committed.push(chunk_coord);
+                }
+                continue;
+            }
+
+            let Ok(chunk) = worker.result_rx.try_recv() else {
+                break;
+            };
+
+            // late results for chunks that are loaded, stored or out of the area are dropped
+            let chunk_coord = chunk.chunk_coord;
+            if resource.loaded_chunks.contains_key(&chunk_coord)
+                || resource.stored_chunks.contains_key(&chunk_coord)
+                || !Self::contains(rect, chunk_coord)
+            {
+                continue;
+            }
+
+            let versions = Self::load_chunk(dataflow, chunk);
+            resource.loaded_chunks.insert(chunk_coord, versions);
+            committed.push(chunk_coord);
+        }
+
+        Ok(committed)
+    }
+
+    // commits exactly the given chunks in order, waiting for the worker when needed,
+    // replays use it to get the same chunks in the same frames as the recorded session
+    pub fn generate_exact(dataflow: &mut dataflow::Dataflow, rect: Rect2, focus: Vec2, chunk_coords: &[IVec2]) -> Result<(), GeneratorError> {
+        let resource = dataflow.find_resources::<GeneratorResource>()?;
+        let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
+        let resource = &mut *resource;
+
+        let Some(rect) = Self::request(dataflow, resource, rect, focus) else {
+            return Ok(());
+        };
+        let worker = resource.worker.as_ref().unwrap();
+
+        // results kept from earlier calls are only useful inside the current area
+        resource.received_chunks.retain(|chunk_coord, _| Self::contains(rect, *chunk_coord));
+
+        for &chunk_coord in chunk_coords {
+            if let Some(index) = resource.restore_queue.iter().position(|restore_coord| *restore_coord == chunk_coord) {
+                resource.restore_queue.remove(index);
+                if let Some(chunk) = resource.stored_chunks.remove(&chunk_coord) {
+                    let versions = Self::load_chunk(dataflow, chunk);
+                    resource.loaded_chunks.insert(chunk_coord, versions);
+                }
+                continue;
+            }
+
+            // the worker generates every chunk of the latest request,
+            // a chunk that does not come means the replay asks for something this run never requested
+            let chunk = loop {
+                if let Some(chunk) = resource.received_chunks.remove(&chunk_coord) {
+                    break chunk;
+                }
+                let Ok(chunk) = worker.result_rx.recv_timeout(Self::EXACT_TIMEOUT) else {
+                    return Err(GeneratorError::Desync(chunk_coord));
+                };
+                if Self::contains(rect, chunk.chunk_coord) && !resource.loaded_chunks.contains_key(&chunk.chunk_coord) {
+                    resource.received_chunks.insert(chunk.chunk_coord, chunk);
+                }
+            };
+
+            let versions = Self::load_chunk(dataflow, chunk);
+            resource.loaded_chunks.insert(chunk_coord, versions);
+        }
+
+        Ok(())
+    }
+
+    // hands reloaded archetypes to the worker and asks for the missing chunks again,
+    // chunks that are already loaded or stored are kept as they are
+    pub fn reload(dataflow: &mut dataflow::Dataflow) -> Result<(), dataflow::DataflowError> {
+        let resource = dataflow.find_resources::<GeneratorResource>()?;
+        let mut resource = resource.borrow_mut()?;
+
+        if let Some(worker) = &resource.worker {
+            let _ = worker.reload_tx.send(dataflow.get_detached_info().clone());
+            resource.rect = None;
+        }
+
+        Ok(())
+    }
+
+    // updates the active area and the worker request, returns the area in chunks
+    fn request(dataflow: &mut dataflow::Dataflow, resource: &mut GeneratorResource, rect: Rect2, focus: Vec2) -> Option<IRect2> {
+        // generators move to the worker on first use
+        if let Some(generators) = resource.generators.take() {
+            let info = dataflow.get_detached_info().clone();
+            resource.worker = Some(GeneratorWorker::spawn(resource.seed, info, generators));
+        }
+        let worker = resource.worker.as_ref()?;
 
         let chunk_size = Vec2::splat(Self::CHUNK_SIZE as f32);
         let rect = IRect2::new(
@@ -89,29 +438,493 @@ impl GeneratorSystem {
         );
 
         if Some(rect) != resource.rect {
+            // save and remove chunks that left the active area, in a fixed order so that runs agree
+            let keep_rect = rect.extends(Self::UNLOAD_MARGIN);
+            let mut unload_chunk_coords = resource
+                .loaded_chunks
+                .keys()
+                .copied()
+                .filter(|chunk_coord| !Self::contains(keep_rect, *chunk_coord))
+                .collect::<Vec<_>>();
+            unload_chunk_coords.sort_by_key(|chunk_coord| chunk_coord.to_array());
+            for chunk_coord in unload_chunk_coords {
+                let versions = resource.loaded_chunks.remove(&chunk_coord).unwrap();
+                if let Some(chunk) = Self::unload_chunk(dataflow, chunk_coord, versions) {
+                    resource.stored_chunks.insert(chunk_coord, chunk);
+                }
+            }
+
+            let mut chunk_coords = vec![];
             for y in rect.min.y..=rect.max.y {
                 for x in rect.min.x..=rect.max.x {
                     let chunk_coord = IVec2::new(x, y);
-
-                    if resource.visited.contains(&chunk_coord) {
-                        continue;
+                    if !resource.loaded_chunks.contains_key(&chunk_coord) {
+                        chunk_coords.push(chunk_coord);
                     }
+                }
+            }
 
-                    let rect = IRect2::new(
-                        chunk_coord * Self::CHUNK_SIZE as i32,
-                        chunk_coord * Self::CHUNK_SIZE as i32 + Self::CHUNK_SIZE as i32,
-                    );
-                    for generator in &resource.generators {
-                        generator.generate(dataflow, rect);
-                    }
+            // nearest chunks first
+            let focus = focus / chunk_size - 0.5;
+            chunk_coords.sort_by(|a, b| {
+                let a = a.as_vec2().distance_squared(focus);
+                let b = b.as_vec2().distance_squared(focus);
+                a.total_cmp(&b)
+            });
 
-                    resource.visited.insert(chunk_coord);
-                }
+            // modified chunks come back from the store, the rest is regenerated
+            let (restore_chunk_coords, generate_chunk_coords): (Vec<_>, Vec<_>) = chunk_coords
+                .into_iter()
+                .partition(|chunk_coord| resource.stored_chunks.contains_key(chunk_coord));
+            resource.restore_queue = restore_chunk_coords.into();
+
+            if let Some(request_tx) = &worker.request_tx {
+                let _ = request_tx.send(GeneratorRequest { rect, chunk_coords: generate_chunk_coords });
             }
 
             resource.rect = Some(rect);
         }
 
-        Ok(())
+        Some(rect)
+    }
+
+    fn load_chunk(dataflow: &mut dataflow::Dataflow, chunk: ChunkData) -> [Option<u64>; 3] {
+        for tile in chunk.tiles {
+            let _ = dataflow.insert_tile(tile);
+        }
+        for block in chunk.blocks {
+            let _ = dataflow.insert_block(block);
+        }
+        for entity in chunk.entities {
+            let _ = dataflow.insert_entity(entity);
+        }
+
+        Self::chunk_versions(dataflow, chunk.chunk_coord)
+    }
+
+    // returns the chunk contents only when they changed since they were loaded
+    fn unload_chunk(dataflow: &mut dataflow::Dataflow, chunk_coord: IVec2, versions: [Option<u64>; 3]) -> Option<ChunkData> {
+        let is_modified = Self::chunk_versions(dataflow, chunk_coord) != versions;
+
+        let mut chunk = ChunkData { chunk_coord, ..Default::default() };
+        let tile_ids = dataflow.get_tile_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+        for tile_id in tile_ids {
+            if let Ok(tile) = dataflow.remove_til(tile_id) {
+                chunk.tiles.push(tile);
+            }
+        }
+        let block_ids = dataflow.get_block_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+        for block_id in block_ids {
+            if let Ok(block) = dataflow.remove_block(block_id) {
+                chunk.blocks.push(block);
+            }
+        }
+        let entity_ids = dataflow.get_entity_chunk(chunk_coord).map(|chunk| chunk.ids.clone()).unwrap_or_default();
+        for entity_id in entity_ids {
+            if let Ok(entity) = dataflow.remove_entity(entity_id) {
+                chunk.entities.push(entity);
+            }
+        }
+
+        is_modified.then_some(chunk)
+    }
+
+    fn chunk_versions(dataflow: &dataflow::Dataflow, chunk_coord: IVec2) -> [Option<u64>; 3] {
+        [
+            dataflow.get_tile_chunk(chunk_coord).ok().map(|chunk| chunk.version),
+            dataflow.get_block_chunk(chunk_coord).ok().map(|chunk| chunk.version),
+            dataflow.get_entity_chunk(chunk_coord).ok().map(|chunk| chunk.version),
+        ]
+    }
+
+    // inserts the block only when it stays inside the generated chunk,
+    // a block reaching into a neighbor would collide with it depending on the order
+    pub fn insert_block_inside(dataflow: &mut dataflow::Dataflow, broad_rect: IRect2, block: dataflow::Block) {
+        let Ok(archetype) = dataflow.get_block_archetype(block.archetype_id) else {
+            return;
+        };
+        let rect = archetype.rect(block.coord);
+        if broad_rect.min.cmple(rect.min).all() && rect.max.cmplt(broad_rect.max).all() {
+            let _ = dataflow.insert_block(block);
+        }
+    }
+
+    #[inline]
+    fn contains(rect: IRect2, coord: IVec2) -> bool {
+        rect.min.cmple(coord).all() && coord.cmple(rect.max).all()
+    }
+
+    // derive an independent stream from (seed, coord, stream) so the result
+    // of a chunk never depends on the order in which chunks are visited
+    pub fn derive_rng(seed: u64, coord: IVec2, stream_id: u32) -> rand::rngs::StdRng {
+        let mut hash = seed;
+        for value in [coord.x as u32 as u64, coord.y as u32 as u64, stream_id as u64] {
+            hash = Self::mix(hash ^ value);
+        }
+        rand::SeedableRng::seed_from_u64(hash)
+    }
+
+    // splitmix64 finalizer
+    fn mix(mut value: u64) -> u64 {
+        value = value.wrapping_add(0x9e3779b97f4a7c15);
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratorError {
+    DataflowError(dataflow::DataflowError),
+    Desync(IVec2),
+}
+
+impl std::fmt::Display for GeneratorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DataflowError(e) => e.fmt(f),
+            Self::Desync(chunk_coord) => write!(f, "desync error: chunk {} was not generated", chunk_coord),
+        }
+    }
+}
+
+impl std::error::Error for GeneratorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DataflowError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<dataflow::DataflowError> for GeneratorError {
+    fn from(e: dataflow::DataflowError) -> Self {
+        Self::DataflowError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addon::{Biome, BiomeGenerator, BiomeSpawn, Structure, StructureGenerator, StructurePart};
+
+    fn make_info() -> dataflow::DetachedInfo {
+        dataflow::DetachedInfo {
+            tile_field: dataflow::TileFieldInfo {
+                tiles: vec![dataflow::TileInfo {
+                    display_name: "tile_0".into(),
+                    description: "tile_0_desc".into(),
+                    collision: false,
+                    layer_id: 0,
+                }],
+                layers: vec![dataflow::TileLayerInfo {
+                    display_name: "layer_0".into(),
+                    description: "layer_0_desc".into(),
+                }],
+            },
+            block_field: dataflow::BlockFieldInfo {
+                blocks: vec![dataflow::BlockInfo {
+                    display_name: "block_0".into(),
+                    description: "block_0_desc".into(),
+                    size: IVec2::new(3, 2),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::ZERO, Vec2::new(3.0, 2.0)),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                }],
+            },
+            entity_field: dataflow::EntityFieldInfo { entities: vec![] },
+            fluid_field: dataflow::FluidFieldInfo { fluids: vec![] },
+            light_field: dataflow::LightFieldInfo { ambient: 0 },
+        }
+    }
+
+    #[test]
+    fn worker_generates_chunk() {
+        let generator = DiscreteGenerator {
+            stage: GeneratorStage::Terrain,
+            probability: 1.0,
+            sample_fn: |dataflow: &mut dataflow::Dataflow, coord| {
+                let _ = dataflow.insert_tile(dataflow::Tile { coord, ..Default::default() });
+            },
+        };
+        let worker = GeneratorWorker::spawn(0, make_info(), vec![Box::new(generator)]);
+
+        let request = GeneratorRequest {
+            rect: IRect2::new(IVec2::new(1, -1), IVec2::new(1, -1)),
+            chunk_coords: vec![IVec2::new(1, -1)],
+        };
+        worker.request_tx.as_ref().unwrap().send(request).unwrap();
+        let chunk = worker.result_rx.recv_timeout(std::time::Duration::from_secs(10)).unwrap();
+        assert_eq!(chunk.chunk_coord, IVec2::new(1, -1));
+        assert_eq!(chunk.tiles.len(), (GeneratorSystem::CHUNK_SIZE * GeneratorSystem::CHUNK_SIZE) as usize);
+    }
+
+    #[test]
+    fn generate_in_any_order() {
+        let make_state = || GeneratorWorkerState {
+            seed: 7,
+            dataflow: dataflow::Dataflow::new_detached(make_info()),
+            generators: vec![
+                Box::new(StructureGenerator::new(7, 16, 1.0, vec![Structure {
+                    parts: (0..4).map(|i| StructurePart::Block { archetype_id: 0, offset: IVec2::new(i * 3, i) }).collect(),
+                }])),
+                Box::new(BiomeGenerator::new(7, Default::default(), vec![Biome {
+                    blocks: vec![BiomeSpawn { archetype_id: 0, probability: 0.2 }],
+                    ..Default::default()
+                }])),
+            ],
+            stages: Default::default(),
+        };
+        let sorted = |chunk: ChunkData| {
+            let mut blocks = chunk.blocks.iter().map(|block| (block.coord.to_array(), block.archetype_id)).collect::<Vec<_>>();
+            blocks.sort();
+            blocks
+        };
+
+        let mut state = make_state();
+        let chunk = sorted(state.generate(IVec2::ZERO));
+        assert!(!chunk.is_empty());
+
+        // the neighbors finish first, so they see none of the chunk's own blocks
+        let mut state = make_state();
+        for y in -1..=1 {
+            for x in -1..=1 {
+                state.generate(IVec2::new(x, y));
+            }
+        }
+        let mut state_reversed = make_state();
+        for y in (-1..=1).rev() {
+            for x in (-1..=1).rev() {
+                state_reversed.generate(IVec2::new(x, y));
+            }
+        }
+        assert_eq!(sorted(state.generate(IVec2::ZERO)), chunk);
+        assert_eq!(sorted(state_reversed.generate(IVec2::ZERO)), chunk);
+    }
+
+    struct RecordGenerator {
+        records: std::sync::Arc<std::sync::Mutex<Vec<(IVec2, GeneratorStage)>>>,
+    }
+
+    impl Generator for RecordGenerator {
+        fn generate(&mut self, _dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, _rng: &mut rand::rngs::StdRng) {
+            let chunk_coord = broad_rect.min / GeneratorSystem::CHUNK_SIZE as i32;
+            self.records.lock().unwrap().push((chunk_coord, stage));
+        }
+    }
+
+    #[test]
+    fn advance_after_neighbors() {
+        let records = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut state = GeneratorWorkerState {
+            seed: 0,
+            dataflow: dataflow::Dataflow::new_detached(make_info()),
+            generators: vec![Box::new(RecordGenerator { records: records.clone() })],
+            stages: Default::default(),
+        };
+        state.generate(IVec2::ZERO);
+
+        // the stage count falls by one per chunk of distance
+        for y in -5..=5 {
+            for x in -5..=5 {
+                let distance = IVec2::new(x, y).abs().max_element() as usize;
+                let expected = GeneratorStage::ALL.len().checked_sub(distance).filter(|stage_count| *stage_count > 0);
+                assert_eq!(state.stages.get(&IVec2::new(x, y)).copied(), expected);
+            }
+        }
+
+        // every stage runs after the previous stage of all neighbors
+        let recorded = records.lock().unwrap().clone();
+        for (index, (chunk_coord, stage)) in recorded.iter().enumerate() {
+            let Some(previous) = GeneratorStage::ALL.iter().position(|other| other == stage).unwrap().checked_sub(1) else {
+                continue;
+            };
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let neighbor = *chunk_coord + IVec2::new(x, y);
+                    assert!(recorded[..index].contains(&(neighbor, GeneratorStage::ALL[previous])));
+                }
+            }
+        }
+
+        // generating again copies the chunk out without running any stage
+        assert_eq!(state.generate(IVec2::ZERO).chunk_coord, IVec2::ZERO);
+        assert_eq!(records.lock().unwrap().len(), recorded.len());
+    }
+
+    #[test]
+    fn evict_chunks() {
+        let generator = DiscreteGenerator {
+            stage: GeneratorStage::Terrain,
+            probability: 1.0,
+            sample_fn: |dataflow: &mut dataflow::Dataflow, coord| {
+                let _ = dataflow.insert_tile(dataflow::Tile { coord, ..Default::default() });
+            },
+        };
+        let mut state = GeneratorWorkerState {
+            seed: 0,
+            dataflow: dataflow::Dataflow::new_detached(make_info()),
+            generators: vec![Box::new(generator)],
+            stages: Default::default(),
+        };
+        let chunk = state.generate(IVec2::ZERO);
+
+        let keep_rect = IRect2::new(IVec2::ZERO, IVec2::ZERO).extends(GeneratorWorkerState::NEIGHBOR_MARGIN);
+        state.evict(keep_rect);
+        assert_eq!(state.stages.len(), 81);
+
+        // only the chunks outside the kept rect are forgotten
+        state.evict(IRect2::new(IVec2::ZERO, IVec2::new(1, 0)));
+        assert_eq!(state.stages.keys().copied().collect::<ahash::AHashSet<_>>(), [IVec2::ZERO, IVec2::new(1, 0)].into());
+        assert!(state.dataflow.get_tile_chunk(IVec2::new(-1, 0)).map_or(true, |chunk| chunk.tiles.is_empty()));
+        assert_eq!(state.dataflow.get_tile_chunk(IVec2::ZERO).unwrap().tiles.len(), chunk.tiles.len());
+
+        state.evict(IRect2::new(IVec2::new(10, 0), IVec2::new(10, 0)));
+        assert!(state.stages.is_empty());
+        assert!(state.dataflow.get_tile_chunk(IVec2::ZERO).unwrap().tiles.is_empty());
+
+        // forgotten chunks come back the same
+        assert_eq!(state.generate(IVec2::ZERO).tiles.len(), chunk.tiles.len());
+    }
+
+    // a resource with a worker whose channels are driven by the test
+    fn make_fake_resource(dataflow: &mut dataflow::Dataflow) -> (std::sync::mpsc::Sender<ChunkData>, std::sync::mpsc::Receiver<GeneratorRequest>) {
+        let (request_tx, request_rx) = std::sync::mpsc::channel();
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+
+        let mut resource = GeneratorResource::new(0, vec![]);
+        resource.generators = None;
+        resource.worker = Some(GeneratorWorker {
+            request_tx: Some(request_tx),
+            reload_tx: std::sync::mpsc::channel().0,
+            result_rx,
+            handle: None,
+        });
+        dataflow.insert_resources(resource).unwrap();
+
+        (result_tx, request_rx)
+    }
+
+    fn make_chunk(chunk_coord: IVec2) -> ChunkData {
+        let coord = chunk_coord * GeneratorSystem::CHUNK_SIZE as i32;
+        ChunkData {
+            chunk_coord,
+            tiles: vec![dataflow::Tile { coord, ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    fn tile_len(dataflow: &dataflow::Dataflow, chunk_coord: IVec2) -> usize {
+        dataflow.get_tile_chunk(chunk_coord).map(|chunk| chunk.tiles.len()).unwrap_or_default()
+    }
+
+    #[test]
+    fn drop_late_results() {
+        let mut dataflow = dataflow::Dataflow::new_detached(make_info());
+        let (result_tx, request_rx) = make_fake_resource(&mut dataflow);
+        let rect = Rect2::new(Vec2::ZERO, Vec2::splat(31.0));
+        let budget = std::time::Duration::from_secs(1);
+
+        let committed = GeneratorSystem::generate(&mut dataflow, rect, Vec2::ZERO, budget).unwrap();
+        assert!(committed.is_empty());
+        let request = request_rx.try_recv().unwrap();
+        assert_eq!(request.chunk_coords, [IVec2::ZERO]);
+
+        // a chunk out of the area and a second copy of a loaded chunk are dropped
+        result_tx.send(make_chunk(IVec2::ZERO)).unwrap();
+        result_tx.send(make_chunk(IVec2::new(3, 0))).unwrap();
+        result_tx.send(make_chunk(IVec2::ZERO)).unwrap();
+        let committed = GeneratorSystem::generate(&mut dataflow, rect, Vec2::ZERO, budget).unwrap();
+        assert_eq!(committed, [IVec2::ZERO]);
+        assert_eq!(tile_len(&dataflow, IVec2::ZERO), 1);
+        assert_eq!(tile_len(&dataflow, IVec2::new(3, 0)), 0);
+    }
+
+    #[test]
+    fn store_restore_regenerate() {
+        let mut dataflow = dataflow::Dataflow::new_detached(make_info());
+        let (result_tx, request_rx) = make_fake_resource(&mut dataflow);
+        let rect = Rect2::new(Vec2::ZERO, Vec2::new(31.0, 63.0));
+        let far_rect = rect + Vec2::new(160.0, 0.0);
+        let budget = std::time::Duration::from_secs(1);
+
+        GeneratorSystem::generate(&mut dataflow, rect, Vec2::ZERO, budget).unwrap();
+        result_tx.send(make_chunk(IVec2::new(0, 0))).unwrap();
+        result_tx.send(make_chunk(IVec2::new(0, 1))).unwrap();
+        let committed = GeneratorSystem::generate(&mut dataflow, rect, Vec2::ZERO, budget).unwrap();
+        assert_eq!(committed, [IVec2::new(0, 0), IVec2::new(0, 1)]);
+
+        // only the modified chunk is kept when the area moves away
+        dataflow.insert_tile(dataflow::Tile { coord: IVec2::new(1, 0), ..Default::default() }).unwrap();
+        GeneratorSystem::generate(&mut dataflow, far_rect, Vec2::ZERO, budget).unwrap();
+        assert_eq!(tile_len(&dataflow, IVec2::new(0, 0)), 0);
+        assert_eq!(tile_len(&dataflow, IVec2::new(0, 1)), 0);
+        {
+            let resource = dataflow.find_resources::<GeneratorResource>().unwrap();
+            let resource = resource.borrow().unwrap();
+            assert_eq!(resource.stored_chunks.keys().copied().collect::<Vec<_>>(), [IVec2::new(0, 0)]);
+        }
+
+        // the stored chunk is restored and the other one is asked for again
+        let committed = GeneratorSystem::generate(&mut dataflow, rect, Vec2::ZERO, budget).unwrap();
+        assert_eq!(committed, [IVec2::new(0, 0)]);
+        assert_eq!(tile_len(&dataflow, IVec2::new(0, 0)), 2);
+        let request = request_rx.try_iter().last().unwrap();
+        assert_eq!(request.chunk_coords, [IVec2::new(0, 1)]);
+    }
+
+    #[test]
+    fn generate_exact_in_area() {
+        let mut dataflow = dataflow::Dataflow::new_detached(make_info());
+        let (result_tx, _request_rx) = make_fake_resource(&mut dataflow);
+        let rect = Rect2::new(Vec2::ZERO, Vec2::new(63.0, 31.0));
+
+        // results out of the area are not kept while waiting
+        result_tx.send(make_chunk(IVec2::new(5, 0))).unwrap();
+        result_tx.send(make_chunk(IVec2::new(1, 0))).unwrap();
+        result_tx.send(make_chunk(IVec2::new(0, 0))).unwrap();
+        GeneratorSystem::generate_exact(&mut dataflow, rect, Vec2::ZERO, &[IVec2::new(0, 0)]).unwrap();
+        assert_eq!(tile_len(&dataflow, IVec2::new(0, 0)), 1);
+        {
+            let resource = dataflow.find_resources::<GeneratorResource>().unwrap();
+            let resource = resource.borrow().unwrap();
+            assert_eq!(resource.received_chunks.keys().copied().collect::<Vec<_>>(), [IVec2::new(1, 0)]);
+        }
+
+        // kept results are dropped once the area moves away
+        let far_rect = rect + Vec2::new(0.0, 64.0);
+        result_tx.send(make_chunk(IVec2::new(0, 2))).unwrap();
+        GeneratorSystem::generate_exact(&mut dataflow, far_rect, Vec2::ZERO, &[IVec2::new(0, 2)]).unwrap();
+        {
+            let resource = dataflow.find_resources::<GeneratorResource>().unwrap();
+            let resource = resource.borrow().unwrap();
+            assert!(resource.received_chunks.is_empty());
+        }
+
+        // a chunk that never comes is reported instead of waited for
+        drop(result_tx);
+        let result = GeneratorSystem::generate_exact(&mut dataflow, far_rect, Vec2::ZERO, &[IVec2::new(1, 2)]);
+        assert_eq!(result, Err(GeneratorError::Desync(IVec2::new(1, 2))));
+    }
+
+    #[test]
+    fn reload_state() {
+        let mut state = GeneratorWorkerState {
+            seed: 0,
+            dataflow: dataflow::Dataflow::new_detached(make_info()),
+            generators: vec![],
+            stages: Default::default(),
+        };
+        state.generate(IVec2::ZERO);
+
+        let mut info = make_info();
+        info.block_field.blocks[0].size = IVec2::new(1, 1);
+        state.reload(info);
+        assert!(state.stages.is_empty());
+        assert_eq!(state.dataflow.get_block_archetype(0).unwrap().size, IVec2::new(1, 1));
     }
 }
@@ -1,7 +1,13 @@
 pub use animal::*;
+pub use biome::*;
 pub use generator::*;
+pub use noise::*;
 pub use player::*;
+pub use structure::*;
 
 mod animal;
+mod biome;
 mod generator;
+mod noise;
 mod player;
+mod structure;
@@ -0,0 +1,123 @@
+use glam::*;
+
+// gradient noise
+
+pub struct PerlinNoise {
+    permutation: Vec<u8>,
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> Self {
+        let rng = &mut <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(seed);
+
+        let mut permutation = (0..=255).collect::<Vec<u8>>();
+        rand::seq::SliceRandom::shuffle(permutation.as_mut_slice(), rng);
+        permutation.extend_from_within(..);
+
+        Self { permutation }
+    }
+
+    // sample in range [-1, 1]
+    pub fn sample(&self, point: Vec2) -> f32 {
+        let cell = point.floor();
+        let local = point - cell;
+        let x = cell.x as i32 & 255;
+        let y = cell.y as i32 & 255;
+
+        let fade = local * local * local * (local * (local * 6.0 - 15.0) + 10.0);
+
+        let v00 = Self::gradient(self.hash(x, y), local);
+        let v10 = Self::gradient(self.hash(x + 1, y), local - Vec2::X);
+        let v01 = Self::gradient(self.hash(x, y + 1), local - Vec2::Y);
+        let v11 = Self::gradient(self.hash(x + 1, y + 1), local - Vec2::ONE);
+
+        let v0 = v00 + (v10 - v00) * fade.x;
+        let v1 = v01 + (v11 - v01) * fade.x;
+        let value = v0 + (v1 - v0) * fade.y;
+
+        (value * std::f32::consts::SQRT_2).clamp(-1.0, 1.0)
+    }
+
+    fn hash(&self, x: i32, y: i32) -> u8 {
+        let index = self.permutation[x as usize] as usize + y as usize;
+        self.permutation[index]
+    }
+
+    fn gradient(hash: u8, offset: Vec2) -> f32 {
+        let value = match hash & 7 {
+            0 => offset.x + offset.y,
+            1 => -offset.x + offset.y,
+            2 => offset.x - offset.y,
+            3 => -offset.x - offset.y,
+            4 => offset.x,
+            5 => -offset.x,
+            6 => offset.y,
+            _ => -offset.y,
+        };
+        value * std::f32::consts::FRAC_1_SQRT_2
+    }
+}
+
+// fractal brownian motion
+
+#[derive(Debug, Clone)]
+pub struct FractalNoiseInfo {
+    pub octaves: u32,
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+}
+
+impl Default for FractalNoiseInfo {
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            frequency: 1.0 / 64.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+pub struct FractalNoise {
+    noise: PerlinNoise,
+    octaves: u32,
+    frequency: f32,
+    lacunarity: f32,
+    persistence: f32,
+}
+
+impl FractalNoise {
+    pub fn new(seed: u64, info: FractalNoiseInfo) -> Self {
+        Self {
+            noise: PerlinNoise::new(seed),
+            octaves: info.octaves,
+            frequency: info.frequency,
+            lacunarity: info.lacunarity,
+            persistence: info.persistence,
+        }
+    }
+
+    // sample in range [-1, 1]
+    pub fn sample(&self, point: Vec2) -> f32 {
+        let mut value = 0.0;
+        let mut total_amplitude = 0.0;
+
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        for octave in 0..self.octaves {
+            // shift each octave so lattice points do not line up
+            let offset = Vec2::splat(octave as f32 * 31.7);
+            value += self.noise.sample(point * frequency + offset) * amplitude;
+            total_amplitude += amplitude;
+
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+
+        if total_amplitude == 0.0 {
+            return 0.0;
+        }
+        value / total_amplitude
+    }
+}
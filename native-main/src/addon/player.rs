@@ -3,31 +3,51 @@ use native_core::*;
 
 // resource
 
+// the player of the local instance, remote players use the ids of their connections
+pub type PlayerId = u32;
+
+pub const LOCAL_PLAYER: PlayerId = 0;
+
 pub enum PlayerState {
     Wait,
     Move,
 }
 
-pub struct PlayerResource {
+struct Player {
     current: Option<dataflow::EntityId>,
     input: Option<Vec2>,
     state: PlayerState,
-    move_speed: f32,
     reverse: bool,
 }
 
-impl PlayerResource {
-    pub fn new() -> Self {
+impl Player {
+    fn new() -> Self {
         Self {
             current: Default::default(),
             input: Default::default(),
             state: PlayerState::Wait,
-            move_speed: 2.0,
             reverse: false,
         }
     }
 }
 
+pub struct PlayerResource {
+    // ordered so that players are processed in the same order on every run
+    players: std::collections::BTreeMap<PlayerId, Player>,
+    spawning: Option<PlayerId>,
+    move_speed: f32,
+}
+
+impl PlayerResource {
+    pub fn new() -> Self {
+        Self {
+            players: Default::default(),
+            spawning: Default::default(),
+            move_speed: 2.0,
+        }
+    }
+}
+
 impl dataflow::Resource for PlayerResource {}
 
 // event handler
@@ -39,20 +59,21 @@ impl dataflow::EventHandler<dataflow::EntityId> for PlayerEventHandler {
         let resource = dataflow.find_resources::<PlayerResource>().unwrap();
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from).unwrap();
 
-        if resource.current.is_some() {
-            panic!("player is already exist.");
+        let player_id = resource.spawning.take().unwrap_or(LOCAL_PLAYER);
+        let player = resource.players.entry(player_id).or_insert_with(Player::new);
+        // another player entity inserted through the scripts stays uncontrolled
+        if player.current.is_none() {
+            player.current = Some(id);
         }
-        resource.current = Some(id);
     }
 
-    fn on_remove(&self, dataflow: &mut dataflow::Dataflow, _: dataflow::EntityId) {
+    fn on_remove(&self, dataflow: &mut dataflow::Dataflow, id: dataflow::EntityId) {
         let resource = dataflow.find_resources::<PlayerResource>().unwrap();
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from).unwrap();
 
-        if resource.current.is_none() {
-            panic!("player is already no exist.");
+        if let Some(player) = resource.players.values_mut().find(|player| player.current == Some(id)) {
+            player.current = None;
         }
-        resource.current = None;
     }
 }
 
@@ -64,59 +85,83 @@ impl PlayerSystem {
     pub fn process(dataflow: &mut dataflow::Dataflow, delta_secs: f32) -> Result<(), PlayerError> {
         let resource = dataflow.find_resources::<PlayerResource>()?;
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
-
-        let entity_id = resource.current.ok_or(PlayerError::NotFound)?;
-        let mut entity = dataflow.get_entity(entity_id).unwrap().clone();
-
-        if let Some(input) = resource.input.take() {
-            let is_move = input.length_squared() > f32::EPSILON;
-
-            if is_move {
-                let new_coord = entity.coord + resource.move_speed * input * delta_secs;
-
-                entity.coord = new_coord;
-
-                if input.x < 0.0 {
-                    resource.reverse = true;
-                } else if input.x > 0.0 {
-                    resource.reverse = false;
+        let move_speed = resource.move_speed;
+
+        for player in resource.players.values_mut() {
+            let Some(entity_id) = player.current else {
+                continue;
+            };
+            // an entity removed while handlers were silenced, as by undo, leaves the player without one
+            let Ok(entity) = dataflow.get_entity(entity_id) else {
+                player.current = None;
+                continue;
+            };
+            let mut entity = entity.clone();
+
+            if let Some(input) = player.input.take() {
+                let is_move = input.length_squared() > f32::EPSILON;
+
+                if is_move {
+                    let new_coord = entity.coord + move_speed * input * delta_secs;
+
+                    entity.coord = new_coord;
+
+                    if input.x < 0.0 {
+                        player.reverse = true;
+                    } else if input.x > 0.0 {
+                        player.reverse = false;
+                    }
                 }
-            }
 
-            match resource.state {
-                PlayerState::Wait if is_move => {
-                    entity.variant = 0b0010;
-                    entity.tick = dataflow.get_tick() as u32;
-                    resource.state = PlayerState::Move;
+                match player.state {
+                    PlayerState::Wait if is_move => {
+                        entity.variant = 0b0010;
+                        entity.tick = dataflow.get_tick() as u32;
+                        player.state = PlayerState::Move;
+                    }
+                    PlayerState::Move if !is_move => {
+                        entity.variant = 0b0000;
+                        entity.tick = dataflow.get_tick() as u32;
+                        player.state = PlayerState::Wait;
+                    }
+                    _ => {}
                 }
-                PlayerState::Move if !is_move => {
-                    entity.variant = 0b0000;
-                    entity.tick = dataflow.get_tick() as u32;
-                    resource.state = PlayerState::Wait;
-                }
-                _ => {}
+
+                entity.variant = (entity.variant & 0b1111_1110) | if player.reverse { 0b0000_0001 } else { 0b0000_0000 };
             }
 
-            entity.variant = (entity.variant & 0b1111_1110) | if resource.reverse { 0b0000_0001 } else { 0b0000_0000 };
+            dataflow.move_entity(entity_id, entity.coord).unwrap();
+            dataflow.modify_entity_variant(entity_id, entity.variant).unwrap();
+            dataflow.modify_entity_tick(entity_id, entity.tick).unwrap();
         }
 
-        dataflow.move_entity(entity_id, entity.coord).unwrap();
-        dataflow.modify_entity_variant(entity_id, entity.variant).unwrap();
-        dataflow.modify_entity_tick(entity_id, entity.tick).unwrap();
-
         Ok(())
     }
 
-    pub fn queue_input(dataflow: &mut dataflow::Dataflow, input: Vec2) -> Result<(), PlayerError> {
+    pub fn queue_input(dataflow: &mut dataflow::Dataflow, player_id: PlayerId, input: Vec2) -> Result<(), PlayerError> {
         let resource = dataflow.find_resources::<PlayerResource>()?;
         let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
 
-        if resource.input.is_some() {
+        let player = resource.players.entry(player_id).or_insert_with(Player::new);
+        if player.input.is_some() {
             return Err(PlayerError::AlreadyExist);
         }
-        resource.input = Some(input);
+        player.input = Some(input);
         Ok(())
     }
+
+    pub fn find_entity(dataflow: &dataflow::Dataflow, player_id: PlayerId) -> Result<dataflow::EntityId, PlayerError> {
+        let resource = dataflow.find_resources::<PlayerResource>()?;
+        let resource = resource.borrow().map_err(dataflow::DataflowError::from)?;
+
+        resource.players.get(&player_id).and_then(|player| player.current).ok_or(PlayerError::NotFound)
+    }
+
+    pub fn find_coord(dataflow: &dataflow::Dataflow, player_id: PlayerId) -> Result<Vec2, PlayerError> {
+        let entity_id = Self::find_entity(dataflow, player_id)?;
+        let entity = dataflow.get_entity(entity_id)?;
+        Ok(entity.coord)
+    }
 }
 
 // spawn mod
@@ -130,17 +175,47 @@ impl dataflow::Resource for PlayerSpawnResource {}
 pub struct PlayerSpawnSystem;
 
 impl PlayerSpawnSystem {
-    pub fn spawn(dataflow: &mut dataflow::Dataflow) -> Result<(), PlayerError> {
-        let resource = dataflow.find_resources::<PlayerSpawnResource>()?;
-        let resource = resource.borrow().map_err(dataflow::DataflowError::from)?;
+    pub fn spawn(dataflow: &mut dataflow::Dataflow, player_id: PlayerId) -> Result<(), PlayerError> {
+        let archetype_id = {
+            let resource = dataflow.find_resources::<PlayerSpawnResource>()?;
+            let resource = resource.borrow().map_err(dataflow::DataflowError::from)?;
+            resource.archetype_id
+        };
+
+        // the event handler picks the player up on insertion
+        let resource = dataflow.find_resources::<PlayerResource>()?;
+        {
+            let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
+            if resource.players.get(&player_id).is_some_and(|player| player.current.is_some()) {
+                return Err(PlayerError::AlreadyExist);
+            }
+            resource.spawning = Some(player_id);
+        }
 
-        dataflow.insert_entity(dataflow::Entity {
-            archetype_id: resource.archetype_id,
+        let result = dataflow.insert_entity(dataflow::Entity {
+            archetype_id,
             ..Default::default()
-        })?;
+        });
+        resource.borrow_mut().map_err(dataflow::DataflowError::from)?.spawning = None;
+        result?;
 
         Ok(())
     }
+
+    pub fn despawn(dataflow: &mut dataflow::Dataflow, player_id: PlayerId) -> Result<(), PlayerError> {
+        let entity_id = {
+            let resource = dataflow.find_resources::<PlayerResource>()?;
+            let resource = resource.borrow().map_err(dataflow::DataflowError::from)?;
+            resource.players.get(&player_id).and_then(|player| player.current).ok_or(PlayerError::NotFound)?
+        };
+
+        dataflow.remove_entity(entity_id)?;
+
+        let resource = dataflow.find_resources::<PlayerResource>()?;
+        let mut resource = resource.borrow_mut().map_err(dataflow::DataflowError::from)?;
+        resource.players.remove(&player_id);
+        Ok(())
+    }
 }
 
 // error handling
@@ -176,3 +251,55 @@ impl From<dataflow::DataflowError> for PlayerError {
         Self::DataflowError(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_dataflow() -> dataflow::Dataflow {
+        let mut dataflow = dataflow::Dataflow::new_detached(dataflow::DetachedInfo {
+            tile_field: dataflow::TileFieldInfo { tiles: vec![], layers: vec![] },
+            block_field: dataflow::BlockFieldInfo { blocks: vec![] },
+            entity_field: dataflow::EntityFieldInfo {
+                entities: vec![dataflow::EntityInfo {
+                    display_name: "entity_player".into(),
+                    description: "entity_player_desc".into(),
+                    collision_rect: None,
+                    hint_rect: Rect2::new(Vec2::ZERO, Vec2::ONE),
+                    y_sorting: false,
+                    light_emission: 0,
+                    light_opacity: 0,
+                }],
+            },
+            fluid_field: dataflow::FluidFieldInfo { fluids: vec![] },
+            light_field: dataflow::LightFieldInfo { ambient: 0 },
+        });
+        dataflow.set_entity_event_handler(0, std::rc::Rc::new(PlayerEventHandler)).unwrap();
+        dataflow.insert_resources(PlayerResource::new()).unwrap();
+        dataflow.insert_resources(PlayerSpawnResource { archetype_id: 0 }).unwrap();
+        dataflow
+    }
+
+    #[test]
+    fn misuse_without_panic() {
+        let mut dataflow = make_dataflow();
+
+        PlayerSpawnSystem::spawn(&mut dataflow, LOCAL_PLAYER).unwrap();
+        assert_eq!(PlayerSpawnSystem::spawn(&mut dataflow, LOCAL_PLAYER), Err(PlayerError::AlreadyExist));
+
+        // a second player entity from the scripts is left alone
+        let other_id = dataflow.insert_entity(dataflow::Entity { coord: Vec2::new(4.0, 0.0), ..Default::default() }).unwrap();
+        dataflow.remove_entity(other_id).unwrap();
+        assert_eq!(PlayerSystem::find_coord(&dataflow, LOCAL_PLAYER), Ok(Vec2::ZERO));
+
+        PlayerSystem::queue_input(&mut dataflow, LOCAL_PLAYER, Vec2::X).unwrap();
+        assert_eq!(PlayerSystem::queue_input(&mut dataflow, LOCAL_PLAYER, Vec2::X), Err(PlayerError::AlreadyExist));
+
+        // the player can be removed and spawned again
+        let entity_id = PlayerSystem::find_entity(&dataflow, LOCAL_PLAYER).unwrap();
+        dataflow.remove_entity(entity_id).unwrap();
+        PlayerSystem::process(&mut dataflow, 0.1).unwrap();
+        PlayerSpawnSystem::spawn(&mut dataflow, LOCAL_PLAYER).unwrap();
+        PlayerSystem::process(&mut dataflow, 0.1).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use glam::*;
+use native_core::*;
+
+use super::{Generator, GeneratorStage, GeneratorSystem};
+
+#[derive(Debug, Clone)]
+pub enum StructurePart {
+    Tile { archetype_id: u16, offset: IVec2 },
+    Block { archetype_id: u16, offset: IVec2 },
+    Entity { archetype_id: u16, offset: Vec2 },
+}
+
+impl StructurePart {
+    fn cell_offset(&self) -> IVec2 {
+        match self {
+            StructurePart::Tile { offset, .. } => *offset,
+            StructurePart::Block { offset, .. } => *offset,
+            StructurePart::Entity { offset, .. } => offset.floor().as_ivec2(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Structure {
+    pub parts: Vec<StructurePart>,
+}
+
+// places at most one structure per region, the placement is derived from
+// (seed, region) only, so every chunk finds the parts in it without shared state,
+// blocks of a part must fit in the chunk of its cell
+
+pub struct StructureGenerator {
+    seed: u64,
+    region_size: i32,
+    probability: f32,
+    structures: Vec<Structure>,
+    reach: IVec2,
+}
+
+impl StructureGenerator {
+    pub fn new(seed: u64, region_size: u32, probability: f32, structures: Vec<Structure>) -> Self {
+        let reach = structures
+            .iter()
+            .flat_map(|structure| structure.parts.iter())
+            .map(|part| part.cell_offset().abs() + 1)
+            .fold(IVec2::ZERO, |a, b| a.max(b));
+
+        Self {
+            seed,
+            region_size: region_size as i32,
+            probability,
+            structures,
+            reach,
+        }
+    }
+
+    // returns the structure and its origin placed in the region, if any
+    fn plan_region(&self, region_coord: IVec2) -> Option<(&Structure, IVec2)> {
+        if self.structures.is_empty() {
+            return None;
+        }
+
+        let rng = &mut GeneratorSystem::derive_rng(self.seed, region_coord, 0);
+        if self.probability < rand::Rng::gen_range(rng, 0.0..1.0) {
+            return None;
+        }
+
+        let structure_id = rand::Rng::gen_range(rng, 0..self.structures.len());
+        let origin = region_coord * self.region_size + IVec2::new(
+            rand::Rng::gen_range(rng, 0..self.region_size),
+            rand::Rng::gen_range(rng, 0..self.region_size),
+        );
+
+        Some((&self.structures[structure_id], origin))
+    }
+
+    fn place_part(dataflow: &mut dataflow::Dataflow, broad_rect: IRect2, origin: IVec2, part: &StructurePart) {
+        match *part {
+            StructurePart::Tile { archetype_id, offset } => {
+                let coord = origin + offset;
+
+                // structures run after terrain, so replace the terrain tile in the same layer
+                if let Ok(archetype) = dataflow.get_tile_archetype(archetype_id) {
+                    let layer_id = archetype.layer_id;
+                    if let Some((&tile_id, _)) = dataflow.find_tile_with_point_in_layer(layer_id, coord) {
+                        let _ = dataflow.remove_til(tile_id);
+                    }
+                }
+
+                let tile = dataflow::Tile { archetype_id, coord, ..Default::default() };
+                let _ = dataflow.insert_tile(tile);
+            }
+            StructurePart::Block { archetype_id, offset } => {
+                // a block crossing the chunk border is left out, see Generator
+                let block = dataflow::Block { archetype_id, coord: origin + offset, ..Default::default() };
+                GeneratorSystem::insert_block_inside(dataflow, broad_rect, block);
+            }
+            StructurePart::Entity { archetype_id, offset } => {
+                let entity = dataflow::Entity { archetype_id, coord: origin.as_vec2() + offset, ..Default::default() };
+                let _ = dataflow.insert_entity(entity);
+            }
+        }
+    }
+}
+
+impl Generator for StructureGenerator {
+    fn generate(&mut self, dataflow: &mut dataflow::Dataflow, stage: GeneratorStage, broad_rect: IRect2, _rng: &mut rand::rngs::StdRng) {
+        if stage != GeneratorStage::Features {
+            return;
+        }
+
+        let chunk_size = IVec2::splat(GeneratorSystem::CHUNK_SIZE as i32);
+        let chunk_coord = broad_rect.min.div_euclid(chunk_size);
+
+        // visit every region whose structure may reach into this chunk,
+        // in a fixed order so overlapping structures resolve the same way
+        let region_size = IVec2::splat(self.region_size);
+        let min_region = (broad_rect.min - self.reach).div_euclid(region_size);
+        let max_region = (broad_rect.max + self.reach).div_euclid(region_size);
+        for y in min_region.y..=max_region.y {
+            for x in min_region.x..=max_region.x {
+                let Some((structure, origin)) = self.plan_region(IVec2::new(x, y)) else {
+                    continue;
+                };
+
+                for part in &structure.parts {
+                    if (origin + part.cell_offset()).div_euclid(chunk_size) != chunk_coord {
+                        continue;
+                    }
+
+                    Self::place_part(dataflow, broad_rect, origin, part);
+                }
+            }
+        }
+    }
+}
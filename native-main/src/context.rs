@@ -8,342 +8,214 @@ use crate::addon;
 #[class(init, base=Object)]
 pub struct Context {
     context: Option<core::Context>,
+    viewport: Option<Gd<godot::classes::Viewport>>,
+    script_handlers: Vec<ScriptHandler>,
+    clipboard: Option<core::Schematic>,
+    server: Option<core::net::Server>,
+    client: Option<core::net::Client>,
+    seed: u64,
+    recorder: Option<core::ReplayRecorder>,
+    record_on_open: bool,
+    replay: Option<core::ReplayDriver>,
+    base: Base<Object>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArchetypeKind {
+    Tile,
+    Block,
+    Entity,
+}
+
+// event handler registered from a script, kept so that it survives open and reload
+struct ScriptHandler {
+    kind: ArchetypeKind,
+    archetype: String,
+    on_insert: Callable,
+    on_remove: Callable,
+}
+
+impl ScriptHandler {
+    fn event_handler(&self) -> core::CallableEventHandler {
+        core::CallableEventHandler {
+            on_insert: self.on_insert.clone(),
+            on_remove: self.on_remove.clone(),
+        }
+    }
 }
 
 #[godot_api]
 impl Context {
-    #[func]
-    fn open(&mut self, viewport: Gd<godot::classes::Viewport>) {
-        let mut builder = core::ContextBuilder::new();
+    // lifecycle signals, emitted once per process for subscribed kinds only
 
-        // dirt tile
-        builder.add_tile("tile_dirt".into(), |_| core::TileInfo {
-            display_name: "Dirt".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/dirt.webp")],
-                ..Default::default()
-            }],
-            collision: false,
-            ..Default::default()
-        });
-
-        // grass tile
-        builder.add_tile("tile_grass".into(), |_| core::TileInfo {
-            display_name: "Grass".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/grass.webp")],
-                ..Default::default()
-            }],
-            collision: false,
-            ..Default::default()
-        });
-
-        // dandelion block
-        builder.add_block("block_dandelion".into(), |_| core::BlockInfo {
-            display_name: "Dandelion".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/dandelion.webp")],
-                ..Default::default()
-            }],
-            size: IVec2::new(1, 1),
-            rendering_rect: core::Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
-            ..Default::default()
-        });
-
-        // fallen leaves block
-        builder.add_block("block_fallenleaves".into(), |_| core::BlockInfo {
-            display_name: "Fallen Leaves".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/fallenleaves.webp")],
-                ..Default::default()
-            }],
-            size: IVec2::new(1, 1),
-            rendering_rect: core::Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
-            ..Default::default()
-        });
-
-        // mix grass block
-        builder.add_block("block_mixgrass".into(), |_| core::BlockInfo {
-            display_name: "Grass".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/mixgrass.webp")],
-                ..Default::default()
-            }],
-            y_sorting: true,
-            size: IVec2::new(1, 1),
-            rendering_rect: core::Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
-            ..Default::default()
-        });
-
-        // mix pebbles block
-        builder.add_block("block_mixpebbles".into(), |_| core::BlockInfo {
-            display_name: "Pebbles".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![load("res://images/mixpebbles.webp")],
-                ..Default::default()
-            }],
-            size: IVec2::new(1, 1),
-            rendering_rect: core::Rect2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)),
-            ..Default::default()
-        });
-
-        // oak tree block
-        builder.add_block("block_oaktree".into(), |_| core::BlockInfo {
-            display_name: "Oak Tree".into(),
-            sprites: vec![core::SpriteInfo {
-                images: vec![
-                    load("res://images/oaktree_0.webp"),
-                    load("res://images/oaktree_1.webp"),
-                ],
-                step_tick: 48,
-                is_loop: true,
-            }],
-            y_sorting: true,
-            size: IVec2::new(4, 2),
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.5, 0.0), Vec2::new(0.5, 2.0))),
-            rendering_rect: core::Rect2::new(Vec2::new(-2.0, 0.0), Vec2::new(2.0, 6.0)),
-            ..Default::default()
-        });
+    #[signal]
+    fn tile_inserted(id: i64);
+    #[signal]
+    fn tile_removed(id: i64);
+    #[signal]
+    fn tile_changed(id: i64);
+    #[signal]
+    fn block_inserted(id: i64);
+    #[signal]
+    fn block_removed(id: i64);
+    #[signal]
+    fn block_changed(id: i64);
+    #[signal]
+    fn entity_inserted(id: i64);
+    #[signal]
+    fn entity_removed(id: i64);
+    #[signal]
+    fn entity_changed(id: i64);
 
-        // player entity
-        builder.add_entity("entity_player".into(), |_| core::EntityInfo {
-            display_name: "Player".into(),
-            sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/player_idle0.webp"),
-                        load("res://images/player_idle1.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/player_idle0r.webp"),
-                        load("res://images/player_idle1r.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/player_idle0.webp"),
-                        load("res://images/player_walk0.webp"),
-                        load("res://images/player_idle1.webp"),
-                        load("res://images/player_walk1.webp"),
-                    ],
-                    step_tick: 6,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/player_idle0r.webp"),
-                        load("res://images/player_walk0r.webp"),
-                        load("res://images/player_idle1r.webp"),
-                        load("res://images/player_walk1r.webp"),
-                    ],
-                    step_tick: 6,
-                    is_loop: true,
-                },
-            ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-0.75, 0.0), Vec2::new(0.75, 2.25)),
-            event_handler: core::EventHandler::new(addon::PlayerEventHandler),
-            ..Default::default()
-        });
+    // errors of the manifest are reported and the context is left closed
+    #[func]
+    fn open(&mut self, viewport: Gd<godot::classes::Viewport>, seed: i64) {
+        let record = std::mem::take(&mut self.record_on_open);
+        let Some(context) = self.build_context(&viewport, seed) else {
+            self.close();
+            return;
+        };
 
-        // pig entity
-        builder.add_entity("entity_pig".into(), |_| core::EntityInfo {
-            display_name: "Pig".into(),
-            sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/pig_idle0.webp"),
-                        load("res://images/pig_idle1.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/pig_walk0.webp"),
-                        load("res://images/pig_idle0.webp"),
-                        load("res://images/pig_walk1.webp"),
-                        load("res://images/pig_idle1.webp"),
-                    ],
-                    step_tick: 12,
-                    is_loop: true,
-                },
-            ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-1.0, 0.0), Vec2::new(1.0, 2.0)),
-            event_handler: core::EventHandler::new(addon::AnimalEventHandler),
-            ..Default::default()
-        });
+        self.context = Some(context);
+        self.viewport = Some(viewport);
+        self.seed = seed as u64;
+        // the spawns that follow open are part of the recording
+        self.recorder = record.then(|| core::ReplayRecorder::new(self.seed));
+        self.replay = None;
+    }
 
-        // cow entity
-        builder.add_entity("entity_cow".into(), |_| core::EntityInfo {
-            display_name: "Cow".into(),
-            sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/cow_idle0.webp"),
-                        load("res://images/cow_idle1.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/cow_walk0.webp"),
-                        load("res://images/cow_idle0.webp"),
-                        load("res://images/cow_walk1.webp"),
-                        load("res://images/cow_idle1.webp"),
-                    ],
-                    step_tick: 12,
-                    is_loop: true,
-                },
-            ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-1.0, 0.0), Vec2::new(1.0, 2.0)),
-            event_handler: core::EventHandler::new(addon::AnimalEventHandler),
-            ..Default::default()
-        });
+    fn build_context(&self, viewport: &Gd<godot::classes::Viewport>, seed: i64) -> Option<core::Context> {
+        let mut builder = core::ContextBuilder::new();
 
-        // sheep entity
-        builder.add_entity("entity_sheep".into(), |_| core::EntityInfo {
-            display_name: "Sheep".into(),
-            sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/sheep_idle0.webp"),
-                        load("res://images/sheep_idle1.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/sheep_walk0.webp"),
-                        load("res://images/sheep_idle0.webp"),
-                        load("res://images/sheep_walk1.webp"),
-                        load("res://images/sheep_idle1.webp"),
-                    ],
-                    step_tick: 12,
-                    is_loop: true,
-                },
-            ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-1.0, 0.0), Vec2::new(1.0, 2.0)),
-            event_handler: core::EventHandler::new(addon::AnimalEventHandler),
-            ..Default::default()
-        });
+        // tile layers, tiles, blocks and entities
+        report(builder.load_manifest(MANIFEST_PATH, &content_handlers()))?;
+        self.apply_script_handlers(&mut builder);
+        let ids = report(ContentIds::resolve(builder.registry()))?;
 
-        // chicken entity
-        builder.add_entity("entity_chicken".into(), |_| core::EntityInfo {
-            display_name: "Chicken".into(),
+        // water fluid
+        report(builder.add_fluid("fluid_water".into(), |_| core::FluidInfo {
+            display_name: "Water".into(),
             sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/chicken_idle.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/chicken_walk.webp"),
-                        load("res://images/chicken_idle.webp"),
-                    ],
-                    step_tick: 12,
-                    is_loop: true,
-                },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.45, 0.7, 0.9))], ..Default::default() },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.3, 0.55, 0.85))], ..Default::default() },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.2, 0.4, 0.75))], ..Default::default() },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.1, 0.25, 0.6))], ..Default::default() },
             ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-0.5, 0.0), Vec2::new(0.5, 1.0)),
-            event_handler: core::EventHandler::new(addon::AnimalEventHandler),
+            viscosity: 0,
             ..Default::default()
-        });
+        }))?;
 
-        // bird entity
-        builder.add_entity("entity_bird".into(), |_| core::EntityInfo {
-            display_name: "Bird".into(),
+        // lava fluid
+        report(builder.add_fluid("fluid_lava".into(), |_| core::FluidInfo {
+            display_name: "Lava".into(),
             sprites: vec![
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/bird_idle.webp"),
-                    ],
-                    step_tick: 24,
-                    is_loop: true,
-                },
-                core::SpriteInfo {
-                    images: vec![
-                        load("res://images/bird_walk.webp"),
-                        load("res://images/bird_idle.webp"),
-                    ],
-                    step_tick: 12,
-                    is_loop: true,
-                },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(1.0, 0.7, 0.2))], ..Default::default() },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.95, 0.5, 0.1))], ..Default::default() },
+                core::SpriteInfo { images: vec![fill_image(Color::from_rgb(0.85, 0.3, 0.05))], ..Default::default() },
             ],
-            y_sorting: true,
-            collision_rect: Some(core::Rect2::new(Vec2::new(-0.4, 0.1), Vec2::new(0.4, 0.9))),
-            rendering_rect: core::Rect2::new(Vec2::new(-0.5, 0.0), Vec2::new(0.5, 1.0)),
-            event_handler: core::EventHandler::new(addon::AnimalEventHandler),
+            viscosity: 10,
             ..Default::default()
-        });
+        }))?;
 
         // generator resource
-        builder.add_resource(|registry| addon::GeneratorResource::new(
+        builder.add_resource(move |_| addon::GeneratorResource::new(
+            seed as u64,
             vec![
+                Box::new(addon::StructureGenerator::new(
+                    seed as u64,
+                    64,
+                    0.5,
+                    vec![
+                        // tree cluster structure
+                        addon::Structure {
+                            parts: vec![
+                                addon::StructurePart::Block { archetype_id: ids.block_oaktree, offset: IVec2::new(0, 0) },
+                                addon::StructurePart::Block { archetype_id: ids.block_oaktree, offset: IVec2::new(4, 1) },
+                                addon::StructurePart::Block { archetype_id: ids.block_oaktree, offset: IVec2::new(-3, 2) },
+                                addon::StructurePart::Block { archetype_id: ids.block_oaktree, offset: IVec2::new(1, 3) },
+                                addon::StructurePart::Block { archetype_id: ids.block_fallenleaves, offset: IVec2::new(2, 2) },
+                                addon::StructurePart::Block { archetype_id: ids.block_fallenleaves, offset: IVec2::new(-1, 1) },
+                                addon::StructurePart::Block { archetype_id: ids.block_fallenleaves, offset: IVec2::new(3, 0) },
+                                addon::StructurePart::Entity { archetype_id: ids.entity_bird, offset: Vec2::new(1.5, 1.5) },
+                            ],
+                        },
+                        // ruin structure
+                        addon::Structure {
+                            parts: {
+                                let archetype_id = ids.block_mixpebbles;
+                                let mut parts = vec![];
+                                for y in 1..5 {
+                                    for x in 1..5 {
+                                        let archetype_id = ids.tile_dirt;
+                                        parts.push(addon::StructurePart::Tile { archetype_id, offset: IVec2::new(x, y) });
+                                    }
+                                }
+                                for i in 0..6 {
+                                    parts.push(addon::StructurePart::Block { archetype_id, offset: IVec2::new(i, 0) });
+                                    parts.push(addon::StructurePart::Block { archetype_id, offset: IVec2::new(i, 5) });
+                                }
+                                for i in 1..5 {
+                                    parts.push(addon::StructurePart::Block { archetype_id, offset: IVec2::new(0, i) });
+                                    parts.push(addon::StructurePart::Block { archetype_id, offset: IVec2::new(5, i) });
+                                }
+                                parts
+                            },
+                        },
+                    ],
+                )),
+                Box::new(addon::BiomeGenerator::new(
+                    seed as u64,
+                    addon::FractalNoiseInfo { frequency: 1.0 / 256.0, ..Default::default() },
+                    vec![
+                        // meadow biome
+                        addon::Biome {
+                            temperature: 0.0,
+                            humidity: 0.0,
+                            tiles: vec![
+                                addon::BiomeSpawn { archetype_id: ids.tile_grass, probability: 0.75 },
+                                addon::BiomeSpawn { archetype_id: ids.tile_dirt, probability: 1.0 },
+                            ],
+                            blocks: vec![
+                                addon::BiomeSpawn { archetype_id: ids.block_oaktree, probability: 0.01 },
+                                addon::BiomeSpawn { archetype_id: ids.block_dandelion, probability: 0.1 },
+                                addon::BiomeSpawn { archetype_id: ids.block_mixgrass, probability: 0.1 },
+                            ],
+                            entities: vec![],
+                        },
+                        // forest biome
+                        addon::Biome {
+                            temperature: -0.25,
+                            humidity: 0.25,
+                            tiles: vec![
+                                addon::BiomeSpawn { archetype_id: ids.tile_grass, probability: 0.95 },
+                                addon::BiomeSpawn { archetype_id: ids.tile_dirt, probability: 1.0 },
+                            ],
+                            blocks: vec![
+                                addon::BiomeSpawn { archetype_id: ids.block_oaktree, probability: 0.05 },
+                                addon::BiomeSpawn { archetype_id: ids.block_mixgrass, probability: 0.2 },
+                            ],
+                            entities: vec![
+                                addon::BiomeSpawn { archetype_id: ids.entity_bird, probability: 0.01 },
+                            ],
+                        },
+                        // barren biome
+                        addon::Biome {
+                            temperature: 0.25,
+                            humidity: -0.25,
+                            tiles: vec![
+                                addon::BiomeSpawn { archetype_id: ids.tile_grass, probability: 0.1 },
+                                addon::BiomeSpawn { archetype_id: ids.tile_dirt, probability: 1.0 },
+                            ],
+                            blocks: vec![],
+                            entities: vec![],
+                        },
+                    ],
+                )),
                 Box::new(addon::DiscreteGenerator {
-                    probability: 0.75,
-                    sample_fn: {
-                        let archetype_id = registry.get("tile_grass");
-                        move |dataflow, coord| {
-                            let tile = core::dataflow::Tile { archetype_id, coord, ..Default::default() };
-                            let _ = dataflow.insert_tile(tile);
-                        }
-                    }
-                }),
-                Box::new(addon::DiscreteGenerator {
-                    probability: 1.0,
-                    sample_fn: {
-                        let archetype_id = registry.get("tile_dirt");
-                        move |dataflow, coord| {
-                            let tile = core::dataflow::Tile { archetype_id, coord, ..Default::default() };
-                            let _ = dataflow.insert_tile(tile);
-                        }
-                    }
-                }),
-                Box::new(addon::DiscreteGenerator {
-                    probability: 0.01,
-                    sample_fn: {
-                        let archetype_id = registry.get("block_oaktree");
-                        move |dataflow, coord| {
-                            let block = core::dataflow::Block { archetype_id, coord, ..Default::default() };
-                            let _ = dataflow.insert_block(block);
-                        }
-                    }
-                }),
-                Box::new(addon::DiscreteGenerator {
-                    probability: 0.1,
-                    sample_fn: {
-                        let archetype_id = registry.get("block_dandelion");
-                        move |dataflow, coord| {
-                            let block = core::dataflow::Block { archetype_id, coord, ..Default::default() };
-                            let _ = dataflow.insert_block(block);
-                        }
-                    }
-                }),
-                Box::new(addon::DiscreteGenerator {
-                    probability: 0.1,
+                    stage: addon::GeneratorStage::Decoration,
+                    probability: 0.005,
                     sample_fn: {
-                        let archetype_id = registry.get("block_mixgrass");
+                        let archetype_id = ids.block_dandelion;
                         move |dataflow, coord| {
                             let block = core::dataflow::Block { archetype_id, coord, ..Default::default() };
                             let _ = dataflow.insert_block(block);
@@ -351,9 +223,10 @@ impl Context {
                     }
                 }),
                 Box::new(addon::RandomGenerator {
-                    probability: 0.01,
+                    stage: addon::GeneratorStage::Entities,
+                    probability: 0.005,
                     sample_fn: {
-                        let archetype_id = registry.get("entity_bird");
+                        let archetype_id = ids.entity_bird;
                         move |dataflow, coord| {
                             let entity = core::dataflow::Entity { archetype_id, coord, ..Default::default() };
                             let _ = dataflow.insert_entity(entity);
@@ -367,13 +240,13 @@ impl Context {
         builder.add_resource(|_| addon::PlayerResource::new());
 
         // animal resource
-        builder.add_resource(|_| addon::AnimalResource::new());
+        builder.add_resource(move |_| addon::AnimalResource::new(seed as u64));
 
         // player spawn resource
-        builder.add_resource(|registry| addon::PlayerSpawnResource { archetype_id: registry.get("entity_player") });
+        builder.add_resource(move |_| addon::PlayerSpawnResource { archetype_id: ids.entity_player });
 
         // animal bulk spawn resource
-        builder.add_resource(|registry| addon::AnimalBulkSpawnResource { archetype_id: registry.get("entity_bird") });
+        builder.add_resource(move |_| addon::AnimalBulkSpawnResource { archetype_id: ids.entity_bird });
 
         // build
         let desc = core::BuildInfo {
@@ -388,74 +261,1048 @@ impl Context {
                 load("res://shaders/field.gdshader"),
                 load("res://shaders/field_shadow.gdshader"),
             ],
-            viewport,
+            fluid_shaders: vec![
+                load("res://shaders/field.gdshader"),
+            ],
+            viewport: viewport.clone(),
         };
-        self.context = Some(builder.build(desc));
+        Some(builder.build(desc))
     }
 
     #[func]
     fn close(&mut self) {
+        self.server = None;
+        self.client = None;
+        self.recorder = None;
+        self.replay = None;
         self.context = None;
+        self.viewport = None;
     }
 
+    // re-read the manifest and swap definitions and sprites in place,
+    // errors are reported and the current content is kept
     #[func]
-    fn spawn_player(&mut self) {
-        let context = self.context.as_mut().unwrap();
+    fn reload(&mut self) {
+        let mut builder = core::ContextBuilder::new();
+        if let Err(e) = builder.load_manifest(MANIFEST_PATH, &content_handlers()) {
+            godot_error!("{}", e);
+            return;
+        }
+        self.apply_script_handlers(&mut builder);
+
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+        if let Err(e) = context.reload(builder) {
+            godot_error!("{}", e);
+            return;
+        }
+        addon::GeneratorSystem::reload(&mut context.dataflow).unwrap();
+    }
 
-        addon::PlayerSpawnSystem::spawn(&mut context.dataflow).unwrap();
+    // a joined instance gets its player from the host
+    #[func]
+    fn spawn_player(&mut self) {
+        if self.client.is_some() || self.replay.is_some() {
+            return;
+        }
+        self.run_command(core::ReplayCommand::SpawnPlayer);
     }
 
     #[func]
     fn spawn_bulk_animal(&mut self) {
-        let context = self.context.as_mut().unwrap();
+        if self.client.is_some() || self.replay.is_some() {
+            return;
+        }
+        self.run_command(core::ReplayCommand::SpawnBulkAnimal);
+    }
+
+    // entity id of the player of this instance, -1 until it is spawned
+    #[func]
+    fn get_player(&self) -> i64 {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return -1;
+        };
+
+        let player = match self.client.as_ref() {
+            Some(client) => client.player(),
+            None => addon::PlayerSystem::find_entity(&context.dataflow, addon::LOCAL_PLAYER).ok(),
+        };
+        player.map(|id| id as i64).unwrap_or(-1)
+    }
+
+    // light, levels run from 0 to 15 and no cell is drawn darker than the ambient
+
+    #[func]
+    fn get_light_ambient(&self) -> i64 {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return 0;
+        };
+
+        context.dataflow.get_light_ambient() as i64
+    }
+
+    #[func]
+    fn set_light_ambient(&mut self, ambient: i64) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        let ambient = ambient.clamp(0, core::dataflow::LightField::MAX_LEVEL as i64) as u8;
+        context.dataflow.set_light_ambient(ambient);
+    }
+
+    // script event handlers, may be set before or after open
+
+    #[func]
+    fn set_tile_event_handler(&mut self, archetype: GString, on_insert: Callable, on_remove: Callable) {
+        self.set_script_handler(ScriptHandler { kind: ArchetypeKind::Tile, archetype: archetype.to_string(), on_insert, on_remove });
+    }
+
+    #[func]
+    fn set_block_event_handler(&mut self, archetype: GString, on_insert: Callable, on_remove: Callable) {
+        self.set_script_handler(ScriptHandler { kind: ArchetypeKind::Block, archetype: archetype.to_string(), on_insert, on_remove });
+    }
+
+    #[func]
+    fn set_entity_event_handler(&mut self, archetype: GString, on_insert: Callable, on_remove: Callable) {
+        self.set_script_handler(ScriptHandler { kind: ArchetypeKind::Entity, archetype: archetype.to_string(), on_insert, on_remove });
+    }
+
+    // the archetype gets back the handler it was declared with
+    #[func]
+    fn clear_tile_event_handler(&mut self, archetype: GString) {
+        self.clear_script_handler(ArchetypeKind::Tile, &archetype.to_string());
+    }
+
+    #[func]
+    fn clear_block_event_handler(&mut self, archetype: GString) {
+        self.clear_script_handler(ArchetypeKind::Block, &archetype.to_string());
+    }
+
+    #[func]
+    fn clear_entity_event_handler(&mut self, archetype: GString) {
+        self.clear_script_handler(ArchetypeKind::Entity, &archetype.to_string());
+    }
+
+    fn set_script_handler(&mut self, script_handler: ScriptHandler) {
+        if let Some(context) = self.context.as_mut() {
+            let handler = std::rc::Rc::new(script_handler.event_handler());
+            let result = match script_handler.kind {
+                ArchetypeKind::Tile => report(context.registry.tiles().get(&script_handler.archetype))
+                    .map(|id| report(context.dataflow.set_tile_event_handler(id, handler))),
+                ArchetypeKind::Block => report(context.registry.blocks().get(&script_handler.archetype))
+                    .map(|id| report(context.dataflow.set_block_event_handler(id, handler))),
+                ArchetypeKind::Entity => report(context.registry.entities().get(&script_handler.archetype))
+                    .map(|id| report(context.dataflow.set_entity_event_handler(id, handler))),
+            };
+            if result.flatten().is_none() {
+                return;
+            }
+        }
+
+        // a later handler for the same archetype replaces the earlier one
+        self.script_handlers.retain(|other| other.kind != script_handler.kind || other.archetype != script_handler.archetype);
+        self.script_handlers.push(script_handler);
+    }
+
+    fn clear_script_handler(&mut self, kind: ArchetypeKind, archetype: &str) {
+        self.script_handlers.retain(|other| other.kind != kind || other.archetype != archetype);
+
+        if let Some(context) = self.context.as_mut() {
+            match kind {
+                ArchetypeKind::Tile => report(context.registry.tiles().get(archetype))
+                    .map(|id| report(context.restore_tile_event_handler(id))),
+                ArchetypeKind::Block => report(context.registry.blocks().get(archetype))
+                    .map(|id| report(context.restore_block_event_handler(id))),
+                ArchetypeKind::Entity => report(context.registry.entities().get(archetype))
+                    .map(|id| report(context.restore_entity_event_handler(id))),
+            };
+        }
+    }
+
+    fn apply_script_handlers(&self, builder: &mut core::ContextBuilder) {
+        for script_handler in &self.script_handlers {
+            let handler = core::EventHandler::new(script_handler.event_handler());
+            let result = match script_handler.kind {
+                ArchetypeKind::Tile => builder.set_tile_event_handler(&script_handler.archetype, handler),
+                ArchetypeKind::Block => builder.set_block_event_handler(&script_handler.archetype, handler),
+                ArchetypeKind::Entity => builder.set_entity_event_handler(&script_handler.archetype, handler),
+            };
+            report(result);
+        }
+    }
+
+    // tile
+
+    #[func]
+    fn insert_tile(&mut self, archetype: GString, coord: Vector2i) -> i64 {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return -1;
+        };
+
+        let Some(archetype_id) = report(context.registry.tiles().get(&archetype.to_string())) else {
+            return -1;
+        };
+        let tile = core::dataflow::Tile {
+            coord: IVec2::new(coord.x, coord.y),
+            archetype_id,
+            ..Default::default()
+        };
+        report(context.dataflow.insert_tile(tile)).map(|id| id as i64).unwrap_or(-1)
+    }
+
+    #[func]
+    fn remove_tile(&mut self, id: i64) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.remove_til(id as u64)).is_some()
+    }
+
+    #[func]
+    fn move_tile(&mut self, id: i64, coord: Vector2i) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.move_tile(id as u64, IVec2::new(coord.x, coord.y))).is_some()
+    }
+
+    #[func]
+    fn get_tile(&self, id: i64) -> VarDictionary {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return VarDictionary::new();
+        };
+
+        let Some(tile) = report(context.dataflow.get_tile(id as u64)) else {
+            return VarDictionary::new();
+        };
+        godot::builtin::vdict! {
+            "archetype": context.registry.tiles().get_name(tile.archetype_id).unwrap_or_default(),
+            "coord": Vector2i::new(tile.coord.x, tile.coord.y),
+            "variant": tile.variant,
+            "tick": tile.tick,
+        }
+    }
+
+    #[func]
+    fn find_tile_with_point(&self, point: Vector2i) -> i64 {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return -1;
+        };
+
+        let point = IVec2::new(point.x, point.y);
+        context.dataflow.find_tile_with_point(point).map(|(id, _)| *id as i64).unwrap_or(-1)
+    }
+
+    #[func]
+    fn find_tiles_with_rect(&self, rect: Rect2i) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_tile_with_rect(to_irect2(rect)))
+    }
+
+    #[func]
+    fn find_tiles_with_collision_point(&self, point: Vector2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        let point = Vec2::new(point.x, point.y);
+        collect_ids(context.dataflow.find_tile_with_collision_point(point))
+    }
+
+    #[func]
+    fn find_tiles_with_collision_rect(&self, rect: Rect2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_tile_with_collision_rect(to_rect2(rect)))
+    }
+
+    // block
+
+    #[func]
+    fn insert_block(&mut self, archetype: GString, coord: Vector2i) -> i64 {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return -1;
+        };
+
+        let Some(archetype_id) = report(context.registry.blocks().get(&archetype.to_string())) else {
+            return -1;
+        };
+        let block = core::dataflow::Block {
+            coord: IVec2::new(coord.x, coord.y),
+            archetype_id,
+            ..Default::default()
+        };
+        report(context.dataflow.insert_block(block)).map(|id| id as i64).unwrap_or(-1)
+    }
+
+    #[func]
+    fn remove_block(&mut self, id: i64) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.remove_block(id as u64)).is_some()
+    }
+
+    #[func]
+    fn move_block(&mut self, id: i64, coord: Vector2i) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.move_block(id as u64, IVec2::new(coord.x, coord.y))).is_some()
+    }
+
+    #[func]
+    fn get_block(&self, id: i64) -> VarDictionary {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return VarDictionary::new();
+        };
+
+        let Some(block) = report(context.dataflow.get_block(id as u64)) else {
+            return VarDictionary::new();
+        };
+        godot::builtin::vdict! {
+            "archetype": context.registry.blocks().get_name(block.archetype_id).unwrap_or_default(),
+            "coord": Vector2i::new(block.coord.x, block.coord.y),
+            "variant": block.variant,
+            "tick": block.tick,
+        }
+    }
+
+    #[func]
+    fn find_block_with_point(&self, point: Vector2i) -> i64 {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return -1;
+        };
+
+        let point = IVec2::new(point.x, point.y);
+        context.dataflow.find_block_with_point(point).map(|(id, _)| *id as i64).unwrap_or(-1)
+    }
+
+    #[func]
+    fn find_blocks_with_rect(&self, rect: Rect2i) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_block_with_rect(to_irect2(rect)))
+    }
+
+    #[func]
+    fn find_blocks_with_collision_point(&self, point: Vector2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        let point = Vec2::new(point.x, point.y);
+        collect_ids(context.dataflow.find_block_collision_point(point))
+    }
+
+    #[func]
+    fn find_blocks_with_collision_rect(&self, rect: Rect2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
 
-        addon::AnimalBulkSpawnSystem::spawn(&mut context.dataflow).unwrap();
+        collect_ids(context.dataflow.find_block_with_collision_rect(to_rect2(rect)))
+    }
+
+    #[func]
+    fn find_blocks_with_hint_point(&self, point: Vector2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        let point = Vec2::new(point.x, point.y);
+        collect_ids(context.dataflow.find_block_with_hint_point(point))
+    }
+
+    #[func]
+    fn find_blocks_with_hint_rect(&self, rect: Rect2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_block_with_hint_rect(to_rect2(rect)))
+    }
+
+    // entity
+
+    #[func]
+    fn insert_entity(&mut self, archetype: GString, coord: Vector2) -> i64 {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return -1;
+        };
+
+        let Some(archetype_id) = report(context.registry.entities().get(&archetype.to_string())) else {
+            return -1;
+        };
+        let entity = core::dataflow::Entity {
+            coord: Vec2::new(coord.x, coord.y),
+            archetype_id,
+            ..Default::default()
+        };
+        report(context.dataflow.insert_entity(entity)).map(|id| id as i64).unwrap_or(-1)
+    }
+
+    #[func]
+    fn remove_entity(&mut self, id: i64) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.remove_entity(id as u64)).is_some()
+    }
+
+    #[func]
+    fn move_entity(&mut self, id: i64, coord: Vector2) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        report(context.dataflow.move_entity(id as u64, Vec2::new(coord.x, coord.y))).is_some()
+    }
+
+    #[func]
+    fn get_entity(&self, id: i64) -> VarDictionary {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return VarDictionary::new();
+        };
+
+        let Some(entity) = report(context.dataflow.get_entity(id as u64)) else {
+            return VarDictionary::new();
+        };
+        godot::builtin::vdict! {
+            "archetype": context.registry.entities().get_name(entity.archetype_id).unwrap_or_default(),
+            "coord": Vector2::new(entity.coord.x, entity.coord.y),
+            "variant": entity.variant,
+            "tick": entity.tick,
+        }
+    }
+
+    #[func]
+    fn find_entities_with_collision_point(&self, point: Vector2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        let point = Vec2::new(point.x, point.y);
+        collect_ids(context.dataflow.find_entity_with_collision_point(point))
+    }
+
+    #[func]
+    fn find_entities_with_collision_rect(&self, rect: Rect2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_entity_with_collision_rect(to_rect2(rect)))
+    }
+
+    #[func]
+    fn find_entities_with_hint_point(&self, point: Vector2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        let point = Vec2::new(point.x, point.y);
+        collect_ids(context.dataflow.find_entity_with_hint_point(point))
+    }
+
+    #[func]
+    fn find_entities_with_hint_rect(&self, rect: Rect2) -> PackedInt64Array {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return PackedInt64Array::new();
+        };
+
+        collect_ids(context.dataflow.find_entity_with_hint_rect(to_rect2(rect)))
+    }
+
+    // picking
+
+    // unproject through the active camera onto the field plane and list what is under it, front to back
+    #[func]
+    fn pick(&self, viewport_position: Vector2) -> VarArray {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return VarArray::new();
+        };
+
+        let mut hits = VarArray::new();
+
+        let Some(camera) = self.viewport.as_ref().and_then(|viewport| viewport.get_camera_3d()) else {
+            godot_error!("no active camera");
+            return hits;
+        };
+        let origin = camera.project_ray_origin(viewport_position);
+        let normal = camera.project_ray_normal(viewport_position);
+        if normal.z.abs() < f32::EPSILON {
+            return hits;
+        }
+        let point = origin - normal * (origin.z / normal.z);
+
+        for hit in context.pick(Vec2::new(point.x, point.y)) {
+            let kind = match hit.kind {
+                core::PickKind::Tile => "tile",
+                core::PickKind::Block => "block",
+                core::PickKind::Entity => "entity",
+            };
+            hits.push(&godot::builtin::vdict! {
+                "kind": kind,
+                "id": hit.id as i64,
+                "archetype": hit.name,
+                "display_name": hit.display_name,
+            }.to_variant());
+        }
+        hits
+    }
+
+    // clipboard, kept by archetype name so that it survives reload and can be saved as a schematic
+
+    #[func]
+    fn copy_region(&mut self, rect: Rect2i) -> bool {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return false;
+        };
+
+        self.clipboard = report(context.copy_schematic(to_irect2(rect)));
+        self.clipboard.is_some()
+    }
+
+    // policy is one of "skip", "overwrite" or "abort"
+    #[func]
+    fn paste_clipboard(&mut self, origin: Vector2i, policy: GString) -> VarDictionary {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return VarDictionary::new();
+        };
+
+        let Some(clipboard) = &self.clipboard else {
+            godot_error!("clipboard is empty");
+            return VarDictionary::new();
+        };
+        let policy = match policy.to_string().as_str() {
+            "skip" => core::dataflow::ConflictPolicy::Skip,
+            "overwrite" => core::dataflow::ConflictPolicy::Overwrite,
+            "abort" => core::dataflow::ConflictPolicy::Abort,
+            _ => {
+                godot_error!("unknown conflict policy: {}", policy);
+                return VarDictionary::new();
+            }
+        };
+
+        let Some(summary) = report(context.paste_schematic(clipboard, IVec2::new(origin.x, origin.y), policy)) else {
+            return VarDictionary::new();
+        };
+        godot::builtin::vdict! {
+            "inserted": summary.inserted as i64,
+            "removed": summary.removed as i64,
+            "skipped": summary.skipped as i64,
+            "aborted": summary.aborted,
+        }
+    }
+
+    #[func]
+    fn save_clipboard(&self, path: GString) -> bool {
+        let Some(clipboard) = &self.clipboard else {
+            godot_error!("clipboard is empty");
+            return false;
+        };
+
+        report(clipboard.save(&path.to_string())).is_some()
+    }
+
+    #[func]
+    fn load_clipboard(&mut self, path: GString) -> bool {
+        let Some(clipboard) = report(core::Schematic::load(&path.to_string())) else {
+            return false;
+        };
+
+        self.clipboard = Some(clipboard);
+        true
+    }
+
+    // the loaded chunks as a schematic that keeps its origin and tick, for inspection outside of godot,
+    // chunks stored by the generator and the state of the systems are not included
+    #[func]
+    fn save_loaded_region(&self, path: GString) -> bool {
+        let Some(context) = opened(self.context.as_ref()) else {
+            return false;
+        };
+
+        let region = context.dataflow.find_occupied_region().unwrap_or(core::IRect2::new(IVec2::ZERO, IVec2::NEG_ONE));
+        let Some(schematic) = report(context.copy_schematic(region)) else {
+            return false;
+        };
+        report(schematic.save(&path.to_string())).is_some()
+    }
+
+    // edit history, changes made by systems during process are not recorded
+
+    #[func]
+    fn enable_journal(&mut self) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        context.dataflow.enable_journal();
+    }
+
+    #[func]
+    fn disable_journal(&mut self) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        context.dataflow.disable_journal();
+    }
+
+    #[func]
+    fn begin_transaction(&mut self, name: GString) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        context.dataflow.begin_transaction(&name.to_string());
+    }
+
+    #[func]
+    fn commit_transaction(&mut self) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        context.dataflow.commit_transaction();
+    }
+
+    // returns the name of the reverted transaction, or an empty string if there was none
+    #[func]
+    fn undo(&mut self) -> GString {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return GString::new();
+        };
+
+        GString::from(&report(context.dataflow.undo()).flatten().unwrap_or_default())
+    }
+
+    // returns the name of the re-applied transaction, or an empty string if there was none
+    #[func]
+    fn redo(&mut self) -> GString {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return GString::new();
+        };
+
+        GString::from(&report(context.dataflow.redo()).flatten().unwrap_or_default())
+    }
+
+    // network, the host runs the simulation and a joined instance only mirrors its chunks
+
+    #[func]
+    fn host(&mut self, port: i64) -> bool {
+        let Some(listener) = report(core::net::TcpListener::bind(&format!("127.0.0.1:{}", port))) else {
+            return false;
+        };
+
+        self.client = None;
+        self.server = Some(core::net::Server::new(listener));
+        true
+    }
+
+    // the replica must not run handlers of its own, its content is replaced by the host
+    #[func]
+    fn join(&mut self, address: GString) -> bool {
+        let Some(connection) = report(core::net::TcpConnection::connect(&address.to_string())) else {
+            return false;
+        };
+
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+        for (id, _) in context.registry.tiles().iter() {
+            context.dataflow.set_tile_event_handler(id, std::rc::Rc::new(())).unwrap();
+        }
+        for (id, _) in context.registry.blocks().iter() {
+            context.dataflow.set_block_event_handler(id, std::rc::Rc::new(())).unwrap();
+        }
+        for (id, _) in context.registry.entities().iter() {
+            context.dataflow.set_entity_event_handler(id, std::rc::Rc::new(())).unwrap();
+        }
+
+        self.server = None;
+        self.client = Some(core::net::Client::new(connection));
+        true
     }
 
     // update system
 
     #[func]
     fn process(&mut self, delta_secs: f64) {
-        let context = self.context.as_mut().unwrap();
+        if self.replay.is_some() {
+            return;
+        }
+        self.process_frame(delta_secs as f32);
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            let Some(context) = opened(self.context.as_ref()) else {
+                return;
+            };
+            recorder.record_process(&context.dataflow, delta_secs as f32);
+        }
+    }
+
+    // returns false if the context is closed or a system failed
+    fn process_frame(&mut self, delta_secs: f32) -> bool {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return false;
+        };
+
+        if let Some(client) = self.client.as_mut() {
+            // the replica has no simulation of its own to fall back to
+            if let Err(e) = client.poll(&mut context.dataflow) {
+                godot_error!("disconnected from the host: {}", e);
+                self.close();
+                return false;
+            }
+            self.flush_events();
+            return true;
+        }
+
+        // spawns and simulation are not user edits, so they stay out of the journal
+        context.dataflow.suspend_journal();
+
+        if let Some(server) = self.server.as_mut() {
+            for event in server.poll() {
+                match event {
+                    core::net::ServerEvent::Connected(id) => {
+                        report(addon::PlayerSpawnSystem::spawn(&mut context.dataflow, id));
+                    }
+                    // a client may send faster than the host processes, extra inputs of a frame are dropped
+                    core::net::ServerEvent::Input(id, input) => {
+                        addon::PlayerSystem::queue_input(&mut context.dataflow, id, input).ok();
+                    }
+                    core::net::ServerEvent::Disconnected(id) => {
+                        report(addon::PlayerSpawnSystem::despawn(&mut context.dataflow, id));
+                    }
+                }
+            }
+        }
 
-        let delta_secs = delta_secs as f32;
         context.dataflow.process(delta_secs);
 
         // player system
-        addon::PlayerSystem::process(&mut context.dataflow, delta_secs).unwrap();
+        let player = report(addon::PlayerSystem::process(&mut context.dataflow, delta_secs)).is_some();
         // animal sysyem
-        addon::AnimalSystem::process(&mut context.dataflow, delta_secs).unwrap();
+        let animal = report(addon::AnimalSystem::process(&mut context.dataflow, delta_secs)).is_some();
+        context.dataflow.resume_journal();
+
+        if let Some(server) = self.server.as_mut() {
+            server.replicate(&context.dataflow, |client_id| addon::PlayerSystem::find_entity(&context.dataflow, client_id).ok());
+        }
+
+        self.flush_events();
+        player && animal
+    }
+
+    #[func]
+    fn subscribe(&mut self, signal: GString) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        match core::dataflow::EventKind::from_name(&signal.to_string()) {
+            Some(kind) => context.dataflow.subscribe_event(kind),
+            None => godot_error!("unknown signal: {}", signal),
+        }
+    }
+
+    #[func]
+    fn unsubscribe(&mut self, signal: GString) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        match core::dataflow::EventKind::from_name(&signal.to_string()) {
+            Some(kind) => context.dataflow.unsubscribe_event(kind),
+            None => godot_error!("unknown signal: {}", signal),
+        }
+    }
+
+    // the context is not borrowed while emitting, so connected scripts may call back into it
+    fn flush_events(&mut self) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        let (events, dropped) = context.dataflow.drain_events();
+        if dropped > 0 {
+            godot_warn!("{} events dropped, the event queue is full", dropped);
+        }
+
+        for event in events {
+            let id = event.id as i64;
+            match event.kind {
+                core::dataflow::EventKind::TileInserted => self.signals().tile_inserted().emit(id),
+                core::dataflow::EventKind::TileRemoved => self.signals().tile_removed().emit(id),
+                core::dataflow::EventKind::TileChanged => self.signals().tile_changed().emit(id),
+                core::dataflow::EventKind::BlockInserted => self.signals().block_inserted().emit(id),
+                core::dataflow::EventKind::BlockRemoved => self.signals().block_removed().emit(id),
+                core::dataflow::EventKind::BlockChanged => self.signals().block_changed().emit(id),
+                core::dataflow::EventKind::EntityInserted => self.signals().entity_inserted().emit(id),
+                core::dataflow::EventKind::EntityRemoved => self.signals().entity_removed().emit(id),
+                core::dataflow::EventKind::EntityChanged => self.signals().entity_changed().emit(id),
+            }
+        }
     }
 
     #[func]
     fn generate_field(&mut self, rect: Rect2) {
-        let context = self.context.as_mut().unwrap();
+        // the field of a joined instance comes from the host
+        if self.client.is_some() || self.replay.is_some() {
+            return;
+        }
 
-        let position = Vec2::new(rect.position.x, rect.position.y);
-        let size = Vec2::new(rect.size.x, rect.size.y);
-        let rect = core::Rect2::new(position, position + size);
-        addon::GeneratorSystem::generate(&mut context.dataflow, rect).unwrap();
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        let rect = to_rect2(rect);
+        let focus = addon::PlayerSystem::find_coord(&context.dataflow, addon::LOCAL_PLAYER).unwrap_or(rect.center());
+        let chunks = context
+            .dataflow
+            .without_journal(|dataflow| addon::GeneratorSystem::generate(dataflow, rect, focus, std::time::Duration::from_millis(4)))
+            .unwrap();
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(core::ReplayCommand::GenerateField {
+                rect: [rect.min.x, rect.min.y, rect.max.x, rect.max.y],
+                focus: focus.to_array(),
+                chunks: chunks.iter().map(|chunk_coord| chunk_coord.to_array()).collect(),
+            });
+        }
     }
 
     #[func]
     fn queue_input(&mut self, input: Vector2) {
-        let context = self.context.as_mut().unwrap();
+        if self.replay.is_some() {
+            return;
+        }
 
         let input = Vec2::new(input.x, input.y);
-        addon::PlayerSystem::queue_input(&mut context.dataflow, input).unwrap();
+        if let Some(client) = self.client.as_mut() {
+            report(client.send_input(input));
+            return;
+        }
+        self.run_command(core::ReplayCommand::QueueInput { input: input.to_array() });
+    }
+
+    // replay, records what the scripts feed into the world from a fresh open,
+    // edits through the scripting api are not recorded
+
+    // call before open, the recording starts with the next open
+    #[func]
+    fn start_recording(&mut self) {
+        self.record_on_open = true;
+    }
+
+    #[func]
+    fn stop_recording(&mut self, path: GString) -> bool {
+        let Some(recorder) = self.recorder.take() else {
+            godot_error!("not recording");
+            return false;
+        };
+
+        report(recorder.finish().save(&path.to_string())).is_some()
+    }
+
+    // opens a world with the seed of the replay, then step_replay takes the place of process
+    #[func]
+    fn play_replay(&mut self, viewport: Gd<godot::classes::Viewport>, path: GString) -> bool {
+        let Some(replay) = report(core::Replay::load(&path.to_string())) else {
+            return false;
+        };
+
+        self.record_on_open = false;
+        self.open(viewport, replay.seed as i64);
+        if self.context.is_none() {
+            return false;
+        }
+        self.replay = Some(core::ReplayDriver::new(replay));
+        true
+    }
+
+    // feeds one recorded frame and checks its state hash,
+    // returns false once the replay is finished or has diverged
+    #[func]
+    fn step_replay(&mut self) -> bool {
+        let Some(frame) = self.replay.as_ref().and_then(|replay| replay.next_frame()).cloned() else {
+            self.replay = None;
+            return false;
+        };
+
+        for command in frame.commands {
+            self.run_command(command);
+        }
+        // a command that diverged ends the replay
+        if self.replay.is_none() {
+            return false;
+        }
+        // so does a system that failed, the recording did not
+        if !self.process_frame(frame.delta_secs) {
+            let replay = self.replay.take().unwrap();
+            godot_error!("replay desync at frame {}: a system failed", replay.frame_index());
+            return false;
+        }
+
+        let Some(context) = opened(self.context.as_ref()) else {
+            return false;
+        };
+        let replay = self.replay.as_mut().unwrap();
+        if report(replay.verify(&context.dataflow)).is_none() {
+            self.replay = None;
+            return false;
+        }
+        true
+    }
+
+    fn run_command(&mut self, command: core::ReplayCommand) {
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
+
+        match &command {
+            // a command that fails is not recorded
+            core::ReplayCommand::SpawnPlayer => {
+                if report(addon::PlayerSpawnSystem::spawn(&mut context.dataflow, addon::LOCAL_PLAYER)).is_none() {
+                    return;
+                }
+            }
+            core::ReplayCommand::SpawnBulkAnimal => {
+                if report(addon::AnimalBulkSpawnSystem::spawn(&mut context.dataflow)).is_none() {
+                    return;
+                }
+            }
+            core::ReplayCommand::QueueInput { input } => {
+                if report(addon::PlayerSystem::queue_input(&mut context.dataflow, addon::LOCAL_PLAYER, Vec2::from_array(*input))).is_none() {
+                    return;
+                }
+            }
+            // only replayed, recording goes through generate_field
+            core::ReplayCommand::GenerateField { rect, focus, chunks } => {
+                let rect = core::Rect2::new(Vec2::new(rect[0], rect[1]), Vec2::new(rect[2], rect[3]));
+                let chunks = chunks.iter().map(|chunk_coord| IVec2::from_array(*chunk_coord)).collect::<Vec<_>>();
+                let result = context
+                    .dataflow
+                    .without_journal(|dataflow| addon::GeneratorSystem::generate_exact(dataflow, rect, Vec2::from_array(*focus), &chunks));
+                if report(result).is_none() {
+                    self.replay = None;
+                }
+                return;
+            }
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(command);
+        }
     }
 
     // draw
 
     #[func]
     fn draw_field(&mut self, rect: Rect2) {
-        let context = self.context.as_mut().unwrap();
+        let Some(context) = opened(self.context.as_mut()) else {
+            return;
+        };
 
-        let position = Vec2::new(rect.position.x, rect.position.y);
-        let size = Vec2::new(rect.size.x, rect.size.y);
-        let rect = core::Rect2::new(position, position + size);
+        let rect = to_rect2(rect);
         context.tile_field_view.update_view(&context.dataflow, rect);
         context.block_field_view.update_view(&context.dataflow, rect);
         context.entity_field_view.update_view(&context.dataflow, rect);
+        context.fluid_field_view.update_view(&context.dataflow, rect);
+    }
+}
+
+// calls into a closed context are reported as godot errors instead of panics
+fn opened<T>(context: Option<T>) -> Option<T> {
+    if context.is_none() {
+        godot_error!("context is not open");
     }
+    context
+}
+
+// failures of the scripting api are reported as godot errors instead of panics
+fn report<T, E>(result: Result<T, E>) -> Option<T> where E: std::fmt::Display {
+    result.map_err(|e| godot_error!("{}", e)).ok()
+}
+
+fn collect_ids<'a, T>(iter: impl Iterator<Item = (&'a u64, T)>) -> PackedInt64Array {
+    iter.map(|(id, _)| *id as i64).collect()
+}
+
+fn to_rect2(rect: Rect2) -> core::Rect2 {
+    let position = Vec2::new(rect.position.x, rect.position.y);
+    let size = Vec2::new(rect.size.x, rect.size.y);
+    core::Rect2::new(position, position + size)
+}
+
+// godot rects exclude the end, core rects include it
+fn to_irect2(rect: Rect2i) -> core::IRect2 {
+    let position = IVec2::new(rect.position.x, rect.position.y);
+    let size = IVec2::new(rect.size.x, rect.size.y);
+    core::IRect2::new(position, position + size - 1)
+}
+
+const MANIFEST_PATH: &str = "res://manifests/content.json";
+
+// event handlers referenced by the manifest
+fn content_handlers() -> core::manifest::HandlerRegistry {
+    let mut handlers = core::manifest::HandlerRegistry::new();
+    handlers.add_entity_handler("player".into(), || core::EventHandler::new(addon::PlayerEventHandler));
+    handlers.add_entity_handler("animal".into(), || core::EventHandler::new(addon::AnimalEventHandler));
+    handlers
+}
+
+// solid color image for sprites without a texture asset
+// archetypes the generators and the spawns are built from, resolved once so that
+// a renamed or removed manifest entry is reported instead of failing inside build
+#[derive(Clone, Copy)]
+struct ContentIds {
+    tile_grass: u16,
+    tile_dirt: u16,
+    block_dandelion: u16,
+    block_fallenleaves: u16,
+    block_mixgrass: u16,
+    block_mixpebbles: u16,
+    block_oaktree: u16,
+    entity_bird: u16,
+    entity_player: u16,
+}
+
+impl ContentIds {
+    fn resolve(registry: &core::Registry) -> Result<Self, core::RegistryError> {
+        Ok(Self {
+            tile_grass: registry.tiles().get("tile_grass")?,
+            tile_dirt: registry.tiles().get("tile_dirt")?,
+            block_dandelion: registry.blocks().get("block_dandelion")?,
+            block_fallenleaves: registry.blocks().get("block_fallenleaves")?,
+            block_mixgrass: registry.blocks().get("block_mixgrass")?,
+            block_mixpebbles: registry.blocks().get("block_mixpebbles")?,
+            block_oaktree: registry.blocks().get("block_oaktree")?,
+            entity_bird: registry.entities().get("entity_bird")?,
+            entity_player: registry.entities().get("entity_player")?,
+        })
+    }
+}
+
+fn fill_image(color: Color) -> Gd<godot::classes::Image> {
+    let mut image = godot::classes::Image::create_empty(16, 16, false, godot::classes::image::Format::RGBA8).unwrap();
+    image.fill(color);
+    image
 }
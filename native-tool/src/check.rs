@@ -0,0 +1,111 @@
+use native_core::*;
+
+use crate::world::World;
+
+// cross-check the loaded world and the raw entries of its file,
+// every inconsistency found is one line of the result
+pub fn check(world: &World) -> Vec<String> {
+    // the file is pasted skipping conflicts, so overlaps are only visible in the raw entries
+    let mut issues = check_schematic(world);
+
+    // the chunk membership, id lookups and spatial indices of the fields
+    issues.extend(world.dataflow.validate().issues);
+
+    issues
+}
+
+// entries of the file that take a cell another entry already took, coordinates are relative to the origin
+fn check_schematic(world: &World) -> Vec<String> {
+    let dataflow = &world.dataflow;
+    let schematic = &world.schematic;
+    let mut issues = vec![];
+
+    let mut tile_cells = std::collections::HashMap::new();
+    for (index, tile) in schematic.tiles.iter().enumerate() {
+        let Ok(archetype_id) = world.registry.tiles().get(&tile.archetype) else {
+            continue;
+        };
+        let layer_id = dataflow.get_tile_archetype(archetype_id).unwrap().layer_id;
+        if let Some(other) = tile_cells.insert((layer_id, tile.coord), index) {
+            issues.push(format!("tile entry {} at ({}, {}): overlaps tile entry {}", index, tile.coord[0], tile.coord[1], other));
+        }
+    }
+
+    let mut block_cells = std::collections::HashMap::new();
+    for (index, block) in schematic.blocks.iter().enumerate() {
+        let Ok(archetype_id) = world.registry.blocks().get(&block.archetype) else {
+            continue;
+        };
+        let rect = dataflow.get_block_archetype(archetype_id).unwrap().rect(IVec2::from_array(block.coord));
+        let mut others = vec![];
+        for y in rect.min.y..=rect.max.y {
+            for x in rect.min.x..=rect.max.x {
+                if let Some(other) = block_cells.insert(IVec2::new(x, y), index) {
+                    others.push(other);
+                }
+            }
+        }
+        others.sort();
+        others.dedup();
+        for other in others {
+            issues.push(format!("block entry {} at ({}, {}): overlaps block entry {}", index, block.coord[0], block.coord[1], other));
+        }
+    }
+
+    // entities may overlap, only exact copies are reported
+    for (index, entity) in schematic.entities.iter().enumerate() {
+        if let Some(other) = schematic.entities[..index].iter().position(|other| other == entity) {
+            issues.push(format!("entity entry {} at ({}, {}): duplicates entity entry {}", index, entity.coord[0], entity.coord[1], other));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_world() {
+        let world = crate::world::tests::make_world();
+        assert_eq!(check(&world), ["tile entry 2 at (1, 0): overlaps tile entry 1"]);
+
+        let world = crate::world::World::parse(
+            "content.json",
+            crate::world::tests::MANIFEST,
+            "world.json",
+            &crate::world::tests::WORLD.replace(
+                r#"{ "archetype": "tile_grass", "coord": [1, 0] },
+            { "archetype": "tile_grass", "coord": [1, 0] }"#,
+                r#"{ "archetype": "tile_grass", "coord": [1, 0] }"#,
+            ),
+        )
+        .unwrap();
+        assert!(check(&world).is_empty());
+    }
+
+    #[test]
+    fn check_overlapping_entries() {
+        let world = crate::world::World::parse(
+            "content.json",
+            crate::world::tests::MANIFEST,
+            "world.json",
+            &crate::world::tests::WORLD
+                .replace(
+                    r#"[{ "archetype": "block_tree", "coord": [2, 1] }]"#,
+                    r#"[{ "archetype": "block_tree", "coord": [2, 1] }, { "archetype": "block_tree", "coord": [3, 2] }]"#,
+                )
+                .replace(
+                    r#"[{ "archetype": "entity_pig", "coord": [0.5, 2.5], "variant": 1 }]"#,
+                    r#"[{ "archetype": "entity_pig", "coord": [0.5, 2.5] }, { "archetype": "entity_pig", "coord": [0.5, 2.5] }]"#,
+                ),
+        )
+        .unwrap();
+        assert_eq!(check(&world), [
+            "tile entry 2 at (1, 0): overlaps tile entry 1",
+            "block entry 1 at (3, 2): overlaps block entry 0",
+            "entity entry 1 at (0.5, 2.5): duplicates entity entry 0",
+        ]);
+    }
+}
@@ -0,0 +1,142 @@
+use native_core::*;
+
+use crate::world::World;
+
+// what covers a cell, entities over blocks over the tile of the topmost layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Tile(u16),
+    Block(u16),
+    Entity(u16),
+}
+
+fn find_cell(world: &World, coord: IVec2) -> Cell {
+    let dataflow = &world.dataflow;
+
+    let center = coord.as_vec2() + 0.5;
+    let entity = dataflow
+        .find_entity_with_hint_point(center)
+        .map(|(id, _)| dataflow.get_entity(*id).unwrap())
+        .find(|entity| entity.coord.floor().as_ivec2() == coord);
+    if let Some(entity) = entity {
+        return Cell::Entity(entity.archetype_id);
+    }
+
+    if let Some((id, _)) = dataflow.find_block_with_point(coord) {
+        return Cell::Block(dataflow.get_block(*id).unwrap().archetype_id);
+    }
+
+    for layer_id in (0..dataflow.get_tile_layer_len()).rev() {
+        if let Some((id, _)) = dataflow.find_tile_with_point_in_layer(layer_id, coord) {
+            return Cell::Tile(dataflow.get_tile(*id).unwrap().archetype_id);
+        }
+    }
+
+    Cell::Empty
+}
+
+// ascii
+
+const TILE_GLYPHS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const BLOCK_GLYPHS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ENTITY_GLYPHS: &[u8] = b"@&%$0123456789";
+
+fn glyph(cell: Cell) -> char {
+    match cell {
+        Cell::Empty => '.',
+        Cell::Tile(id) => TILE_GLYPHS[id as usize % TILE_GLYPHS.len()] as char,
+        Cell::Block(id) => BLOCK_GLYPHS[id as usize % BLOCK_GLYPHS.len()] as char,
+        Cell::Entity(id) => ENTITY_GLYPHS[id as usize % ENTITY_GLYPHS.len()] as char,
+    }
+}
+
+// one character per cell with the first row at the minimum y, followed by a legend of the glyphs in use
+pub fn dump_ascii(world: &World, rect: IRect2) -> String {
+    let mut lines = vec![];
+    let mut used = vec![];
+    for y in rect.min.y..=rect.max.y {
+        let mut line = String::new();
+        for x in rect.min.x..=rect.max.x {
+            let cell = find_cell(world, IVec2::new(x, y));
+            if cell != Cell::Empty && !used.contains(&cell) {
+                used.push(cell);
+            }
+            line.push(glyph(cell));
+        }
+        lines.push(line);
+    }
+
+    used.sort_by_key(|cell| match cell {
+        Cell::Empty => (0, 0),
+        Cell::Tile(id) => (1, *id),
+        Cell::Block(id) => (2, *id),
+        Cell::Entity(id) => (3, *id),
+    });
+    for cell in used {
+        let name = match cell {
+            Cell::Empty => continue,
+            Cell::Tile(id) => world.registry.tiles().get_name(id),
+            Cell::Block(id) => world.registry.blocks().get_name(id),
+            Cell::Entity(id) => world.registry.entities().get_name(id),
+        };
+        lines.push(format!("{} {}", glyph(cell), name.unwrap_or("?")));
+    }
+
+    lines.join("\n")
+}
+
+// png
+
+// one pixel per cell, the color is derived from the archetype name so that it is the same in every map
+pub fn dump_image(world: &World, rect: IRect2) -> image::RgbImage {
+    let size = (rect.max - rect.min + IVec2::ONE).max(IVec2::ZERO).as_uvec2();
+    image::RgbImage::from_fn(size.x, size.y, |x, y| {
+        let coord = rect.min + UVec2::new(x, y).as_ivec2();
+        let color = match find_cell(world, coord) {
+            Cell::Empty => [0, 0, 0],
+            Cell::Tile(id) => name_color(world.registry.tiles().get_name(id).unwrap_or_default(), 0.5),
+            Cell::Block(id) => name_color(world.registry.blocks().get_name(id).unwrap_or_default(), 0.8),
+            Cell::Entity(id) => name_color(world.registry.entities().get_name(id).unwrap_or_default(), 1.0),
+        };
+        image::Rgb(color)
+    })
+}
+
+fn name_color(name: &str, brightness: f32) -> [u8; 3] {
+    // fnv-1a
+    let mut hash = 0x811c9dc5u32;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+    }
+
+    let [r, g, b, _] = hash.to_le_bytes();
+    [r, g, b].map(|value| ((value / 2 + 64) as f32 * brightness + 48.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_world() {
+        let world = crate::world::tests::make_world();
+        let rect = IRect2::new(IVec2::new(9, 20), IVec2::new(13, 22));
+
+        assert_eq!(dump_ascii(&world, rect), [
+            ".ab..",
+            "...AA",
+            ".@.AA",
+            "a core:tile_dirt",
+            "b core:tile_grass",
+            "A core:block_tree",
+            "@ core:entity_pig",
+        ].join("\n"));
+
+        let image = dump_image(&world, rect);
+        assert_eq!(image.dimensions(), (5, 3));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_ne!(image.get_pixel(1, 0), image.get_pixel(2, 0));
+        assert_eq!(image.get_pixel(3, 1), image.get_pixel(4, 2));
+    }
+}
@@ -0,0 +1,54 @@
+use native_core::*;
+
+use crate::world::World;
+
+// entities whose coordinate lies in the rect, one per line ordered by coordinate
+pub fn list_entities(world: &World, rect: IRect2) -> String {
+    let dataflow = &world.dataflow;
+
+    let min = rect.min.as_vec2();
+    let max = (rect.max + IVec2::ONE).as_vec2();
+    let min_chunk_coord = dataflow.find_entity_chunk_coord(min);
+    let max_chunk_coord = dataflow.find_entity_chunk_coord(max);
+
+    let mut entities = vec![];
+    for y in min_chunk_coord.y..=max_chunk_coord.y {
+        for x in min_chunk_coord.x..=max_chunk_coord.x {
+            let Ok(chunk) = dataflow.get_entity_chunk(IVec2::new(x, y)) else {
+                continue;
+            };
+
+            for (id, entity) in chunk.ids.iter().zip(&chunk.entities) {
+                if entity.coord.cmpge(min).all() && entity.coord.cmplt(max).all() {
+                    entities.push((*id, entity));
+                }
+            }
+        }
+    }
+    entities.sort_by(|(a_id, a), (b_id, b)| (a.coord.y, a.coord.x, a_id).partial_cmp(&(b.coord.y, b.coord.x, b_id)).unwrap());
+
+    entities
+        .into_iter()
+        .map(|(id, entity)| {
+            let name = world.registry.entities().get_name(entity.archetype_id).unwrap_or("?");
+            format!("{} {} ({}, {}) variant={} tick={}", id, name, entity.coord.x, entity.coord.y, entity.variant, entity.tick)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_world_entities() {
+        let world = crate::world::tests::make_world();
+
+        let list = list_entities(&world, IRect2::new(IVec2::new(10, 20), IVec2::new(13, 22)));
+        assert!(list.ends_with(" core:entity_pig (10.5, 22.5) variant=1 tick=0"));
+        assert_eq!(list.lines().count(), 1);
+
+        assert_eq!(list_entities(&world, IRect2::new(IVec2::new(10, 20), IVec2::new(13, 21))), "");
+    }
+}
@@ -0,0 +1,90 @@
+use native_core::*;
+
+mod check;
+mod dump;
+mod entities;
+mod stats;
+mod world;
+
+use world::{ToolError, World};
+
+const USAGE: &str = "usage: native-tool <manifest> <world> <command>
+commands:
+  stats
+  dump <x> <y> <width> <height> [--png <path>]
+  entities <x> <y> <width> <height>
+  check
+a world written by save_loaded_region leaves out the chunks stored by the generator,
+so only what was edited or loaded from the file is inspected";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(ToolError::InvalidUsage(message)) => {
+            eprintln!("{}\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+// false if the command found a problem with the world
+fn run(args: &[String]) -> Result<bool, ToolError> {
+    let [manifest_path, world_path, command, rest @ ..] = args else {
+        return Err(ToolError::InvalidUsage("missing arguments".into()));
+    };
+    let world = World::open(manifest_path, world_path)?;
+
+    match (command.as_str(), rest) {
+        ("stats", []) => {
+            println!("{}", stats::stats(&world));
+            Ok(true)
+        }
+        ("dump", [x, y, width, height, options @ ..]) => {
+            let rect = parse_rect(x, y, width, height)?;
+            match options {
+                [] => println!("{}", dump::dump_ascii(&world, rect)),
+                [flag, path] if flag == "--png" => {
+                    dump::dump_image(&world, rect).save(path).map_err(|e| ToolError::WriteError {
+                        path: path.clone(),
+                        message: e.to_string(),
+                    })?;
+                }
+                _ => return Err(ToolError::InvalidUsage(format!("invalid options: {}", options.join(" ")))),
+            }
+            Ok(true)
+        }
+        ("entities", [x, y, width, height]) => {
+            let rect = parse_rect(x, y, width, height)?;
+            let list = entities::list_entities(&world, rect);
+            if !list.is_empty() {
+                println!("{}", list);
+            }
+            Ok(true)
+        }
+        ("check", []) => {
+            let issues = check::check(&world);
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            Ok(issues.is_empty())
+        }
+        _ => Err(ToolError::InvalidUsage(format!("invalid command: {}", [std::slice::from_ref(command), rest].concat().join(" ")))),
+    }
+}
+
+fn parse_rect(x: &str, y: &str, width: &str, height: &str) -> Result<IRect2, ToolError> {
+    let parse = |value: &str| value.parse::<i32>().map_err(|_| ToolError::InvalidUsage(format!("invalid number: {}", value)));
+    let min = IVec2::new(parse(x)?, parse(y)?);
+    let size = IVec2::new(parse(width)?, parse(height)?);
+    if size.cmple(IVec2::ZERO).any() {
+        return Err(ToolError::InvalidUsage(format!("invalid size: {} {}", width, height)));
+    }
+
+    Ok(IRect2::new(min, min + size - 1))
+}
@@ -0,0 +1,88 @@
+use native_core::*;
+
+use crate::world::World;
+
+pub fn stats(world: &World) -> String {
+    let dataflow = &world.dataflow;
+    let mut lines = vec![];
+
+    lines.push(format!("tick: {}", world.tick));
+    if let Some(region) = dataflow.find_occupied_region() {
+        lines.push(format!("region: ({}, {}) - ({}, {})", region.min.x, region.min.y, region.max.x, region.max.y));
+    }
+    if world.skipped > 0 {
+        lines.push(format!("skipped: {}", world.skipped));
+    }
+
+    // chunks are only counted while they hold something
+    let tile_chunks = dataflow.get_tile_chunk_coords().map(|chunk_coord| dataflow.get_tile_chunk(chunk_coord).unwrap()).filter(|chunk| !chunk.tiles.is_empty());
+    let block_chunks = dataflow.get_block_chunk_coords().map(|chunk_coord| dataflow.get_block_chunk(chunk_coord).unwrap()).filter(|chunk| !chunk.blocks.is_empty());
+    let entity_chunks = dataflow.get_entity_chunk_coords().map(|chunk_coord| dataflow.get_entity_chunk(chunk_coord).unwrap()).filter(|chunk| !chunk.entities.is_empty());
+
+    let mut tile_counts = vec![0usize; world.registry.tiles().len()];
+    let mut chunk_count = 0;
+    for chunk in tile_chunks {
+        chunk_count += 1;
+        for tile in &chunk.tiles {
+            tile_counts[tile.archetype_id as usize] += 1;
+        }
+    }
+    lines.push(format!("tiles: {} in {} chunks", tile_counts.iter().sum::<usize>(), chunk_count));
+    push_counts(&mut lines, world.registry.tiles(), &tile_counts);
+
+    let mut block_counts = vec![0usize; world.registry.blocks().len()];
+    let mut chunk_count = 0;
+    for chunk in block_chunks {
+        chunk_count += 1;
+        for block in &chunk.blocks {
+            block_counts[block.archetype_id as usize] += 1;
+        }
+    }
+    lines.push(format!("blocks: {} in {} chunks", block_counts.iter().sum::<usize>(), chunk_count));
+    push_counts(&mut lines, world.registry.blocks(), &block_counts);
+
+    let mut entity_counts = vec![0usize; world.registry.entities().len()];
+    let mut chunk_count = 0;
+    for chunk in entity_chunks {
+        chunk_count += 1;
+        for entity in &chunk.entities {
+            entity_counts[entity.archetype_id as usize] += 1;
+        }
+    }
+    lines.push(format!("entities: {} in {} chunks", entity_counts.iter().sum::<usize>(), chunk_count));
+    push_counts(&mut lines, world.registry.entities(), &entity_counts);
+
+    lines.join("\n")
+}
+
+// archetypes without any object are left out
+fn push_counts(lines: &mut Vec<String>, table: &NameTable, counts: &[usize]) {
+    for (id, name) in table.iter() {
+        let count = counts[id as usize];
+        if count > 0 {
+            lines.push(format!("  {}: {}", name, count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_stats() {
+        let world = crate::world::tests::make_world();
+        assert_eq!(stats(&world), [
+            "tick: 120",
+            "region: (10, 20) - (12, 22)",
+            "skipped: 1",
+            "tiles: 2 in 1 chunks",
+            "  core:tile_dirt: 1",
+            "  core:tile_grass: 1",
+            "blocks: 1 in 1 chunks",
+            "  core:block_tree: 1",
+            "entities: 1 in 1 chunks",
+            "  core:entity_pig: 1",
+        ].join("\n"));
+    }
+}
@@ -0,0 +1,154 @@
+use native_core::*;
+
+// a saved world is a schematic of the loaded region that was copied with its origin and tick,
+// its archetype names are resolved against the content manifest
+pub struct World {
+    pub registry: Registry,
+    pub dataflow: dataflow::Dataflow,
+    // the file as read, before overlapping objects were left out
+    pub schematic: Schematic,
+    pub tick: u64,
+    // objects of the file that overlapped others and were left out
+    pub skipped: usize,
+}
+
+impl World {
+    pub fn open(manifest_path: &str, world_path: &str) -> Result<Self, ToolError> {
+        let read = |path: &str| std::fs::read_to_string(path).map_err(|e| ToolError::ReadError {
+            path: path.to_string(),
+            message: e.to_string(),
+        });
+
+        Self::parse(manifest_path, &read(manifest_path)?, world_path, &read(world_path)?)
+    }
+
+    pub fn parse(manifest_path: &str, manifest_source: &str, world_path: &str, world_source: &str) -> Result<Self, ToolError> {
+        // handlers belong to the game, every referenced name gets one that does nothing
+        let manifest = manifest::Manifest::parse(manifest_path, manifest_source)?;
+        let mut handlers = manifest::HandlerRegistry::new();
+        for name in manifest.tiles.iter().filter_map(|tile| tile.event_handler.clone()) {
+            handlers.add_tile_handler(name, || EventHandler::new(()));
+        }
+        for name in manifest.blocks.iter().filter_map(|block| block.event_handler.clone()) {
+            handlers.add_block_handler(name, || EventHandler::new(()));
+        }
+        for name in manifest.entities.iter().filter_map(|entity| entity.event_handler.clone()) {
+            handlers.add_entity_handler(name, || EventHandler::new(()));
+        }
+
+        let mut builder = ContextBuilder::new();
+        builder.set_skip_images(true);
+        builder.load_manifest_source(manifest_path, manifest_source, &handlers)?;
+        let HeadlessContext { registry, mut dataflow } = builder.build_headless();
+
+        let schematic = Schematic::parse(world_path, world_source)?;
+        let clipboard = schematic.to_clipboard(&registry)?;
+        let summary = dataflow
+            .paste_region(&clipboard, IVec2::from_array(schematic.origin), dataflow::ConflictPolicy::Skip)
+            .map_err(SchematicError::from)?;
+
+        Ok(Self {
+            registry,
+            dataflow,
+            tick: schematic.tick,
+            schematic,
+            skipped: summary.skipped,
+        })
+    }
+}
+
+// error handling
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolError {
+    ReadError { path: String, message: String },
+    WriteError { path: String, message: String },
+    ManifestError(manifest::ManifestError),
+    SchematicError(SchematicError),
+    InvalidUsage(String),
+}
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadError { path, message } => write!(f, "{}: failed to read: {}", path, message),
+            Self::WriteError { path, message } => write!(f, "{}: failed to write: {}", path, message),
+            Self::ManifestError(e) => e.fmt(f),
+            Self::SchematicError(e) => e.fmt(f),
+            Self::InvalidUsage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+impl From<manifest::ManifestError> for ToolError {
+    fn from(value: manifest::ManifestError) -> Self {
+        Self::ManifestError(value)
+    }
+}
+
+impl From<SchematicError> for ToolError {
+    fn from(value: SchematicError) -> Self {
+        Self::SchematicError(value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub const MANIFEST: &str = r#"{
+        "tile_layers": [{ "name": "layer_ground" }],
+        "tiles": [
+            { "name": "tile_dirt", "sprites": [{ "images": ["res://dirt.webp"] }], "layer": "layer_ground" },
+            { "name": "tile_grass", "sprites": [{ "images": ["res://grass.webp"] }], "layer": "layer_ground" }
+        ],
+        "blocks": [
+            {
+                "name": "block_tree",
+                "sprites": [{ "images": ["res://tree.webp"] }],
+                "size": [2, 2],
+                "rendering_rect": { "min": [0.0, 0.0], "max": [2.0, 2.0] }
+            }
+        ],
+        "entities": [
+            {
+                "name": "entity_pig",
+                "sprites": [{ "images": ["res://pig.webp"] }],
+                "rendering_rect": { "min": [0.0, 0.0], "max": [1.0, 1.0] },
+                "event_handler": "animal"
+            }
+        ]
+    }"#;
+
+    pub const WORLD: &str = r#"{
+        "size": [4, 3],
+        "origin": [10, 20],
+        "tick": 120,
+        "tiles": [
+            { "archetype": "tile_dirt", "coord": [0, 0] },
+            { "archetype": "tile_grass", "coord": [1, 0] },
+            { "archetype": "tile_grass", "coord": [1, 0] }
+        ],
+        "blocks": [{ "archetype": "block_tree", "coord": [2, 1] }],
+        "entities": [{ "archetype": "entity_pig", "coord": [0.5, 2.5], "variant": 1 }]
+    }"#;
+
+    pub fn make_world() -> World {
+        World::parse("content.json", MANIFEST, "world.json", WORLD).unwrap()
+    }
+
+    #[test]
+    fn open_world() {
+        let world = make_world();
+        assert_eq!(world.tick, 120);
+        assert_eq!(world.skipped, 1);
+        assert!(world.dataflow.find_tile_with_point(IVec2::new(10, 20)).is_some());
+        assert!(world.dataflow.find_block_with_point(IVec2::new(13, 22)).is_some());
+        assert_eq!(world.dataflow.find_entity_with_hint_point(Vec2::new(10.5, 22.5)).count(), 1);
+
+        let error = World::parse("content.json", MANIFEST, "world.json", &WORLD.replace("tile_dirt", "tile_sand")).err().unwrap();
+        assert!(matches!(error, ToolError::SchematicError(SchematicError::InvalidEntry { .. })));
+    }
+}
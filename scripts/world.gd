@@ -1,6 +1,9 @@
 extends Node3D
 
 
+@export var world_seed: int = 0
+@export_range(0, 15) var light_ambient: int = 10
+
 var _gen_rect: Rect2
 var _view_rect: Rect2
 
@@ -8,7 +11,8 @@ var _view_rect: Rect2
 func _enter_tree() -> void:
 	_warmup()
 
-	Context.open(self.get_viewport())
+	Context.open(self.get_viewport(), world_seed)
+	Context.set_light_ambient(light_ambient)
 	# spawn player
 	Context.spawn_player()
 	# spawn 65,536 animal for load-test
@@ -20,6 +24,12 @@ func _process(delta: float) -> void:
 	_draw_process()
 
 
+func _unhandled_input(event: InputEvent) -> void:
+	# hot-reload content definitions and sprites
+	if event is InputEventKey and event.pressed and not event.echo and event.keycode == KEY_F5:
+		Context.reload()
+
+
 func _logic_process(delta: float) -> void:
 	Context.process(delta)
 	Context.generate_field(_gen_rect)